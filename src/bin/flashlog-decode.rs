@@ -0,0 +1,104 @@
+//! Decodes binary log files written by `Logger::with_binary_file` into the JSON lines `flash_xxx_ct!` produces.
+//!
+//! Usage: `flashlog-decode [--format json|text|logfmt] [--timestamp FORMAT] [FILE]...`
+//! Reads standard input when no file is given. Files ending in `.gz` are decompressed, as are `.zst` and `.lz4`
+//! files when built with the `zstd` and `lz4` features.
+use flashlog::binary_file::BinaryFileReader;
+use flashlog::{set_timestamp_format, LogfmtFormatter, TextFormatter, TimestampFormat};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+#[derive(Clone, Copy)]
+enum Format {
+    Json,
+    Text,
+    Logfmt,
+}
+
+fn decode<R: Read, W: Write>(reader: R, format: Format, out: &mut W) -> io::Result<()> {
+    let reader = BinaryFileReader::new(BufReader::new(reader));
+    let reader = match format {
+        Format::Json => reader,
+        Format::Text => reader.with_formatter(TextFormatter::new()),
+        Format::Logfmt => reader.with_formatter(LogfmtFormatter::new()),
+    };
+    for line in reader {
+        out.write_all(line?.as_bytes())?;
+    }
+    Ok(())
+}
+
+fn run(paths: &[String], format: Format) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+
+    if paths.is_empty() {
+        decode(io::stdin().lock(), format, &mut out)?;
+    }
+    for path in paths {
+        let file = File::open(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+        let result = match path.rsplit('.').next() {
+            Some("gz") => decode(flate2::read::GzDecoder::new(file), format, &mut out),
+            #[cfg(feature = "zstd")]
+            Some("zst") => zstd::Decoder::new(file).and_then(|reader| decode(reader, format, &mut out)),
+            #[cfg(feature = "lz4")]
+            Some("lz4") => decode(lz4_flex::frame::FrameDecoder::new(file), format, &mut out),
+            _ => decode(file, format, &mut out),
+        };
+        result.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+    }
+    out.flush()
+}
+
+fn usage() {
+    println!("Usage: flashlog-decode [--format json|text|logfmt] [--timestamp FORMAT] [FILE]...");
+    println!("Decodes flashlog binary (.flog) files into JSON lines. Reads stdin when no file is given.");
+    println!("FORMAT is legacy (default), rfc3339, rfc3339:N, epoch_millis, epoch_micros or epoch_nanos.");
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut paths = Vec::new();
+    let mut format = Format::Json;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                usage();
+                return;
+            }
+            "-f" | "--format" => {
+                format = match args.next().as_deref() {
+                    Some("json") => Format::Json,
+                    Some("text") => Format::Text,
+                    Some("logfmt") => Format::Logfmt,
+                    _ => {
+                        eprintln!("flashlog-decode: --format expects json, text or logfmt");
+                        std::process::exit(2);
+                    }
+                };
+            }
+            "-t" | "--timestamp" => {
+                match args.next().as_deref().map(str::parse::<TimestampFormat>) {
+                    Some(Ok(timestamp_format)) => set_timestamp_format(timestamp_format),
+                    _ => {
+                        eprintln!(
+                            "flashlog-decode: --timestamp expects legacy, rfc3339, rfc3339:N, epoch_millis, \
+                             epoch_micros or epoch_nanos"
+                        );
+                        std::process::exit(2);
+                    }
+                }
+            }
+            _ => paths.push(arg),
+        }
+    }
+
+    match run(&paths, format) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+        Err(e) => {
+            eprintln!("flashlog-decode: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
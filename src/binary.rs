@@ -0,0 +1,668 @@
+//! Binary (deferred-formatting) records.
+//!
+//! `flash_xxx_bin!` macros copy their primitive arguments into a `BinaryRecord` tagged by a static
+//! `Callsite` (file, line, level, topic, format string and keys). Nothing is formatted and no closure is
+//! boxed in the calling thread: the logger thread (or an offline tool) decodes the bytes and formats them later.
+use crate::formatter::{format_to_string, Formatter, JsonFormatter, LogRecord, Record};
+use crate::timezone::utc_offset_at;
+use crate::timer::{get_unix_nano, CallsiteTime};
+use std::fmt::Write;
+
+/// Arguments up to this many bytes are stored inline in `BinaryRecord` without a heap allocation
+pub const BINARY_INLINE_CAPACITY: usize = 96;
+
+/// Description of a `flash_xxx_bin!` call site. One static instance exists per macro invocation.
+/// The lifetime lets offline tools build a `Callsite` from a dictionary read back from a file.
+#[derive(Debug)]
+pub struct Callsite<'a> {
+    pub level: usize,
+    pub topic: &'a str,
+    pub fmt: &'a str,
+    /// Number of format arguments. They are encoded first, followed by one value per key.
+    pub arg_count: usize,
+    pub keys: &'a [&'a str],
+    pub file: &'a str,
+    pub line: u32,
+    pub module_path: &'a str,
+}
+
+impl<'a> Callsite<'a> {
+    #[allow(clippy::too_many_arguments)]
+    pub const fn new(
+        level: usize,
+        topic: &'a str,
+        fmt: &'a str,
+        arg_count: usize,
+        keys: &'a [&'a str],
+        file: &'a str,
+        line: u32,
+        module_path: &'a str,
+    ) -> Callsite<'a> {
+        Callsite { level, topic, fmt, arg_count, keys, file, line, module_path }
+    }
+}
+
+const TAG_I8: u8 = 0;
+const TAG_I16: u8 = 1;
+const TAG_I32: u8 = 2;
+const TAG_I64: u8 = 3;
+const TAG_U8: u8 = 4;
+const TAG_U16: u8 = 5;
+const TAG_U32: u8 = 6;
+const TAG_U64: u8 = 7;
+const TAG_F32: u8 = 8;
+const TAG_F64: u8 = 9;
+const TAG_BOOL: u8 = 10;
+const TAG_CHAR: u8 = 11;
+const TAG_STR: u8 = 12;
+
+/// Encoded arguments of one log call
+pub struct BinaryRecord {
+    callsite: &'static Callsite<'static>,
+    time: CallsiteTime,
+    len: usize,
+    inline: [u8; BINARY_INLINE_CAPACITY],
+    spill: Vec<u8>,
+}
+
+impl BinaryRecord {
+    #[inline]
+    pub fn new(callsite: &'static Callsite<'static>) -> BinaryRecord {
+        BinaryRecord {
+            callsite,
+            time: CallsiteTime::now(),
+            len: 0,
+            inline: [0; BINARY_INLINE_CAPACITY],
+            spill: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn callsite(&self) -> &'static Callsite<'static> {
+        self.callsite
+    }
+
+    /// Clock reading taken when the record was created, see `Logger::with_callsite_timestamp`
+    #[inline]
+    pub fn time(&self) -> CallsiteTime {
+        self.time
+    }
+
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        if self.spill.is_empty() {
+            &self.inline[..self.len]
+        } else {
+            &self.spill
+        }
+    }
+
+    /// Returns true if the arguments are stored without a heap allocation
+    #[inline]
+    pub fn is_inline(&self) -> bool {
+        self.spill.is_empty()
+    }
+
+    #[inline]
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        if self.spill.is_empty() && self.len + bytes.len() <= BINARY_INLINE_CAPACITY {
+            self.inline[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+            self.len += bytes.len();
+        } else {
+            self.write_spill(bytes);
+        }
+    }
+
+    #[cold]
+    fn write_spill(&mut self, bytes: &[u8]) {
+        if self.spill.is_empty() {
+            self.spill.reserve(self.len + bytes.len());
+            self.spill.extend_from_slice(&self.inline[..self.len]);
+        }
+        self.spill.extend_from_slice(bytes);
+        self.len = self.spill.len();
+    }
+
+    /// Decodes the arguments. Returns `None` if the bytes do not match the callsite.
+    pub fn decode(&self) -> Option<(Vec<ArgValue>, Vec<ArgValue>)> {
+        decode_values(self.callsite.arg_count, self.callsite.keys.len(), self.as_bytes())
+    }
+
+    /// Decodes the record into a `LogRecord` timestamped with `unixnano`
+    pub fn to_log_record(&self, unixnano: u64) -> LogRecord {
+        let (message, data) = decode_message(self.callsite, self.as_bytes());
+        LogRecord {
+            level: self.callsite.level,
+            topic: self.callsite.topic.to_string(),
+            message,
+            data,
+            file: self.callsite.file,
+            line: self.callsite.line,
+            module_path: self.callsite.module_path,
+            unixnano,
+            queue_latency: None,
+        }
+    }
+
+    /// Formats the record with `formatter`, timestamped now
+    pub fn format(&self, formatter: &dyn Formatter) -> String {
+        let unixnano = get_unix_nano();
+        format_binary(formatter, self.callsite, self.as_bytes(), unixnano, utc_offset_at(unixnano))
+    }
+}
+
+/// Message and key/value data of encoded arguments, with the same data conventions as `flash_xxx_ct!`
+fn decode_message<'a>(callsite: &Callsite<'a>, bytes: &[u8]) -> (String, Option<Vec<(&'a str, serde_json::Value)>>) {
+    match decode_values(callsite.arg_count, callsite.keys.len(), bytes) {
+        Some((args, values)) => {
+            let message = format_message(callsite.fmt, &args);
+            let data = if !values.is_empty() {
+                Some(callsite.keys.iter().copied().zip(values.iter().map(ArgValue::to_json)).collect())
+            } else if !args.is_empty() {
+                Some(Vec::new())
+            } else {
+                None
+            };
+            (message, data)
+        }
+        None => (
+            callsite.fmt.to_string(),
+            Some(vec![("error", serde_json::json!("invalid binary record"))]),
+        ),
+    }
+}
+
+/// Formats encoded arguments of `callsite` with `formatter`
+pub fn format_binary(
+    formatter: &dyn Formatter,
+    callsite: &Callsite<'_>,
+    bytes: &[u8],
+    unixnano: u64,
+    utc_offset: i32,
+) -> String {
+    let (message, data) = decode_message(callsite, bytes);
+    let record = Record {
+        level: callsite.level,
+        topic: callsite.topic,
+        message: &message,
+        data: data.as_deref(),
+        file: callsite.file,
+        line: callsite.line,
+        module_path: callsite.module_path,
+        unixnano,
+        utc_offset,
+        queue_latency: None,
+    };
+    format_to_string(formatter, &record)
+}
+
+/// Formats encoded arguments of `callsite` as a JSON line, in the same layout as `flash_xxx_ct!`
+pub fn json_line(
+    callsite: &Callsite<'_>,
+    bytes: &[u8],
+    unixnano: u64,
+    utc_offset: i32,
+    include_unixnano: bool,
+) -> String {
+    format_binary(&JsonFormatter::with_unixnano(include_unixnano), callsite, bytes, unixnano, utc_offset)
+}
+
+/// Primitive types that can be copied into a `BinaryRecord`
+pub trait Encode {
+    fn encode(&self, record: &mut BinaryRecord);
+}
+
+macro_rules! impl_encode_number {
+    ($($ty:ty => $tag:expr),* $(,)?) => {
+        $(
+            impl Encode for $ty {
+                #[inline]
+                fn encode(&self, record: &mut BinaryRecord) {
+                    record.write_bytes(&[$tag]);
+                    record.write_bytes(&self.to_le_bytes());
+                }
+            }
+        )*
+    };
+}
+
+impl_encode_number!(
+    i8 => TAG_I8, i16 => TAG_I16, i32 => TAG_I32, i64 => TAG_I64,
+    u8 => TAG_U8, u16 => TAG_U16, u32 => TAG_U32, u64 => TAG_U64,
+    f32 => TAG_F32, f64 => TAG_F64,
+);
+
+impl Encode for isize {
+    #[inline]
+    fn encode(&self, record: &mut BinaryRecord) {
+        (*self as i64).encode(record);
+    }
+}
+
+impl Encode for usize {
+    #[inline]
+    fn encode(&self, record: &mut BinaryRecord) {
+        (*self as u64).encode(record);
+    }
+}
+
+impl Encode for bool {
+    #[inline]
+    fn encode(&self, record: &mut BinaryRecord) {
+        record.write_bytes(&[TAG_BOOL, *self as u8]);
+    }
+}
+
+impl Encode for char {
+    #[inline]
+    fn encode(&self, record: &mut BinaryRecord) {
+        record.write_bytes(&[TAG_CHAR]);
+        record.write_bytes(&(*self as u32).to_le_bytes());
+    }
+}
+
+impl Encode for str {
+    #[inline]
+    fn encode(&self, record: &mut BinaryRecord) {
+        record.write_bytes(&[TAG_STR]);
+        record.write_bytes(&(self.len() as u32).to_le_bytes());
+        record.write_bytes(self.as_bytes());
+    }
+}
+
+impl Encode for String {
+    #[inline]
+    fn encode(&self, record: &mut BinaryRecord) {
+        self.as_str().encode(record);
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    #[inline]
+    fn encode(&self, record: &mut BinaryRecord) {
+        (**self).encode(record);
+    }
+}
+
+/// Decoded argument
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgValue {
+    I64(i64),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+}
+
+impl ArgValue {
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            ArgValue::I64(v) => serde_json::json!(v),
+            ArgValue::U64(v) => serde_json::json!(v),
+            ArgValue::F32(v) => serde_json::json!(v),
+            ArgValue::F64(v) => serde_json::json!(v),
+            ArgValue::Bool(v) => serde_json::json!(v),
+            ArgValue::Char(v) => serde_json::json!(v),
+            ArgValue::Str(v) => serde_json::json!(v),
+        }
+    }
+}
+
+impl std::fmt::Display for ArgValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArgValue::I64(v) => v.fmt(f),
+            ArgValue::U64(v) => v.fmt(f),
+            ArgValue::F32(v) => v.fmt(f),
+            ArgValue::F64(v) => v.fmt(f),
+            ArgValue::Bool(v) => v.fmt(f),
+            ArgValue::Char(v) => v.fmt(f),
+            ArgValue::Str(v) => v.fmt(f),
+        }
+    }
+}
+
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    if bytes.len() < n {
+        return None;
+    }
+    let (head, tail) = bytes.split_at(n);
+    *bytes = tail;
+    Some(head)
+}
+
+fn take_array<const N: usize>(bytes: &mut &[u8]) -> Option<[u8; N]> {
+    take(bytes, N).map(|b| b.try_into().expect("length checked"))
+}
+
+fn decode_value(bytes: &mut &[u8]) -> Option<ArgValue> {
+    let tag = take_array::<1>(bytes)?[0];
+    let value = match tag {
+        TAG_I8 => ArgValue::I64(i8::from_le_bytes(take_array(bytes)?) as i64),
+        TAG_I16 => ArgValue::I64(i16::from_le_bytes(take_array(bytes)?) as i64),
+        TAG_I32 => ArgValue::I64(i32::from_le_bytes(take_array(bytes)?) as i64),
+        TAG_I64 => ArgValue::I64(i64::from_le_bytes(take_array(bytes)?)),
+        TAG_U8 => ArgValue::U64(u8::from_le_bytes(take_array(bytes)?) as u64),
+        TAG_U16 => ArgValue::U64(u16::from_le_bytes(take_array(bytes)?) as u64),
+        TAG_U32 => ArgValue::U64(u32::from_le_bytes(take_array(bytes)?) as u64),
+        TAG_U64 => ArgValue::U64(u64::from_le_bytes(take_array(bytes)?)),
+        TAG_F32 => ArgValue::F32(f32::from_le_bytes(take_array(bytes)?)),
+        TAG_F64 => ArgValue::F64(f64::from_le_bytes(take_array(bytes)?)),
+        TAG_BOOL => ArgValue::Bool(take_array::<1>(bytes)?[0] != 0),
+        TAG_CHAR => ArgValue::Char(char::from_u32(u32::from_le_bytes(take_array(bytes)?))?),
+        TAG_STR => {
+            let len = u32::from_le_bytes(take_array(bytes)?) as usize;
+            ArgValue::Str(String::from_utf8_lossy(take(bytes, len)?).into_owned())
+        }
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Splits encoded bytes into `arg_count` format arguments and `value_count` key values
+pub fn decode_values(arg_count: usize, value_count: usize, mut bytes: &[u8]) -> Option<(Vec<ArgValue>, Vec<ArgValue>)> {
+    // every value takes at least its tag byte, which also bounds the counts read from a file
+    if arg_count.saturating_add(value_count) > bytes.len() {
+        return None;
+    }
+    let mut args = Vec::with_capacity(arg_count);
+    for _ in 0..arg_count {
+        args.push(decode_value(&mut bytes)?);
+    }
+    let mut values = Vec::with_capacity(value_count);
+    for _ in 0..value_count {
+        values.push(decode_value(&mut bytes)?);
+    }
+    if !bytes.is_empty() {
+        return None;
+    }
+    Some((args, values))
+}
+
+/// Substitutes `args` into a `format!`-style string at runtime.
+/// Supports `{}`, `{:?}`, `{:.N}` and the `{{` / `}}` escapes; other specs are formatted like `{}`.
+/// `flash_xxx_bin!` rejects other specs at compile time, see `is_supported_format`.
+pub fn format_message(fmt: &str, args: &[ArgValue]) -> String {
+    let mut out = String::with_capacity(fmt.len() + args.len() * 8);
+    let mut args = args.iter();
+    let mut chars = fmt.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let mut spec = String::new();
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        break;
+                    }
+                    spec.push(c);
+                }
+                let Some(arg) = args.next() else {
+                    continue;
+                };
+                let precision = spec
+                    .strip_prefix(":.")
+                    .and_then(|p| p.parse::<usize>().ok());
+                let _ = match (spec.as_str(), precision, arg) {
+                    (":?", _, ArgValue::Str(v)) => write!(out, "{:?}", v),
+                    (":?", _, ArgValue::Char(v)) => write!(out, "{:?}", v),
+                    (":?", _, ArgValue::F32(v)) => write!(out, "{:?}", v),
+                    (":?", _, ArgValue::F64(v)) => write!(out, "{:?}", v),
+                    (_, Some(p), ArgValue::F32(v)) => write!(out, "{:.*}", p, v),
+                    (_, Some(p), ArgValue::F64(v)) => write!(out, "{:.*}", p, v),
+                    _ => write!(out, "{}", arg),
+                };
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Whether `format_message` supports every placeholder of `fmt`: `{}`, `{:?}` and `{:.N}`, besides the `{{` and `}}`
+/// escapes. Positional and named arguments, width, alignment, sign and radix specs such as `{0}`, `{px}`, `{:>8}` or
+/// `{:x}` are not supported.
+pub const fn is_supported_format(fmt: &str) -> bool {
+    let bytes = fmt.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'{' {
+            i += 1;
+            continue;
+        }
+        if i + 1 < bytes.len() && bytes[i + 1] == b'{' {
+            i += 2;
+            continue;
+        }
+        let start = i + 1;
+        let mut end = start;
+        while end < bytes.len() && bytes[end] != b'}' {
+            end += 1;
+        }
+        if end == bytes.len() || !is_supported_spec(bytes, start, end) {
+            return false;
+        }
+        i = end + 1;
+    }
+    true
+}
+
+/// `bytes[start..end]` is the inside of a placeholder
+const fn is_supported_spec(bytes: &[u8], start: usize, end: usize) -> bool {
+    match end - start {
+        0 => true,
+        2 if bytes[start] == b':' && bytes[start + 1] == b'?' => true,
+        len if len >= 3 && bytes[start] == b':' && bytes[start + 1] == b'.' => {
+            let mut i = start + 2;
+            while i < end {
+                if !bytes[i].is_ascii_digit() {
+                    return false;
+                }
+                i += 1;
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Checks at compile time that `$fmt` has one placeholder per argument, as `format!` does, and only placeholders
+/// that `format_message` supports
+#[doc(hidden)]
+#[macro_export]
+macro_rules! check_binary_format {
+    ($fmt:expr, $($arg:expr),*) => {
+        const _: () = assert!(
+            $crate::binary::is_supported_format($fmt),
+            "flash_xxx_bin! format strings only support {{}}, {{:?}} and {{:.N}} placeholders"
+        );
+        // never called, only type-checked
+        let _ = || {
+            let _ = format_args!($fmt, $($arg),*);
+        };
+    };
+}
+
+/// Implements `flash_xxx_bin!`. A format string takes one argument per placeholder, checked at compile time, and
+/// supports only `{}`, `{:?}`, `{:.N}` (floats) and the `{{` / `}}` escapes, as the logger thread or
+/// `flashlog-decode` formats the record without the types of the arguments.
+#[macro_export]
+macro_rules! log_with_level_bin {
+    // Case 1: topic, format string argument arguments, and key-value pairs
+    ($level:expr, $topic:expr; $fmt:expr, $($arg:expr),* ; $($key:ident = $value:expr),+ $(,)?) => {{
+        $crate::check_binary_format!($fmt, $($arg),*);
+        $crate::log_fn_binary!($level, $topic, $fmt, [$($arg),*], [$($key = $value),+]);
+    }};
+
+    // Case 2: topic and static string, and key-value pairs
+    ($level:expr, $topic:expr; $msg:expr; $($key:ident = $value:expr),+ $(,)?) => {{
+        $crate::log_fn_binary!($level, $topic, $msg, [], [$($key = $value),+]);
+    }};
+
+    // Case 3: topic and key-value pairs
+    ($level:expr, $topic:expr; $($key:ident = $value:expr),+ $(,)?) => {{
+        $crate::log_fn_binary!($level, $topic, "", [], [$($key = $value),+]);
+    }};
+
+    // Case 4: topic and format string with arguments
+    ($level:expr, $topic:expr; $fmt:expr, $($arg:expr),* $(,)?) => {{
+        $crate::check_binary_format!($fmt, $($arg),*);
+        $crate::log_fn_binary!($level, $topic, $fmt, [$($arg),*], []);
+    }};
+
+    // Case 5: topic and static string
+    ($level:expr, $topic:expr; $msg:expr) => {{
+        $crate::log_fn_binary!($level, $topic, $msg, [], []);
+    }};
+
+    // Case 6: Topic only
+    ($level:expr, $topic:expr) => {{
+        $crate::log_fn_binary!($level, $topic, "", [], []);
+    }};
+
+    // Case 7: key-value pairs without topic
+    ($level:expr, $($key:ident = $value:expr),+ $(,)?) => {{
+        $crate::log_fn_binary!($level, "", "", [], [$($key = $value),+]);
+    }};
+}
+
+#[macro_export]
+macro_rules! log_fn_binary {
+    ($level:expr, $topic:expr, $fmt:expr, [$($arg:expr),*], [$($key:ident = $value:expr),*]) => {{
+        if $crate::callsite_enabled!($level, const $topic) {
+            static CALLSITE: $crate::binary::Callsite = $crate::binary::Callsite::new(
+                $level,
+                $topic,
+                $fmt,
+                <[&str]>::len(&[$(stringify!($arg)),*]),
+                &[$(stringify!($key)),*],
+                file!(),
+                line!(),
+                module_path!(),
+            );
+            let mut record = $crate::binary::BinaryRecord::new(&CALLSITE);
+            $(
+                $crate::binary::Encode::encode(&$arg, &mut record);
+            )*
+            $(
+                $crate::binary::Encode::encode(&$value, &mut record);
+            )*
+            $crate::LOG_SENDER.send_log($crate::LogMessage::Binary(record));
+        }
+    }};
+}
+
+#[macro_export]
+macro_rules! flash_trace_bin {
+    ( $( $key:ident = $value:expr ),+ $(,)? ) => {
+        $crate::log_with_level_bin!($crate::compile_time::TRACE, ""; $( $key = $value ),+ )
+    };
+    ( $($args:tt)* ) => {
+        $crate::log_with_level_bin!($crate::compile_time::TRACE, $($args)* )
+    };
+}
+
+#[macro_export]
+macro_rules! flash_debug_bin {
+    ( $( $key:ident = $value:expr ),+ $(,)? ) => {
+        $crate::log_with_level_bin!($crate::compile_time::DEBUG, ""; $( $key = $value ),+ )
+    };
+    ( $($args:tt)* ) => {
+        $crate::log_with_level_bin!($crate::compile_time::DEBUG, $($args)* )
+    };
+}
+
+#[macro_export]
+macro_rules! flash_info_bin {
+    ( $( $key:ident = $value:expr ),+ $(,)? ) => {
+        $crate::log_with_level_bin!($crate::compile_time::INFO, ""; $( $key = $value ),+ )
+    };
+    ( $($args:tt)* ) => {
+        $crate::log_with_level_bin!($crate::compile_time::INFO, $($args)* )
+    };
+}
+
+#[macro_export]
+macro_rules! flash_warn_bin {
+    ( $( $key:ident = $value:expr ),+ $(,)? ) => {
+        $crate::log_with_level_bin!($crate::compile_time::WARN, ""; $( $key = $value ),+ )
+    };
+    ( $($args:tt)* ) => {
+        $crate::log_with_level_bin!($crate::compile_time::WARN, $($args)* )
+    };
+}
+
+#[macro_export]
+macro_rules! flash_error_bin {
+    ( $( $key:ident = $value:expr ),+ $(,)? ) => {
+        $crate::log_with_level_bin!($crate::compile_time::ERROR, ""; $( $key = $value ),+ )
+    };
+    ( $($args:tt)* ) => {
+        $crate::log_with_level_bin!($crate::compile_time::ERROR, $($args)* )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static CALLSITE: Callsite = Callsite::new(3, "topic", "px {} qty {} side {:?}", 3, &["venue", "ok"], file!(), line!(), module_path!());
+
+    #[test]
+    fn test_encode_decode_round_trip() {
+        let mut record = BinaryRecord::new(&CALLSITE);
+        101.25f64.encode(&mut record);
+        7u32.encode(&mut record);
+        "buy".encode(&mut record);
+        "KRX".to_string().encode(&mut record);
+        true.encode(&mut record);
+        assert!(record.is_inline());
+
+        let (args, values) = record.decode().unwrap();
+        assert_eq!(args, vec![ArgValue::F64(101.25), ArgValue::U64(7), ArgValue::Str("buy".to_string())]);
+        assert_eq!(values, vec![ArgValue::Str("KRX".to_string()), ArgValue::Bool(true)]);
+        assert_eq!(format_message(CALLSITE.fmt, &args), "px 101.25 qty 7 side \"buy\"");
+    }
+
+    #[test]
+    fn test_spill_to_heap() {
+        let mut record = BinaryRecord::new(&CALLSITE);
+        1.0f64.encode(&mut record);
+        2u32.encode(&mut record);
+        "x".repeat(200).encode(&mut record);
+        (-3i64).encode(&mut record);
+        'c'.encode(&mut record);
+        assert!(!record.is_inline());
+
+        let (args, values) = record.decode().unwrap();
+        assert_eq!(args[2], ArgValue::Str("x".repeat(200)));
+        assert_eq!(values, vec![ArgValue::I64(-3), ArgValue::Char('c')]);
+    }
+
+    #[test]
+    fn test_format_message() {
+        let args = [ArgValue::F64(1.23456), ArgValue::I64(-5)];
+        assert_eq!(format_message("{{x}} {:.2} {}", &args), "{x} 1.23 -5");
+        assert_eq!(format_message("no args", &[]), "no args");
+    }
+
+    #[test]
+    fn test_supported_format() {
+        for fmt in ["", "no args", "{{x}} {:.2} {}", "{:?}}}", "px {:.10}"] {
+            assert!(is_supported_format(fmt), "{}", fmt);
+        }
+        for fmt in ["{0}", "{px}", "{:>8}", "{:x}", "{:.}", "{:.2x}", "{:#?}", "{"] {
+            assert!(!is_supported_format(fmt), "{}", fmt);
+        }
+    }
+}
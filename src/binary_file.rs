@@ -0,0 +1,507 @@
+//! Compact binary log files.
+//!
+//! Records of `flash_xxx_bin!` are written as they were encoded, next to a per-file dictionary of their callsites,
+//! so a record costs a few bytes of framing plus its arguments instead of a full JSON line.
+//! The `flashlog-decode` binary turns these files back into the JSON lines that `flash_xxx_ct!` produces.
+//!
+//! Layout (integers marked varint are LEB128, strings are a varint length followed by UTF-8 bytes):
+//! - header: `FLASHLOG`, format version (u8), timezone offset in seconds at the base unixnano (i32 LE),
+//!   include unixnano (u8), base unixnano (u64 LE), IANA name of the timezone (string, empty for fixed offsets).
+//!   Written at the start of every file, and again whenever files are concatenated. Version 1 headers have the
+//!   offset in hours and no name.
+//! - callsite frame: `0x01`, id, level, line, arg count (varints), topic, format, file, module path (strings),
+//!   key count (varint), keys (strings). Emitted the first time a callsite is seen in a file.
+//! - record frame: `0x02`, callsite id (varint), zigzag varint delta of unixnano from the previous record
+//!   (or the header base), payload length (varint), payload as encoded by `BinaryRecord`.
+use crate::binary::{format_binary, json_line, BinaryRecord, Callsite};
+use crate::formatter::Formatter;
+use crate::logger::INCLUDE_UNIXNANO;
+use crate::rolling_file::{RollingConfig, RollingFileWriter};
+use crate::timezone::{current_zone, Zone};
+use chrono_tz::Tz;
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::sync::atomic::Ordering;
+
+pub const MAGIC: &[u8; 8] = b"FLASHLOG";
+pub const FORMAT_VERSION: u8 = 2;
+/// Extension of binary log files
+pub const BINARY_FILE_EXTENSION: &str = "flog";
+
+const FRAME_CALLSITE: u8 = 0x01;
+const FRAME_RECORD: u8 = 0x02;
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_str(buf: &mut Vec<u8>, value: &str) {
+    write_varint(buf, value.len() as u64);
+    buf.extend_from_slice(value.as_bytes());
+}
+
+#[inline]
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+#[inline]
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+/// Encodes headers, callsite definitions and records into a byte buffer.
+/// The dictionary is per file, so `write_header` starts a new one.
+#[derive(Default)]
+pub struct BinaryEncoder {
+    callsites: HashMap<usize, u64>,
+    last_unixnano: u64,
+}
+
+impl BinaryEncoder {
+    pub fn new() -> BinaryEncoder {
+        BinaryEncoder::default()
+    }
+
+    pub fn write_header(&mut self, buf: &mut Vec<u8>, zone: Zone, include_unixnano: bool, unixnano: u64) {
+        self.callsites.clear();
+        self.last_unixnano = unixnano;
+
+        buf.extend_from_slice(MAGIC);
+        buf.push(FORMAT_VERSION);
+        buf.extend_from_slice(&zone.utc_offset_at(unixnano).to_le_bytes());
+        buf.push(include_unixnano as u8);
+        buf.extend_from_slice(&unixnano.to_le_bytes());
+        write_str(buf, zone.name().unwrap_or(""));
+    }
+
+    pub fn write_record(&mut self, buf: &mut Vec<u8>, record: &BinaryRecord, unixnano: u64) {
+        let callsite = record.callsite();
+        let address = callsite as *const Callsite as usize;
+        let id = match self.callsites.get(&address) {
+            Some(id) => *id,
+            None => {
+                let id = self.callsites.len() as u64;
+                self.callsites.insert(address, id);
+                write_callsite(buf, id, callsite);
+                id
+            }
+        };
+
+        let delta = unixnano.wrapping_sub(self.last_unixnano) as i64;
+        self.last_unixnano = unixnano;
+
+        let bytes = record.as_bytes();
+        buf.push(FRAME_RECORD);
+        write_varint(buf, id);
+        write_varint(buf, zigzag(delta));
+        write_varint(buf, bytes.len() as u64);
+        buf.extend_from_slice(bytes);
+    }
+}
+
+fn write_callsite(buf: &mut Vec<u8>, id: u64, callsite: &Callsite) {
+    buf.push(FRAME_CALLSITE);
+    write_varint(buf, id);
+    write_varint(buf, callsite.level as u64);
+    write_varint(buf, callsite.line as u64);
+    write_varint(buf, callsite.arg_count as u64);
+    write_str(buf, callsite.topic);
+    write_str(buf, callsite.fmt);
+    write_str(buf, callsite.file);
+    write_str(buf, callsite.module_path);
+    write_varint(buf, callsite.keys.len() as u64);
+    for key in callsite.keys {
+        write_str(buf, key);
+    }
+}
+
+/// Writes binary records to rolling `.flog` files. Every new file starts with a header and an empty dictionary,
+/// so each file can be decoded on its own.
+pub struct BinaryFileWriter {
+    writer: RollingFileWriter,
+    encoder: BinaryEncoder,
+    buffer: Vec<u8>,
+    needs_header: bool,
+}
+
+impl BinaryFileWriter {
+    pub fn new(config: RollingConfig) -> io::Result<Self> {
+        Ok(Self {
+            // the initial and current log file paths refer to the JSON log file
+            writer: RollingFileWriter::open(config, false)?,
+            encoder: BinaryEncoder::new(),
+            buffer: Vec::with_capacity(256),
+            needs_header: true,
+        })
+    }
+
+    pub fn write_record(&mut self, record: &BinaryRecord, unixnano: u64) -> io::Result<()> {
+        self.encode(record, unixnano);
+        if self.writer.roll_if_needed(self.buffer.len())? {
+            // the new file starts with a header and an empty callsite dictionary
+            self.needs_header = true;
+            self.encode(record, unixnano);
+        }
+        let result = self.writer.write_frame(&self.buffer);
+        if result.is_err() {
+            // the frame is not in the file, but the encoder counts its callsite as defined, so the next frame
+            // starts a new dictionary
+            self.needs_header = true;
+        }
+        result
+    }
+
+    fn encode(&mut self, record: &BinaryRecord, unixnano: u64) {
+        self.buffer.clear();
+        if self.needs_header {
+            let include_unixnano = INCLUDE_UNIXNANO.load(Ordering::Relaxed);
+            self.encoder.write_header(&mut self.buffer, current_zone(), include_unixnano, unixnano);
+            self.needs_header = false;
+        }
+        self.encoder.write_record(&mut self.buffer, record, unixnano);
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn sync_all(&mut self) -> io::Result<()> {
+        self.writer.sync_all()
+    }
+
+    /// Reopens the current path. The reopened file may be a new one, so it starts with a header again.
+    pub fn reopen(&mut self) -> io::Result<()> {
+        self.writer.reopen()?;
+        self.needs_header = true;
+        Ok(())
+    }
+}
+
+struct Header {
+    /// Offsets of a zone without a name are fixed at the one of the header
+    zone: Zone,
+    include_unixnano: bool,
+}
+
+struct DecodedCallsite {
+    level: usize,
+    line: u32,
+    arg_count: usize,
+    topic: String,
+    fmt: String,
+    file: String,
+    module_path: String,
+    keys: Vec<String>,
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads a binary log file and yields the JSON line of each record, in the layout of `flash_xxx_ct!`.
+/// Concatenated files (e.g. `cat a.flog b.flog`) are accepted since every file starts with a header.
+pub struct BinaryFileReader<R: Read> {
+    reader: R,
+    header: Option<Header>,
+    callsites: HashMap<u64, DecodedCallsite>,
+    last_unixnano: u64,
+    payload: Vec<u8>,
+    formatter: Option<Box<dyn Formatter>>,
+}
+
+impl<R: Read> BinaryFileReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            header: None,
+            callsites: HashMap::new(),
+            last_unixnano: 0,
+            payload: Vec::new(),
+            formatter: None,
+        }
+    }
+
+    /// Formats records with `formatter` instead of the JSON layout recorded in the file header
+    pub fn with_formatter<F: Formatter + 'static>(mut self, formatter: F) -> Self {
+        self.formatter = Some(Box::new(formatter));
+        self
+    }
+
+    /// Returns the next record as a formatted line (with a trailing newline), or `None` at the end of the input.
+    pub fn next_line(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let mut tag = [0u8; 1];
+            if self.reader.read(&mut tag)? == 0 {
+                return Ok(None);
+            }
+
+            match tag[0] {
+                t if t == MAGIC[0] => self.read_header()?,
+                FRAME_CALLSITE => self.read_callsite()?,
+                FRAME_RECORD => return self.read_record().map(Some),
+                _ => return Err(invalid_data("unknown frame tag")),
+            }
+        }
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        let mut magic = [0u8; 7];
+        self.reader.read_exact(&mut magic)?;
+        if magic != MAGIC[1..] {
+            return Err(invalid_data("not a flashlog binary file"));
+        }
+        let version = self.read_array::<1>()?[0];
+        if version == 0 || version > FORMAT_VERSION {
+            return Err(invalid_data("unsupported format version"));
+        }
+        let offset = i32::from_le_bytes(self.read_array()?);
+        let include_unixnano = self.read_array::<1>()?[0] != 0;
+        self.last_unixnano = u64::from_le_bytes(self.read_array()?);
+        let zone = match version {
+            1 => Zone::Fixed(offset * 3600),
+            _ => match self.read_str()?.parse::<Tz>() {
+                Ok(tz) => Zone::Tz(tz),
+                Err(_) => Zone::Fixed(offset),
+            },
+        };
+        self.callsites.clear();
+        self.header = Some(Header { zone, include_unixnano });
+        Ok(())
+    }
+
+    fn read_callsite(&mut self) -> io::Result<()> {
+        let id = self.read_varint()?;
+        let level = self.read_varint()? as usize;
+        let line = self.read_varint()? as u32;
+        let arg_count = self.read_varint()? as usize;
+        let topic = self.read_str()?;
+        let fmt = self.read_str()?;
+        let file = self.read_str()?;
+        let module_path = self.read_str()?;
+        let key_count = self.read_varint()?;
+        // not collected from the range, which would reserve `key_count` strings up front
+        let mut keys = Vec::new();
+        for _ in 0..key_count {
+            keys.push(self.read_str()?);
+        }
+
+        self.callsites.insert(id, DecodedCallsite { level, line, arg_count, topic, fmt, file, module_path, keys });
+        Ok(())
+    }
+
+    fn read_record(&mut self) -> io::Result<String> {
+        let id = self.read_varint()?;
+        let delta = unzigzag(self.read_varint()?);
+        let len = self.read_varint()?;
+        let mut payload = std::mem::take(&mut self.payload);
+        let result = self.read_bytes(len, &mut payload);
+        self.payload = payload;
+        result?;
+
+        let header = self.header.as_ref().ok_or_else(|| invalid_data("record before header"))?;
+        let decoded = self.callsites.get(&id).ok_or_else(|| invalid_data("unknown callsite id"))?;
+        self.last_unixnano = self.last_unixnano.wrapping_add(delta as u64);
+
+        let keys: Vec<&str> = decoded.keys.iter().map(String::as_str).collect();
+        let callsite = Callsite::new(
+            decoded.level,
+            &decoded.topic,
+            &decoded.fmt,
+            decoded.arg_count,
+            &keys,
+            &decoded.file,
+            decoded.line,
+            &decoded.module_path,
+        );
+        let utc_offset = header.zone.utc_offset_at(self.last_unixnano);
+        let line = match self.formatter {
+            Some(ref formatter) => format_binary(&**formatter, &callsite, &self.payload, self.last_unixnano, utc_offset),
+            None => json_line(&callsite, &self.payload, self.last_unixnano, utc_offset, header.include_unixnano),
+        };
+        Ok(line)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut bytes = [0u8; N];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn read_varint(&mut self) -> io::Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_array::<1>()?[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid_data("varint is too long"))
+    }
+
+    /// Reads `len` bytes into `buf`. The buffer grows with the bytes actually read, so a corrupt length fails at the
+    /// end of the input instead of allocating it up front.
+    fn read_bytes(&mut self, len: u64, buf: &mut Vec<u8>) -> io::Result<()> {
+        buf.clear();
+        (&mut self.reader).take(len).read_to_end(buf)?;
+        if (buf.len() as u64) < len {
+            return Err(invalid_data("frame is longer than the input"));
+        }
+        Ok(())
+    }
+
+    fn read_str(&mut self) -> io::Result<String> {
+        let len = self.read_varint()?;
+        let mut bytes = Vec::new();
+        self.read_bytes(len, &mut bytes)?;
+        String::from_utf8(bytes).map_err(|_| invalid_data("invalid utf-8 string"))
+    }
+}
+
+impl<R: Read> Iterator for BinaryFileReader<R> {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_line().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binary::Encode;
+
+    static FILL: Callsite = Callsite::new(3, "orders", "px {} qty {}", 2, &["venue"], file!(), line!(), module_path!());
+    static CANCEL: Callsite = Callsite::new(4, "orders", "cancel", 0, &[], file!(), line!(), module_path!());
+
+    #[test]
+    fn test_varint_zigzag() {
+        for value in [0u64, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let mut buf = Vec::new();
+            write_varint(&mut buf, value);
+            let mut reader = BinaryFileReader::new(buf.as_slice());
+            assert_eq!(reader.read_varint().unwrap(), value);
+        }
+        for value in [0i64, -1, 1, -1_000_000_000, i64::MAX, i64::MIN] {
+            assert_eq!(unzigzag(zigzag(value)), value);
+        }
+        assert_eq!(zigzag(-1), 1);
+    }
+
+    #[test]
+    fn test_round_trip_matches_json_line() {
+        let base = 1_700_000_000_000_000_000u64;
+        let mut fill = BinaryRecord::new(&FILL);
+        101.25f64.encode(&mut fill);
+        7u32.encode(&mut fill);
+        "KRX".encode(&mut fill);
+        let cancel = BinaryRecord::new(&CANCEL);
+
+        let mut encoder = BinaryEncoder::new();
+        let mut buf = Vec::new();
+        encoder.write_header(&mut buf, Zone::Tz(Tz::Asia__Seoul), true, base);
+        encoder.write_record(&mut buf, &fill, base + 10);
+        encoder.write_record(&mut buf, &cancel, base + 5); // timestamps may go backwards
+        encoder.write_record(&mut buf, &fill, base + 2_000);
+        // a second file appended to the first one
+        encoder.write_header(&mut buf, Zone::Fixed(9 * 3600), true, base + 3_000);
+        encoder.write_record(&mut buf, &cancel, base + 3_001);
+
+        let lines = BinaryFileReader::new(buf.as_slice()).collect::<io::Result<Vec<_>>>().unwrap();
+        let expected = vec![
+            json_line(&FILL, fill.as_bytes(), base + 10, 9 * 3600, true),
+            json_line(&CANCEL, cancel.as_bytes(), base + 5, 9 * 3600, true),
+            json_line(&FILL, fill.as_bytes(), base + 2_000, 9 * 3600, true),
+            json_line(&CANCEL, cancel.as_bytes(), base + 3_001, 9 * 3600, true),
+        ];
+        assert_eq!(lines, expected);
+
+        // the callsite is defined once per file
+        let definitions = buf.windows(b"px {} qty {}".len()).filter(|w| *w == b"px {} qty {}").count();
+        assert_eq!(definitions, 1);
+    }
+
+    #[test]
+    fn test_rejects_corrupt_file() {
+        let header = |version: u8| {
+            let mut buf = Vec::new();
+            buf.extend_from_slice(MAGIC);
+            buf.push(version);
+            buf.extend_from_slice(&0i32.to_le_bytes());
+            buf.push(0);
+            buf.extend_from_slice(&0u64.to_le_bytes());
+            buf
+        };
+        let error_kind = |buf: Vec<u8>| {
+            let result = BinaryFileReader::new(buf.as_slice()).collect::<io::Result<Vec<_>>>();
+            result.unwrap_err().kind()
+        };
+
+        // record whose payload length is far beyond the input
+        let mut buf = header(1);
+        buf.push(FRAME_RECORD);
+        write_varint(&mut buf, 0);
+        write_varint(&mut buf, 0);
+        write_varint(&mut buf, u64::MAX >> 1);
+        buf.extend_from_slice(b"short");
+        assert_eq!(error_kind(buf), io::ErrorKind::InvalidData);
+
+        // callsite with a huge string and with a huge number of keys
+        let mut buf = header(1);
+        buf.push(FRAME_CALLSITE);
+        for value in [0, 3, 1, 0] {
+            write_varint(&mut buf, value);
+        }
+        write_varint(&mut buf, 1 << 40);
+        assert_eq!(error_kind(buf), io::ErrorKind::InvalidData);
+        let mut buf = header(1);
+        buf.push(FRAME_CALLSITE);
+        for value in [0, 3, 1, 0, 0, 0, 0, 0, 1 << 40] {
+            write_varint(&mut buf, value);
+        }
+        assert_eq!(error_kind(buf), io::ErrorKind::UnexpectedEof);
+
+        // a huge argument count only fails to decode the record
+        let mut buf = header(1);
+        buf.push(FRAME_CALLSITE);
+        for value in [0, 3, 1, 1 << 40, 0, 0, 0, 0, 0] {
+            write_varint(&mut buf, value);
+        }
+        buf.push(FRAME_RECORD);
+        for value in [0, 0, 1, 0] {
+            write_varint(&mut buf, value);
+        }
+        assert_eq!(BinaryFileReader::new(buf.as_slice()).count(), 1);
+
+        assert_eq!(error_kind(header(0)), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_reads_version_1_header() {
+        let base = 1_700_000_000_000_000_000u64;
+        let cancel = BinaryRecord::new(&CANCEL);
+        let mut buf = Vec::new();
+        buf.extend_from_slice(MAGIC);
+        buf.push(1);
+        buf.extend_from_slice(&9i32.to_le_bytes()); // hours
+        buf.push(1);
+        buf.extend_from_slice(&base.to_le_bytes());
+        let mut encoder = BinaryEncoder::new();
+        encoder.last_unixnano = base;
+        encoder.write_record(&mut buf, &cancel, base + 1);
+
+        let lines = BinaryFileReader::new(buf.as_slice()).collect::<io::Result<Vec<_>>>().unwrap();
+        assert_eq!(lines, vec![json_line(&CANCEL, cancel.as_bytes(), base + 1, 9 * 3600, true)]);
+    }
+
+    #[test]
+    fn test_rejects_unknown_input() {
+        let mut reader = BinaryFileReader::new(&b"{\"date\":1}"[..]);
+        assert!(reader.next_line().is_err());
+    }
+}
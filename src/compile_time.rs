@@ -1,3 +1,6 @@
+use crate::logger::LogLevel;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 pub const TRACE: usize = 5;
 pub const DEBUG: usize = 4;
 pub const INFO: usize = 3;
@@ -21,6 +24,55 @@ pub const MAX_LEVEL: usize = if cfg!(feature = "max-level-trace") {
     TRACE
 };
 
+/// Least severe level logged by the `_ct` and `_bin` macros outside of the directives of `set_filter`, checked after
+/// `MAX_LEVEL` so that levels disabled at compile time still compile away. Changed at runtime with `set_level`.
+static RUNTIME_LEVEL: AtomicUsize = AtomicUsize::new(TRACE);
+
+/// Sets the least severe level logged from now on, from any thread, e.g. `set_level(LogLevel::Debug)` while
+/// investigating an incident. `LogLevel::NIL` turns logging off. Levels above `MAX_LEVEL` stay disabled, and the
+/// directives of `set_filter` take precedence.
+pub fn set_level(level: LogLevel) {
+    store_runtime_level(level);
+    crate::level_filter::refresh_callsites();
+}
+
+/// Least severe level currently logged outside of the directives of `set_filter`: the level set by `set_level`,
+/// capped by `MAX_LEVEL`
+pub fn get_level() -> LogLevel {
+    LogLevel::from_usize(runtime_level().min(MAX_LEVEL)).unwrap_or(LogLevel::Trace)
+}
+
+pub(crate) fn store_runtime_level(level: LogLevel) {
+    RUNTIME_LEVEL.store(level.as_usize(), Ordering::Relaxed);
+}
+
+pub(crate) fn runtime_level() -> usize {
+    RUNTIME_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Whether a callsite logs `$level`: a constant comparison, then one relaxed atomic load of the level cached for
+/// the callsite. The topic is either the topic expression of a `_ct` macro or, after `const`, a `&'static str`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! callsite_enabled {
+    ($level:expr) => {
+        $crate::callsite_enabled!(@check $level, $crate::level_filter::CallsiteLevel::new("", module_path!()))
+    };
+    ($level:expr, const $topic:expr) => {
+        $crate::callsite_enabled!(@check $level, $crate::level_filter::CallsiteLevel::new($topic, module_path!()))
+    };
+    ($level:expr, $topic:expr) => {
+        $crate::callsite_enabled!(
+            @check $level,
+            $crate::level_filter::CallsiteLevel::with_topic_tokens(stringify!($topic), module_path!())
+        )
+    };
+    (@check $level:expr, $callsite:expr) => {{
+        static LEVEL: $crate::level_filter::CallsiteLevel = $callsite;
+        $level <= $crate::compile_time::MAX_LEVEL && LEVEL.enabled($level)
+    }};
+}
+
 #[macro_export]
 macro_rules! log_with_level_ct {
     // Case 1: topic, format string argument arguments, and key-value pairs
@@ -142,268 +194,157 @@ pub fn usize_to_level(level: usize) -> &'static str {
 macro_rules! log_fn_json_v3 {
     // Case 1: topic, format sring, kv
     ($level:expr, $topic:expr; $fmt:expr, $($arg:expr),*; $($key:ident = $value:expr),+ $(,)?) => {{
-        if $level <= $crate::compile_time::MAX_LEVEL {
+        if $crate::callsite_enabled!($level, $topic) {
             $(
                 #[allow(non_snake_case)]
                 let $key = $value.clone();
             )+
 
+            let callsite_time = $crate::timer::CallsiteTime::now();
             let func = move || {
-                let json_obj = $crate::serde_json::json!({
-                    $(
-                        stringify!($key): $key,
-                    )+
-                });
-                let unixnano = $crate::get_unix_nano();
-                let include_unixnano = $crate::logger::INCLUDE_UNIXNANO.load(std::sync::atomic::Ordering::Relaxed);
-                let timezone = $crate::TIMEZONE.load(std::sync::atomic::Ordering::Relaxed);
-                let (date, time) = $crate::convert_unix_nano_to_date_and_time(unixnano, timezone);
-                
-                let json_msg = match include_unixnano {
-                    false => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $crate::compile_time::usize_to_level($level),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": $topic,
-                        "data": json_obj,
-                        "message": format!($fmt, $($arg),*),
-                    }),
-                    true => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $crate::compile_time::usize_to_level($level),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": $topic,
-                        "data": json_obj,
-                        "message": format!($fmt, $($arg),*),
-                        "unixnano": unixnano,
-                    }),
-                };
-
-                json_msg.to_string() + "\n"
+                let (unixnano, queue_latency) = callsite_time.resolve();
+                $crate::LogRecord {
+                    level: $level,
+                    topic: $topic.to_string(),
+                    message: format!($fmt, $($arg),*),
+                    data: Some(vec![$((stringify!($key), $crate::serde_json::json!($key)),)+]),
+                    file: file!(),
+                    line: line!(),
+                    module_path: module_path!(),
+                    unixnano,
+                    queue_latency,
+                }
             };
 
-            $crate::LOG_SENDER.try_send($crate::LogMessage::LazyMessage($crate::LazyMessage::new(func))).unwrap();
+            $crate::LOG_SENDER.send_log($crate::LogMessage::LazyMessage($crate::LazyMessage::record($level, func)));
         }
     }};
     
     // Case 2: topic, static string, kv
     ($level:expr, $topic:expr; $msg:expr; $($key:ident = $value:expr),+ $(,)?) => {{
-        if $level <= $crate::compile_time::MAX_LEVEL {
+        if $crate::callsite_enabled!($level, $topic) {
             $(
                 #[allow(non_snake_case)]
                 let $key = $value.clone();
-            )*
+            )+
+
+            let callsite_time = $crate::timer::CallsiteTime::now();
             let func = move || {
-                let json_obj = $crate::serde_json::json!({
-                    $(
-                        stringify!($key): $key,
-                    )+
-                });
-                let unixnano = $crate::get_unix_nano();
-                let include_unixnano = $crate::logger::INCLUDE_UNIXNANO.load(std::sync::atomic::Ordering::Relaxed);
-                let timezone = $crate::TIMEZONE.load(std::sync::atomic::Ordering::Relaxed);
-                let (date, time) = $crate::convert_unix_nano_to_date_and_time(unixnano, timezone);
-                let json_msg = match include_unixnano {
-                    false => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $crate::compile_time::usize_to_level($level),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": $topic,
-                        "data": json_obj,
-                        "message": $msg,
-                    }),
-                    true => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $crate::compile_time::usize_to_level($level),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": $topic,
-                        "data": json_obj,
-                        "message": $msg,
-                        "unixnano": unixnano,
-                    }),
-                };
-
-                json_msg.to_string() + "\n"
+                let (unixnano, queue_latency) = callsite_time.resolve();
+                $crate::LogRecord {
+                    level: $level,
+                    topic: $topic.to_string(),
+                    message: $msg.to_string(),
+                    data: Some(vec![$((stringify!($key), $crate::serde_json::json!($key)),)+]),
+                    file: file!(),
+                    line: line!(),
+                    module_path: module_path!(),
+                    unixnano,
+                    queue_latency,
+                }
             };
 
-            $crate::LOG_SENDER.try_send($crate::LogMessage::LazyMessage($crate::LazyMessage::new(func)))
-                .unwrap();
+            $crate::LOG_SENDER.send_log($crate::LogMessage::LazyMessage($crate::LazyMessage::record($level, func)));
         }
     }};
     
     // Case 3: topic and formated string
     ($level:expr, $topic:expr; $fmt:expr, $($arg:expr),* $(,)?) => {{
-        if $level <= $crate::compile_time::MAX_LEVEL {
+        if $crate::callsite_enabled!($level, $topic) {
+            let callsite_time = $crate::timer::CallsiteTime::now();
             let func = move || {
-                let timezone = $crate::TIMEZONE.load(std::sync::atomic::Ordering::Relaxed);
-                let unixnano = $crate::get_unix_nano();
-                let (date, time) = $crate::convert_unix_nano_to_date_and_time(unixnano, timezone);
-                let include_unixnano = $crate::logger::INCLUDE_UNIXNANO.load(std::sync::atomic::Ordering::Relaxed);
-                let json_msg = match include_unixnano {
-                    false => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $crate::compile_time::usize_to_level($level),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": $topic,
-                        "message": format!($fmt, $($arg),*),
-                        "data": "",
-                    }),
-                    true => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $crate::compile_time::usize_to_level($level),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": $topic,
-                        "message": format!($fmt, $($arg),*),
-                        "data": "",
-                        "unixnano": unixnano,
-                    }),
-                };
-                json_msg.to_string() + "\n"
+                let (unixnano, queue_latency) = callsite_time.resolve();
+                $crate::LogRecord {
+                    level: $level,
+                    topic: $topic.to_string(),
+                    message: format!($fmt, $($arg),*),
+                    data: Some(Vec::new()),
+                    file: file!(),
+                    line: line!(),
+                    module_path: module_path!(),
+                    unixnano,
+                    queue_latency,
+                }
             };
-            $crate::LOG_SENDER.try_send($crate::LogMessage::LazyMessage($crate::LazyMessage::new(func))).unwrap();
+
+            $crate::LOG_SENDER.send_log($crate::LogMessage::LazyMessage($crate::LazyMessage::record($level, func)));
         }
     }};
     
     // Case 4: topic and static string
     ($level:expr, $topic:expr; $msg:expr $(,)?) => {{
-        if $level <= $crate::compile_time::MAX_LEVEL {
+        if $crate::callsite_enabled!($level, $topic) {
+            let callsite_time = $crate::timer::CallsiteTime::now();
             let func = move || {
-                let unixnano = $crate::get_unix_nano();
-                let include_unixnano = $crate::logger::INCLUDE_UNIXNANO.load(std::sync::atomic::Ordering::Relaxed);
-                let timezone = $crate::TIMEZONE.load(std::sync::atomic::Ordering::Relaxed);
-                let (date, time) = $crate::convert_unix_nano_to_date_and_time(unixnano, timezone);
-                let json_msg = match include_unixnano {
-                    false => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $crate::compile_time::usize_to_level($level),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": $topic.to_string(),
-                        "message": $msg
-                    }),
-                    true => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $crate::compile_time::usize_to_level($level),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": $topic.to_string(),
-                        "message": $msg,
-                        "unixnano": unixnano,
-                    }),
-                };
-
-                json_msg.to_string() + "\n"
+                let (unixnano, queue_latency) = callsite_time.resolve();
+                $crate::LogRecord {
+                    level: $level,
+                    topic: $topic.to_string(),
+                    message: $msg.to_string(),
+                    data: None,
+                    file: file!(),
+                    line: line!(),
+                    module_path: module_path!(),
+                    unixnano,
+                    queue_latency,
+                }
             };
 
-            $crate::LOG_SENDER.try_send($crate::LogMessage::LazyMessage($crate::LazyMessage::new(func))).unwrap();
+            $crate::LOG_SENDER.send_log($crate::LogMessage::LazyMessage($crate::LazyMessage::record($level, func)));
         }
     }};
 
     // **Case 7: Single key-value pair without topic**
     ($level:expr, $key:ident = $value:expr) => {{
-        if $level <= $crate::compile_time::MAX_LEVEL {
-            $(
-                #[allow(non_snake_case)]
-                let $key = $value.clone();
-            )*
+        if $crate::callsite_enabled!($level) {
+            #[allow(non_snake_case)]
+            let $key = $value.clone();
+
+            let callsite_time = $crate::timer::CallsiteTime::now();
             let func = move || {
-                let unixnano = $crate::get_unix_nano();
-                let include_unixnano = $crate::logger::INCLUDE_UNIXNANO.load(std::sync::atomic::Ordering::Relaxed);
-                let json_obj = $crate::serde_json::json!({
-                    $(
-                        stringify!($key): $key,
-                    )+
-                });
-                let timezone = $crate::TIMEZONE.load(std::sync::atomic::Ordering::Relaxed);
-                let (date, time) = $crate::convert_unix_nano_to_date_and_time(unixnano, timezone);
-                let json_msg = match include_unixnano {
-                    false => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $crate::compile_time::usize_to_level($level),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": "",
-                        "data": json_obj,
-                        "message": "",
-                    }),
-                    true => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $crate::compile_time::usize_to_level($level),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": "",
-                        "data": json_obj,
-                        "message": "",
-                        "unixnano": unixnano,
-                    }),
-                };
-                json_msg.to_string() + "\n"
+                let (unixnano, queue_latency) = callsite_time.resolve();
+                $crate::LogRecord {
+                    level: $level,
+                    topic: String::new(),
+                    message: String::new(),
+                    data: Some(vec![(stringify!($key), $crate::serde_json::json!($key))]),
+                    file: file!(),
+                    line: line!(),
+                    module_path: module_path!(),
+                    unixnano,
+                    queue_latency,
+                }
             };
 
-            $crate::LOG_SENDER.try_send($crate::LogMessage::LazyMessage($crate::LazyMessage::new(func))).unwrap();
+            $crate::LOG_SENDER.send_log($crate::LogMessage::LazyMessage($crate::LazyMessage::record($level, func)));
         }
     }};
     
     // **Case 8: Multiple key-value pairs without topic**
     ($level:expr, $($key:ident = $value:expr),+ $(,)?) => {{
-        if $level <= $crate::compile_time::MAX_LEVEL {
+        if $crate::callsite_enabled!($level) {
             $(
                 #[allow(non_snake_case)]
                 let $key = $value.clone();
-            )*
+            )+
+
+            let callsite_time = $crate::timer::CallsiteTime::now();
             let func = move || {
-                let unixnano = $crate::get_unix_nano();
-                let include_unixnano = $crate::logger::INCLUDE_UNIXNANO.load(std::sync::atomic::Ordering::Relaxed);
-                let json_obj = $crate::serde_json::json!({
-                    $(
-                        stringify!($key): $key,
-                    )+
-                });
-                let timezone = $crate::TIMEZONE.load(std::sync::atomic::Ordering::Relaxed);
-                let (date, time) = $crate::convert_unix_nano_to_date_and_time(unixnano, timezone);
-                let json_msg = match include_unixnano {
-                    false => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $crate::compile_time::usize_to_level($level),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "data": json_obj,
-                        "topic": "",
-                        "message": "",
-                    }),
-                    true => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $crate::compile_time::usize_to_level($level),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "data": json_obj,
-                        "topic": "",
-                        "message": "",
-                        "unixnano": unixnano,
-                    }),
-                };
-                json_msg.to_string() + "\n"
+                let (unixnano, queue_latency) = callsite_time.resolve();
+                $crate::LogRecord {
+                    level: $level,
+                    topic: String::new(),
+                    message: String::new(),
+                    data: Some(vec![$((stringify!($key), $crate::serde_json::json!($key)),)+]),
+                    file: file!(),
+                    line: line!(),
+                    module_path: module_path!(),
+                    unixnano,
+                    queue_latency,
+                }
             };
 
-            $crate::LOG_SENDER.try_send($crate::LogMessage::LazyMessage($crate::LazyMessage::new(func))).unwrap();
+            $crate::LOG_SENDER.send_log($crate::LogMessage::LazyMessage($crate::LazyMessage::record($level, func)));
         }
     }};
 }
@@ -0,0 +1,269 @@
+//! Compression of rolled log files.
+//!
+//! The logger thread only queues a rolled file; a low-priority background thread streams it into the compressed
+//! file in chunks, then removes the original. If the logger was launched, the outcome is logged as a `flashlog`
+//! record: `Info` when the file is compressed and `Error` (with the original left in place) when it fails.
+//! gzip is always available, zstd and lz4 with the `zstd` and `lz4` cargo features.
+use crate::logger::{internal_record, LazyMessage, LogLevel, LogMessage, LOG_SENDER};
+use crossbeam_channel::{unbounded, Sender};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs::{remove_file, rename, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+/// Files are read and compressed in chunks of this size
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// `.gz` (default)
+    #[default]
+    Gzip,
+    /// `.zst`
+    #[cfg(feature = "zstd")]
+    Zstd,
+    /// `.lz4` (frame format)
+    #[cfg(feature = "lz4")]
+    Lz4,
+}
+
+impl Compression {
+    /// Extension of the compressed files, appended to the name of the log file
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => "zst",
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => "lz4",
+        }
+    }
+
+    /// Path of the compressed `path`, e.g. `message-20240915.log.gz`. The extension of the log file is kept, so
+    /// `message-20240915.log` and `message-20240915.flog` do not compress to the same file.
+    pub fn compressed_path(&self, path: &Path) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".");
+        name.push(self.extension());
+        PathBuf::from(name)
+    }
+}
+
+/// Compresses `source` into `Compression::compressed_path`, streaming it in chunks.
+/// The output is written to a `.tmp` file first, and `source` is removed only when the output is complete.
+/// Returns the path of the compressed file.
+pub fn compress_file(source: &Path, compression: Compression) -> io::Result<PathBuf> {
+    let target = compression.compressed_path(source);
+    let mut partial = target.clone().into_os_string();
+    partial.push(".tmp");
+    let partial = PathBuf::from(partial);
+    let result = (|| {
+        let mut input = BufReader::with_capacity(CHUNK_SIZE, File::open(source)?);
+        let output = BufWriter::with_capacity(CHUNK_SIZE, File::create(&partial)?);
+        let mut output = match compression {
+            Compression::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::fast());
+                io::copy(&mut input, &mut encoder)?;
+                encoder.finish()?
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                let mut encoder = zstd::Encoder::new(output, 0)?;
+                io::copy(&mut input, &mut encoder)?;
+                encoder.finish()?
+            }
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(output);
+                io::copy(&mut input, &mut encoder)?;
+                encoder.finish().map_err(io::Error::other)?
+            }
+        };
+        output.flush()?;
+        rename(&partial, &target)?;
+        remove_file(source)
+    })();
+
+    match result {
+        Ok(()) => Ok(target),
+        Err(e) => {
+            let _ = remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+struct Job {
+    source: PathBuf,
+    compression: Compression,
+    /// Run after the file is compressed, e.g. to remove old compressed files
+    then: Box<dyn FnOnce() + Send>,
+}
+
+struct Compressor {
+    sender: Sender<Job>,
+    /// Files queued or being compressed
+    pending: Mutex<HashSet<PathBuf>>,
+    idle: Condvar,
+}
+
+static COMPRESSOR: Lazy<Compressor> = Lazy::new(|| {
+    let (sender, receiver) = unbounded::<Job>();
+    thread::Builder::new()
+        .name("flashlog-compress".to_string())
+        .spawn(move || {
+            lower_thread_priority();
+            while let Ok(job) = receiver.recv() {
+                let size = std::fs::metadata(&job.source).map(|m| m.len()).unwrap_or(0);
+                let result = compress_file(&job.source, job.compression);
+                let compressed = result.is_ok();
+                report(&job.source, size, result);
+                if compressed {
+                    (job.then)();
+                }
+
+                let compressor = &*COMPRESSOR;
+                let mut pending = compressor.pending.lock().expect("Failed to lock pending compressions");
+                pending.remove(&job.source);
+                if pending.is_empty() {
+                    compressor.idle.notify_all();
+                }
+            }
+        })
+        .expect("Failed to spawn the compression thread");
+
+    Compressor {
+        sender,
+        pending: Mutex::new(HashSet::new()),
+        idle: Condvar::new(),
+    }
+});
+
+#[cfg(target_os = "linux")]
+fn lower_thread_priority() {
+    // on Linux the nice value is per thread
+    unsafe {
+        libc::setpriority(libc::PRIO_PROCESS, libc::gettid() as libc::id_t, 10);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn lower_thread_priority() {}
+
+fn report(source: &Path, size: u64, result: io::Result<PathBuf>) {
+    // nothing to report to if the logger was never launched, e.g. a writer used on its own
+    if Lazy::get(&LOG_SENDER).is_none() {
+        return;
+    }
+    let file = source.display().to_string();
+    let message = match result {
+        Ok(target) => {
+            let compressed_size = std::fs::metadata(&target).map(|m| m.len()).unwrap_or(0);
+            let target = target.display().to_string();
+            LazyMessage::record(LogLevel::Info.as_usize(), move || {
+                internal_record(
+                    LogLevel::Info,
+                    "rolled file compressed",
+                    vec![
+                        ("file", serde_json::json!(file)),
+                        ("compressed_file", serde_json::json!(target)),
+                        ("size", serde_json::json!(size)),
+                        ("compressed_size", serde_json::json!(compressed_size)),
+                    ],
+                )
+            })
+        }
+        Err(e) => {
+            let error = e.to_string();
+            LazyMessage::record(LogLevel::Error.as_usize(), move || {
+                internal_record(
+                    LogLevel::Error,
+                    "failed to compress rolled file",
+                    vec![("file", serde_json::json!(file)), ("error", serde_json::json!(error))],
+                )
+            })
+        }
+    };
+    // the report is dropped if the logger thread has already stopped
+    let _ = LOG_SENDER.send(LogMessage::LazyMessage(message));
+}
+
+/// Queues `source` for compression, running `then` once it is compressed. Returns false if it is already queued.
+pub(crate) fn submit<F: FnOnce() + Send + 'static>(source: PathBuf, compression: Compression, then: F) -> bool {
+    let compressor = &*COMPRESSOR;
+    if !compressor.pending.lock().expect("Failed to lock pending compressions").insert(source.clone()) {
+        return false;
+    }
+    if let Err(e) = compressor.sender.send(Job { source, compression, then: Box::new(then) }) {
+        compressor.pending.lock().expect("Failed to lock pending compressions").remove(&e.0.source);
+        return false;
+    }
+    true
+}
+
+/// Whether `path` is queued or being compressed
+pub(crate) fn is_pending(path: &Path) -> bool {
+    match Lazy::get(&COMPRESSOR) {
+        Some(compressor) => compressor.pending.lock().expect("Failed to lock pending compressions").contains(path),
+        None => false,
+    }
+}
+
+/// Blocks until every queued file is compressed. Called by `Logger::finalize`.
+pub fn wait_for_compression() {
+    let Some(compressor) = Lazy::get(&COMPRESSOR) else {
+        return;
+    };
+    let mut pending = compressor.pending.lock().expect("Failed to lock pending compressions");
+    while !pending.is_empty() {
+        pending = compressor.idle.wait(pending).expect("Failed to lock pending compressions");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn round_trip(compression: Compression, decode: impl Fn(File) -> Box<dyn Read>) {
+        let dir = std::env::temp_dir().join(format!("flashlog_test_compress_{}", compression.extension()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("message-20240915.log");
+        let content: String = (0..10_000).map(|i| format!("{{\"seq\":{}}}\n", i)).collect();
+        std::fs::write(&source, &content).unwrap();
+        // a binary file of the same period compresses to a file of its own
+        let binary_source = dir.join("message-20240915.flog");
+        std::fs::write(&binary_source, b"FLASHLOG").unwrap();
+        let binary_target = compress_file(&binary_source, compression).unwrap();
+        assert_eq!(binary_target, dir.join(format!("message-20240915.flog.{}", compression.extension())));
+
+        let target = compress_file(&source, compression).unwrap();
+        assert_eq!(target, dir.join(format!("message-20240915.log.{}", compression.extension())));
+        assert!(!source.exists());
+        let mut decoded = String::new();
+        decode(File::open(&target).unwrap()).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, content);
+
+        // a failure leaves no partial file behind
+        assert!(compress_file(&source, compression).is_err());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        assert!(binary_target.exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_compress_file_round_trip() {
+        round_trip(Compression::Gzip, |file| Box::new(flate2::read::GzDecoder::new(file)));
+        #[cfg(feature = "zstd")]
+        round_trip(Compression::Zstd, |file| Box::new(zstd::Decoder::new(file).unwrap()));
+        #[cfg(feature = "lz4")]
+        round_trip(Compression::Lz4, |file| Box::new(lz4_flex::frame::FrameDecoder::new(file)));
+    }
+}
@@ -0,0 +1,505 @@
+//! Logger configuration from a TOML or JSON file.
+//!
+//! [`LoggerConfig`] mirrors the builder options of `Logger`. `Logger::from_config_file` reads it from a file, and
+//! `Logger::with_config_watch` rereads the file while the logger runs. On a change, the level, the flush settings,
+//! the console, the formatters and the timestamp format are applied again through `LogMessage::SetConfig`. The other
+//! settings need a restart. Options taking code, such as `with_error_callback` or sinks other than stdout, stderr
+//! and rolling files, are only available on the builder.
+use crate::compression::Compression;
+use crate::error_policy::ErrorPolicy;
+use crate::formatter::{
+    set_timestamp_format, Formatter, JsonFormatter, LogfmtFormatter, PrettyFormatter, TextFormatter, TimestampFormat,
+    CONSOLE_FORMATTER, FORMATTER,
+};
+use crate::level_filter::{set_filter, LevelFilter};
+use crate::logger::{
+    internal_record, LazyMessage, LogLevel, LogMessage, LogTransport, Logger, LoggerError, OverflowPolicy, TimeZone,
+    CONSOLE_REPORT, LOGGER_HANDLER, LOG_MESSAGE_BUFFER_SIZE, LOG_MESSAGE_FLUSH_INTERVAL, LOG_SENDER,
+};
+use crate::rolling_file::{RollingConfig, RollingPeriod};
+use crate::sink::{FileSink, FlushPolicy, SinkConfig, StderrSink, StdoutSink};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Settings of a logger. Missing settings keep the defaults of `Logger::initialize()`.
+///
+/// ```toml
+/// level = "orders=debug,*=info"
+/// console = false
+/// buffer_size = 100
+/// flush_interval = 1_000_000_000
+/// timezone = "seoul"
+///
+/// [file]
+/// path = "logs"
+/// prefix = "message"
+/// roll_period = "daily"
+/// compress = true
+///
+/// [[topic_files]]
+/// topic = "orders"
+/// path = "logs"
+/// prefix = "orders"
+/// roll_period = "hourly"
+///
+/// [[sinks]]
+/// type = "stderr"
+/// level = "error"
+/// format = "text"
+///
+/// [error_policy.retry]
+/// attempts = 3
+/// backoff_ms = 10
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggerConfig {
+    /// A level (`info`) or level filter directives (`orders=debug,*=info`), see `set_filter`
+    pub level: Option<String>,
+    pub file: Option<FileConfig>,
+    /// Directory and prefix of the `flash_xxx_bin!` records, see `Logger::with_binary_file`
+    pub binary_file: Option<FileLocation>,
+    pub topic_files: Vec<TopicFileConfig>,
+    pub sinks: Vec<SinkEntry>,
+    pub console: Option<bool>,
+    /// Formatter of the file and, unless `console_format` is set, of the console
+    pub format: Option<FormatterKind>,
+    pub console_format: Option<FormatterKind>,
+    pub buffer_size: Option<usize>,
+    /// In nanoseconds
+    pub flush_interval: Option<u64>,
+    pub timezone: Option<TimeZone>,
+    pub include_unixnano: Option<bool>,
+    /// See `Logger::with_callsite_timestamp`
+    pub callsite_timestamp: Option<bool>,
+    /// Adds `queue_latency_ns` to each record, see `Logger::with_queue_latency`
+    pub queue_latency: Option<bool>,
+    /// `legacy`, `rfc3339`, `rfc3339:N` (N fractional digits), `epoch_millis`, `epoch_micros` or `epoch_nanos`
+    pub timestamp_format: Option<TimestampFormat>,
+    /// Core of the logger thread, `-1` for any
+    pub core: Option<i32>,
+    /// Uses `LogTransport::RingBuffer` with this capacity instead of the channel
+    pub ring_buffer_capacity: Option<usize>,
+    /// `block`, `drop_newest`, `drop_oldest` or `{ drop_below_level = "warn" }`, see `Logger::with_overflow_policy`
+    pub overflow_policy: Option<OverflowPolicy>,
+    /// `fallback_to_stderr`, `drop` or `{ retry = { attempts = 3, backoff_ms = 10 } }`, see `Logger::with_error_policy`
+    pub error_policy: Option<ErrorPolicy>,
+    pub thread_batch_size: Option<usize>,
+}
+
+/// The log file set by `Logger::with_file` and its rolling settings
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    pub path: String,
+    pub prefix: String,
+    pub roll_period: Option<RollingPeriod>,
+    pub max_roll_files: Option<usize>,
+    pub max_file_size: Option<u64>,
+    pub max_age_secs: Option<u64>,
+    pub max_total_size: Option<u64>,
+    pub compress: Option<bool>,
+    pub compression: Option<Compression>,
+    pub file_name_pattern: Option<String>,
+    pub current_link: Option<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileLocation {
+    pub path: String,
+    pub prefix: String,
+}
+
+/// A file of its own for a topic, see `Logger::with_topic_route`.
+/// Missing rolling settings keep the defaults of `Logger::with_topic_file`: rolled daily, keeping 10 files.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TopicFileConfig {
+    pub topic: String,
+    pub path: String,
+    pub prefix: String,
+    pub roll_period: Option<RollingPeriod>,
+    pub max_roll_files: Option<usize>,
+    pub max_file_size: Option<u64>,
+    pub max_age_secs: Option<u64>,
+    pub max_total_size: Option<u64>,
+    pub compress: Option<bool>,
+    pub compression: Option<Compression>,
+    pub file_name_pattern: Option<String>,
+    pub current_link: Option<bool>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkType {
+    Stdout,
+    Stderr,
+    /// Rolling file with the defaults of `FileSink::new`. Needs `path` and `prefix`.
+    File,
+}
+
+/// A sink added with `Logger::with_sink`
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SinkEntry {
+    #[serde(rename = "type")]
+    pub sink_type: SinkType,
+    pub path: Option<String>,
+    pub prefix: Option<String>,
+    pub level: Option<LogLevel>,
+    pub format: Option<FormatterKind>,
+    pub flush_policy: Option<FlushPolicy>,
+}
+
+/// Built-in formatters by name
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FormatterKind {
+    Json,
+    Text,
+    Logfmt,
+    Pretty,
+}
+
+impl FormatterKind {
+    pub fn formatter(&self) -> Arc<dyn Formatter> {
+        match self {
+            FormatterKind::Json => Arc::new(JsonFormatter::new()),
+            FormatterKind::Text => Arc::new(TextFormatter::new()),
+            FormatterKind::Logfmt => Arc::new(LogfmtFormatter::new()),
+            FormatterKind::Pretty => Arc::new(PrettyFormatter::new()),
+        }
+    }
+}
+
+impl LoggerConfig {
+    pub fn from_toml(content: &str) -> Result<LoggerConfig, LoggerError> {
+        toml::from_str(content).map_err(|e| LoggerError::InvalidConfig(e.to_string()))
+    }
+
+    pub fn from_json(content: &str) -> Result<LoggerConfig, LoggerError> {
+        serde_json::from_str(content).map_err(|e| LoggerError::InvalidConfig(e.to_string()))
+    }
+
+    /// Reads JSON if the file name ends with `.json`, TOML otherwise
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<LoggerConfig, LoggerError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(LoggerError::Io)?;
+        let config = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")) {
+            LoggerConfig::from_json(&content)
+        } else {
+            LoggerConfig::from_toml(&content)
+        };
+        config.map_err(|e| LoggerError::InvalidConfig(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Applies the settings to `logger`. Only the level filter is checked up front, so an invalid level filter
+    /// applies nothing; a file or sink that cannot be opened returns an error after the settings before it.
+    pub fn apply(&self, mut logger: Logger) -> Result<Logger, LoggerError> {
+        self.check()?;
+        if let Some(file) = &self.file {
+            logger = logger.with_file(&file.path, &file.prefix).map_err(LoggerError::Io)?;
+        }
+        if let Some(binary_file) = &self.binary_file {
+            logger = logger.with_binary_file(&binary_file.path, &binary_file.prefix).map_err(LoggerError::Io)?;
+        }
+        // rolling settings apply to both files
+        if let Some(file) = &self.file {
+            if let Some(period) = &file.roll_period {
+                logger = logger.with_roll_period(period.clone())?;
+            }
+            if let Some(max_roll_files) = file.max_roll_files {
+                logger = logger.with_max_roll_files(max_roll_files)?;
+            }
+            if let Some(max_file_size) = file.max_file_size {
+                logger = logger.with_max_file_size(max_file_size)?;
+            }
+            if let Some(max_age_secs) = file.max_age_secs {
+                logger = logger.with_max_age(Duration::from_secs(max_age_secs))?;
+            }
+            if let Some(max_total_size) = file.max_total_size {
+                logger = logger.with_max_total_size(max_total_size)?;
+            }
+            if let Some(compress) = file.compress {
+                logger = logger.with_compress(compress)?;
+            }
+            if let Some(compression) = file.compression {
+                logger = logger.with_compression(compression)?;
+            }
+            if let Some(pattern) = &file.file_name_pattern {
+                logger = logger.with_file_name_pattern(pattern)?;
+            }
+            if let Some(current_link) = file.current_link {
+                logger = logger.with_current_link(current_link)?;
+            }
+        }
+        for topic_file in &self.topic_files {
+            logger = logger
+                .with_topic_route(&topic_file.topic, topic_file.rolling_config())
+                .map_err(LoggerError::Io)?;
+        }
+        for entry in &self.sinks {
+            logger = logger.with_sink(entry.sink_config()?);
+        }
+
+        if let Some(timezone) = &self.timezone {
+            logger = logger.with_timezone(timezone.clone());
+        }
+        if let Some(include) = self.include_unixnano {
+            logger = logger.include_unixnano(include);
+        }
+        if let Some(enabled) = self.callsite_timestamp {
+            logger = logger.with_callsite_timestamp(enabled);
+        }
+        if let Some(enabled) = self.queue_latency {
+            logger = logger.with_queue_latency(enabled);
+        }
+        if let Some(core) = self.core {
+            logger = logger.with_logger_core(core);
+        }
+        if let Some(capacity) = self.ring_buffer_capacity {
+            logger = logger.with_transport(LogTransport::RingBuffer(capacity));
+        }
+        if let Some(policy) = self.overflow_policy {
+            logger = logger.with_overflow_policy(policy);
+        }
+        if let Some(policy) = self.error_policy {
+            logger = logger.with_error_policy(policy);
+        }
+        if let Some(size) = self.thread_batch_size {
+            logger = logger.with_thread_batch_size(size);
+        }
+        self.apply_reloadable()?;
+        Ok(logger)
+    }
+
+    fn check(&self) -> Result<(), LoggerError> {
+        if let Some(level) = &self.level {
+            LevelFilter::parse(level).map_err(LoggerError::InvalidLevelFilter)?;
+        }
+        Ok(())
+    }
+
+    /// Stores the settings read by the logger thread on `LogMessage::SetConfig`, and the level filter
+    fn apply_reloadable(&self) -> Result<(), LoggerError> {
+        self.check()?;
+        if let Some(level) = &self.level {
+            set_filter(level).map_err(LoggerError::InvalidLevelFilter)?;
+        }
+        if let Some(size) = self.buffer_size {
+            LOG_MESSAGE_BUFFER_SIZE.store(size, Ordering::Relaxed);
+        }
+        if let Some(interval) = self.flush_interval {
+            LOG_MESSAGE_FLUSH_INTERVAL.store(interval, Ordering::Relaxed);
+        }
+        if let Some(console) = self.console {
+            CONSOLE_REPORT.store(console, Ordering::Relaxed);
+        }
+        if let Some(format) = self.format {
+            let formatter = format.formatter();
+            *FORMATTER.lock().expect("Failed to lock FORMATTER") = formatter.clone();
+            *CONSOLE_FORMATTER.lock().expect("Failed to lock CONSOLE_FORMATTER") = formatter;
+        }
+        if let Some(format) = self.timestamp_format {
+            set_timestamp_format(format);
+        }
+        if let Some(format) = self.console_format {
+            *CONSOLE_FORMATTER.lock().expect("Failed to lock CONSOLE_FORMATTER") = format.formatter();
+        }
+        Ok(())
+    }
+}
+
+impl TopicFileConfig {
+    fn rolling_config(&self) -> RollingConfig {
+        let defaults = RollingConfig::default();
+        RollingConfig {
+            base_path: PathBuf::from(&self.path),
+            file_name_prefix: self.prefix.clone(),
+            roll_period: Some(self.roll_period.clone().unwrap_or(RollingPeriod::Daily)),
+            max_roll_files: Some(self.max_roll_files.unwrap_or(10)),
+            max_file_size: self.max_file_size,
+            max_age: self.max_age_secs.map(Duration::from_secs),
+            max_total_size: self.max_total_size,
+            compress: self.compress.unwrap_or(defaults.compress),
+            compression: self.compression.unwrap_or(defaults.compression),
+            file_name_pattern: self.file_name_pattern.clone().unwrap_or(defaults.file_name_pattern),
+            current_link: self.current_link.unwrap_or(defaults.current_link),
+            ..defaults
+        }
+    }
+}
+
+impl SinkEntry {
+    fn sink_config(&self) -> Result<SinkConfig, LoggerError> {
+        let mut config = match self.sink_type {
+            SinkType::Stdout => SinkConfig::new(StdoutSink),
+            SinkType::Stderr => SinkConfig::new(StderrSink),
+            SinkType::File => {
+                let (Some(path), Some(prefix)) = (&self.path, &self.prefix) else {
+                    return Err(LoggerError::InvalidConfig("A file sink needs a path and a prefix".to_string()));
+                };
+                SinkConfig::new(FileSink::new(path, prefix).map_err(LoggerError::Io)?)
+            }
+        };
+        if let Some(level) = self.level {
+            config = config.with_level(level);
+        }
+        if let Some(format) = self.format {
+            config = config.with_formatter(format.formatter());
+        }
+        if let Some(flush_policy) = self.flush_policy {
+            config = config.with_flush_policy(flush_policy);
+        }
+        Ok(config)
+    }
+}
+
+fn logger_running() -> bool {
+    LOGGER_HANDLER
+        .lock()
+        .expect("Failed to lock LOGGER_HANDLER")
+        .as_ref()
+        .is_some_and(|handler| !handler.is_finished())
+}
+
+/// Rereads `path` every `interval` while the logger thread runs, and applies the reloadable settings when the
+/// content changes. Each reload is logged as a `flashlog` record.
+pub(crate) fn watch(path: PathBuf, interval: Duration) {
+    // read before spawning, so that a change made right after the launch is not taken as the initial content
+    let mut last = std::fs::read(&path).ok();
+    thread::Builder::new()
+        .name("flashlog-config-watch".to_string())
+        .spawn(move || {
+            loop {
+                thread::sleep(interval);
+                if !logger_running() {
+                    break;
+                }
+                // the file may be missing for a moment while an editor replaces it
+                let Ok(content) = std::fs::read(&path) else {
+                    continue;
+                };
+                if last.as_ref() == Some(&content) {
+                    continue;
+                }
+                last = Some(content);
+                let result = LoggerConfig::from_file(&path).and_then(|config| config.apply_reloadable());
+                if result.is_ok() {
+                    let _ = LOG_SENDER.send(LogMessage::SetConfig);
+                }
+                report(&path, result);
+            }
+        })
+        .expect("Failed to spawn the config watch thread");
+}
+
+fn report(path: &Path, result: Result<(), LoggerError>) {
+    let file = path.display().to_string();
+    let message = match result {
+        Ok(()) => LazyMessage::record(LogLevel::Info.as_usize(), move || {
+            internal_record(LogLevel::Info, "config reloaded", vec![("file", serde_json::json!(file))])
+        }),
+        Err(e) => {
+            let error = e.to_string();
+            LazyMessage::record(LogLevel::Error.as_usize(), move || {
+                internal_record(
+                    LogLevel::Error,
+                    "failed to reload config",
+                    vec![("file", serde_json::json!(file)), ("error", serde_json::json!(error))],
+                )
+            })
+        }
+    };
+    let _ = LOG_SENDER.send(LogMessage::LazyMessage(message));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toml_and_json_config() {
+        let toml = r#"
+            level = "orders=debug,*=info"
+            console = false
+            format = "logfmt"
+            buffer_size = 100
+            flush_interval = 1_000_000_000
+            timezone = "new_york"
+            timestamp_format = "rfc3339:3"
+
+            [file]
+            path = "logs"
+            prefix = "message"
+            roll_period = "hourly"
+            max_age_secs = 86400
+            compression = "gzip"
+
+            [[topic_files]]
+            topic = "orders"
+            path = "logs"
+            prefix = "orders"
+            roll_period = "hourly"
+            max_file_size = 1_000_000
+
+            [[topic_files]]
+            topic = "md"
+            path = "logs"
+            prefix = "md"
+
+            [[sinks]]
+            type = "stderr"
+            level = "error"
+            flush_policy = "immediate"
+
+            [overflow_policy]
+            drop_below_level = "warn"
+
+            [error_policy.retry]
+            attempts = 3
+            backoff_ms = 10
+        "#;
+        let config = LoggerConfig::from_toml(toml).unwrap();
+        assert_eq!(config.level.as_deref(), Some("orders=debug,*=info"));
+        assert_eq!(config.format, Some(FormatterKind::Logfmt));
+        assert_eq!(config.timezone, Some(TimeZone::NewYork));
+        assert_eq!(config.timestamp_format, Some(TimestampFormat::Rfc3339 { digits: 3 }));
+        let file = config.file.as_ref().unwrap();
+        assert_eq!(file.roll_period, Some(RollingPeriod::Hourly));
+        assert_eq!(file.max_age_secs, Some(86400));
+        assert_eq!(config.topic_files[0].topic, "orders");
+        let orders = config.topic_files[0].rolling_config();
+        assert_eq!((orders.roll_period, orders.max_file_size), (Some(RollingPeriod::Hourly), Some(1_000_000)));
+        // like `Logger::with_topic_file`
+        let md = config.topic_files[1].rolling_config();
+        assert_eq!((md.roll_period, md.max_roll_files), (Some(RollingPeriod::Daily), Some(10)));
+        assert_eq!(config.overflow_policy, Some(OverflowPolicy::DropBelowLevel(LogLevel::Warn)));
+        let backoff = Duration::from_millis(10);
+        assert_eq!(config.error_policy, Some(ErrorPolicy::Retry { attempts: 3, backoff }));
+        assert_eq!(config.sinks[0].sink_type, SinkType::Stderr);
+        assert_eq!(config.sinks[0].level, Some(LogLevel::Error));
+        assert_eq!(config.sinks[0].flush_policy, Some(FlushPolicy::Immediate));
+
+        let json = r#"{"level": "warn", "file": {"path": "logs", "prefix": "message"}, "core": -1}"#;
+        let config = LoggerConfig::from_json(json).unwrap();
+        assert_eq!(config.level.as_deref(), Some("warn"));
+        assert_eq!(config.core, Some(-1));
+        assert_eq!(config.file.unwrap().roll_period, None);
+        let config = LoggerConfig::from_json(r#"{"callsite_timestamp": true, "queue_latency": false}"#).unwrap();
+        assert_eq!((config.callsite_timestamp, config.queue_latency), (Some(true), Some(false)));
+        let config = LoggerConfig::from_json(r#"{"overflow_policy": "drop_oldest", "error_policy": "drop"}"#).unwrap();
+        assert_eq!((config.overflow_policy, config.error_policy), (Some(OverflowPolicy::DropOldest), Some(ErrorPolicy::Drop)));
+
+        assert!(matches!(LoggerConfig::from_toml("buffer = 10"), Err(LoggerError::InvalidConfig(_))));
+        assert!(matches!(LoggerConfig::from_toml("level = 3"), Err(LoggerError::InvalidConfig(_))));
+        let invalid = LoggerConfig::from_toml("timestamp_format = \"iso\"");
+        assert!(matches!(invalid, Err(LoggerError::InvalidConfig(_))));
+        assert_eq!(LoggerConfig::from_toml("").unwrap(), LoggerConfig::default());
+    }
+}
@@ -0,0 +1,121 @@
+//! Handling of I/O errors in the logger thread.
+//!
+//! A failed write, flush or file open never stops the logger thread. The error is counted, passed to the callback
+//! set by `Logger::with_error_callback` and handled by the [`ErrorPolicy`]. `Logger::health()` reports the counts.
+use crate::logger::LOGGER_HANDLER;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Deserializer};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// What the logger thread does when writing to a log file or sink fails
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorPolicy {
+    /// Retries up to `attempts` more times, `backoff` apart, then drops the lines. The logger thread waits meanwhile.
+    /// In configuration files the backoff is given in milliseconds as `backoff_ms`.
+    Retry {
+        attempts: u32,
+        #[serde(rename = "backoff_ms", deserialize_with = "deserialize_millis")]
+        backoff: Duration,
+    },
+    /// Writes the lines to stderr instead (default)
+    #[default]
+    FallbackToStderr,
+    /// Drops the lines
+    Drop,
+}
+
+fn deserialize_millis<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_millis)
+}
+
+/// Called in the logger thread with the destination (`"log file"`, `"topic file"`, `"binary file"`, `"console"`
+/// or `"sink"`) and the error. It should return quickly, as the logger thread waits for it.
+pub type ErrorCallback = Arc<dyn Fn(&str, &io::Error) + Send + Sync>;
+
+pub(crate) static ERROR_POLICY: Lazy<Mutex<ErrorPolicy>> = Lazy::new(|| Mutex::new(ErrorPolicy::default()));
+pub(crate) static ERROR_CALLBACK: Lazy<Mutex<Option<ErrorCallback>>> = Lazy::new(|| Mutex::new(None));
+
+static IO_ERRORS: AtomicU64 = AtomicU64::new(0);
+static LOST_LINES: AtomicU64 = AtomicU64::new(0);
+static LAST_ERROR: Mutex<Option<String>> = Mutex::new(None);
+
+/// State of the logger thread, returned by `Logger::health()`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoggerHealth {
+    /// Whether the logger thread is running
+    pub running: bool,
+    /// Failed writes, flushes and file opens, retries included
+    pub io_errors: u64,
+    /// Lines that could not be written anywhere
+    pub lost_lines: u64,
+    /// Destination and message of the latest error
+    pub last_error: Option<String>,
+}
+
+pub(crate) fn health() -> LoggerHealth {
+    let running = LOGGER_HANDLER
+        .lock()
+        .expect("Failed to lock LOGGER_HANDLER")
+        .as_ref()
+        .is_some_and(|handler| !handler.is_finished());
+    LoggerHealth {
+        running,
+        io_errors: IO_ERRORS.load(Ordering::Relaxed),
+        lost_lines: LOST_LINES.load(Ordering::Relaxed),
+        last_error: LAST_ERROR.lock().expect("Failed to lock LAST_ERROR").clone(),
+    }
+}
+
+fn policy() -> ErrorPolicy {
+    *ERROR_POLICY.lock().expect("Failed to lock ERROR_POLICY")
+}
+
+/// Counts the error and passes it to the callback
+pub(crate) fn report(destination: &str, error: &io::Error) {
+    IO_ERRORS.fetch_add(1, Ordering::Relaxed);
+    *LAST_ERROR.lock().expect("Failed to lock LAST_ERROR") = Some(format!("{}: {}", destination, error));
+    let callback = ERROR_CALLBACK.lock().expect("Failed to lock ERROR_CALLBACK").clone();
+    if let Some(callback) = callback {
+        callback(destination, error);
+    }
+}
+
+/// Runs `operation`, again as long as `ErrorPolicy::Retry` allows. Returns `None` if it still fails.
+pub(crate) fn attempt<T>(destination: &str, mut operation: impl FnMut() -> io::Result<T>) -> Option<T> {
+    let (mut retries, backoff) = match policy() {
+        ErrorPolicy::Retry { attempts, backoff } => (attempts, backoff),
+        _ => (0, Duration::ZERO),
+    };
+    loop {
+        match operation() {
+            Ok(value) => return Some(value),
+            Err(e) => {
+                report(destination, &e);
+                if retries == 0 {
+                    return None;
+                }
+                retries -= 1;
+                thread::sleep(backoff);
+            }
+        }
+    }
+}
+
+/// Handles `lines` lines in `buf` that could not be written: written to stderr with `ErrorPolicy::FallbackToStderr`,
+/// counted as lost otherwise
+pub(crate) fn fall_back(buf: &[u8], lines: usize) {
+    if policy() == ErrorPolicy::FallbackToStderr && io::stderr().lock().write_all(buf).is_ok() {
+        return;
+    }
+    LOST_LINES.fetch_add(lines as u64, Ordering::Relaxed);
+}
+
+/// Whether a file that cannot be opened is replaced by stderr
+pub(crate) fn falls_back_to_stderr() -> bool {
+    policy() == ErrorPolicy::FallbackToStderr
+}
@@ -0,0 +1,618 @@
+//! Output formatting.
+//!
+//! Log macros describe each message as a structured [`Record`] (level, topic, message, key/value data, callsite
+//! and timestamp) and the logger thread turns it into a line with the [`Formatter`] set by `Logger::with_formatter`.
+//! [`JsonFormatter`] is the default and keeps the layout of the previous releases.
+//! The console can use its own formatter, e.g. [`PrettyFormatter`] while the file stays JSON.
+use crate::compile_time::usize_to_level;
+use crate::logger::INCLUDE_UNIXNANO;
+use crate::timer::{write_date, write_rfc3339, write_time, write_time_millis};
+use crate::timezone::{offset_in_hours, utc_offset_at};
+use once_cell::sync::Lazy;
+use std::fmt::Write;
+use std::io::IsTerminal;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+
+static DEFAULT_FORMATTER: Lazy<Arc<dyn Formatter>> = Lazy::new(|| Arc::new(JsonFormatter::new()));
+/// Formatter of the log file. It is read when the logger thread (re)loads its configuration.
+pub static FORMATTER: Lazy<Mutex<Arc<dyn Formatter>>> = Lazy::new(|| Mutex::new(DEFAULT_FORMATTER.clone()));
+/// Formatter of the console. While it is the same `Arc` as `FORMATTER`, each line is formatted once for both.
+pub static CONSOLE_FORMATTER: Lazy<Mutex<Arc<dyn Formatter>>> = Lazy::new(|| Mutex::new(DEFAULT_FORMATTER.clone()));
+
+/// A log message as seen by a [`Formatter`]
+#[derive(Debug, Clone, Copy)]
+pub struct Record<'a> {
+    pub level: usize,
+    pub topic: &'a str,
+    pub message: &'a str,
+    /// Key/value pairs in the order they were given. `Some` with no pairs marks a formatted message without
+    /// key/value pairs, which the JSON layout reports as `"data": ""`. `None` omits the data field.
+    pub data: Option<&'a [(&'a str, serde_json::Value)]>,
+    pub file: &'a str,
+    /// 0 if the record does not come from a source line
+    pub line: u32,
+    pub module_path: &'a str,
+    pub unixnano: u64,
+    /// Offset from UTC in seconds at `unixnano`
+    pub utc_offset: i32,
+    /// Nanoseconds between the log call and its evaluation by the logger thread, see `Logger::with_queue_latency`
+    pub queue_latency: Option<u64>,
+}
+
+impl Record<'_> {
+    /// `file:line`, or only `file` if the line is unknown
+    pub fn src(&self) -> String {
+        match self.line {
+            0 => self.file.to_string(),
+            line => format!("{}:{}", self.file, line),
+        }
+    }
+}
+
+/// Owned record built by the log macros in the logger thread.
+/// The offset of the timezone is attached when it is formatted.
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    pub level: usize,
+    pub topic: String,
+    pub message: String,
+    pub data: Option<Vec<(&'static str, serde_json::Value)>>,
+    pub file: &'static str,
+    pub line: u32,
+    pub module_path: &'static str,
+    pub unixnano: u64,
+    pub queue_latency: Option<u64>,
+}
+
+impl LogRecord {
+    /// The record with the offset of the logger's timezone at its timestamp
+    pub fn as_record(&self) -> Record<'_> {
+        Record {
+            level: self.level,
+            topic: &self.topic,
+            message: &self.message,
+            data: self.data.as_deref(),
+            file: self.file,
+            line: self.line,
+            module_path: self.module_path,
+            unixnano: self.unixnano,
+            utc_offset: utc_offset_at(self.unixnano),
+            queue_latency: self.queue_latency,
+        }
+    }
+}
+
+/// Turns a record into one line of output, including the trailing newline
+pub trait Formatter: Send + Sync {
+    fn format(&self, record: &Record<'_>, buf: &mut String);
+}
+
+impl<F: Formatter + ?Sized> Formatter for Arc<F> {
+    fn format(&self, record: &Record<'_>, buf: &mut String) {
+        (**self).format(record, buf)
+    }
+}
+
+impl<F: Formatter + ?Sized> Formatter for Box<F> {
+    fn format(&self, record: &Record<'_>, buf: &mut String) {
+        (**self).format(record, buf)
+    }
+}
+
+/// Formats `record` into a new string
+pub fn format_to_string(formatter: &dyn Formatter, record: &Record<'_>) -> String {
+    let mut buf = String::with_capacity(128 + record.message.len());
+    formatter.format(record, &mut buf);
+    buf
+}
+
+#[inline]
+fn include_unixnano(setting: Option<bool>) -> bool {
+    setting.unwrap_or_else(|| INCLUDE_UNIXNANO.load(Ordering::Relaxed))
+}
+
+/// How `JsonFormatter`, `TextFormatter` and `LogfmtFormatter` write the timestamp of a record, set by
+/// `Logger::with_timestamp_format`. `PrettyFormatter` always writes `HH:MM:SS.mmm`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum TimestampFormat {
+    /// `date` (`20240915`), `time` (`20:34:30.684:921:877`) and `offset` (hours) fields
+    #[default]
+    Legacy,
+    /// One `timestamp` field in RFC 3339 (a profile of ISO 8601) with `digits` fractional digits, at most 9,
+    /// e.g. `2024-09-15T20:34:30.684+09:00`. UTC is written as `Z`.
+    Rfc3339 { digits: u8 },
+    /// One `timestamp` field with the milliseconds since the Unix epoch
+    EpochMillis,
+    EpochMicros,
+    EpochNanos,
+}
+
+impl TimestampFormat {
+    fn as_u8(&self) -> u8 {
+        match self {
+            TimestampFormat::Legacy => 0,
+            TimestampFormat::EpochMillis => 1,
+            TimestampFormat::EpochMicros => 2,
+            TimestampFormat::EpochNanos => 3,
+            TimestampFormat::Rfc3339 { digits } => 16 + (*digits).min(9),
+        }
+    }
+
+    fn from_u8(format: u8) -> TimestampFormat {
+        match format {
+            1 => TimestampFormat::EpochMillis,
+            2 => TimestampFormat::EpochMicros,
+            3 => TimestampFormat::EpochNanos,
+            16.. => TimestampFormat::Rfc3339 { digits: format - 16 },
+            _ => TimestampFormat::Legacy,
+        }
+    }
+
+    /// Writes the value of the `timestamp` field, quoted if `quote` and the value is a string.
+    /// Nothing is written for `Legacy`.
+    fn write_timestamp(&self, buf: &mut String, record: &Record<'_>, quote: bool) {
+        match self {
+            TimestampFormat::Legacy => {}
+            TimestampFormat::Rfc3339 { digits } => {
+                if quote {
+                    buf.push('"');
+                }
+                write_rfc3339(buf, record.unixnano, record.utc_offset, *digits);
+                if quote {
+                    buf.push('"');
+                }
+            }
+            TimestampFormat::EpochMillis => {
+                let _ = write!(buf, "{}", record.unixnano / 1_000_000);
+            }
+            TimestampFormat::EpochMicros => {
+                let _ = write!(buf, "{}", record.unixnano / 1_000);
+            }
+            TimestampFormat::EpochNanos => {
+                let _ = write!(buf, "{}", record.unixnano);
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for TimestampFormat {
+    type Err = String;
+
+    /// Parses `legacy`, `rfc3339` (9 fractional digits), `rfc3339:N`, `epoch_millis`, `epoch_micros` or `epoch_nanos`
+    fn from_str(format: &str) -> Result<TimestampFormat, String> {
+        let invalid = || format!("Invalid timestamp format {:?}", format);
+        match format.to_ascii_lowercase().as_str() {
+            "legacy" => Ok(TimestampFormat::Legacy),
+            "rfc3339" => Ok(TimestampFormat::Rfc3339 { digits: 9 }),
+            "epoch_millis" => Ok(TimestampFormat::EpochMillis),
+            "epoch_micros" => Ok(TimestampFormat::EpochMicros),
+            "epoch_nanos" => Ok(TimestampFormat::EpochNanos),
+            other => match other.strip_prefix("rfc3339:").map(str::parse::<u8>) {
+                Some(Ok(digits)) if digits <= 9 => Ok(TimestampFormat::Rfc3339 { digits }),
+                _ => Err(invalid()),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for TimestampFormat {
+    type Error = String;
+
+    fn try_from(format: String) -> Result<TimestampFormat, String> {
+        format.parse()
+    }
+}
+
+static TIMESTAMP_FORMAT: AtomicU8 = AtomicU8::new(0);
+
+/// Sets the timestamp format of the built-in formatters. Can be called at any time.
+pub fn set_timestamp_format(format: TimestampFormat) {
+    TIMESTAMP_FORMAT.store(format.as_u8(), Ordering::Relaxed);
+}
+
+pub fn get_timestamp_format() -> TimestampFormat {
+    TimestampFormat::from_u8(TIMESTAMP_FORMAT.load(Ordering::Relaxed))
+}
+
+/// `{"data":..,"date":..,"level":..,"message":..,"offset":..,"src":..,"time":..,"topic":..,"unixnano":..}`.
+/// Other timestamp formats replace `date`, `offset` and `time` with `timestamp`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonFormatter {
+    include_unixnano: Option<bool>,
+}
+
+impl JsonFormatter {
+    /// Includes `unixnano` if the logger is set up with `include_unixnano(true)`
+    pub fn new() -> JsonFormatter {
+        JsonFormatter { include_unixnano: None }
+    }
+
+    /// Includes `unixnano` regardless of the logger setting
+    pub fn with_unixnano(include_unixnano: bool) -> JsonFormatter {
+        JsonFormatter { include_unixnano: Some(include_unixnano) }
+    }
+}
+
+impl Formatter for JsonFormatter {
+    fn format(&self, record: &Record<'_>, buf: &mut String) {
+        // keys in the order of a serialized `serde_json::Map`
+        buf.push('{');
+        match record.data {
+            Some([]) => buf.push_str("\"data\":\"\","),
+            Some(data) => {
+                let data: serde_json::Map<String, serde_json::Value> = data
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.clone()))
+                    .collect();
+                buf.push_str("\"data\":");
+                let _ = serde_json::to_writer(StringWriter(buf), &data);
+                buf.push(',');
+            }
+            None => {}
+        }
+        let timestamp_format = get_timestamp_format();
+        let legacy = timestamp_format == TimestampFormat::Legacy;
+        if legacy {
+            buf.push_str("\"date\":\"");
+            write_date(buf, record.unixnano, record.utc_offset);
+            buf.push_str("\",");
+        }
+        buf.push_str("\"level\":");
+        write_json_str(buf, usize_to_level(record.level));
+        buf.push_str(",\"message\":");
+        write_json_str(buf, record.message);
+        if legacy {
+            let _ = write!(buf, ",\"offset\":{}", offset_in_hours(record.utc_offset));
+        }
+        if let Some(queue_latency) = record.queue_latency {
+            let _ = write!(buf, ",\"queue_latency_ns\":{}", queue_latency);
+        }
+        buf.push_str(",\"src\":");
+        write_json_str(buf, record.file);
+        if record.line != 0 {
+            // `file:line` inside the quotes of `file`
+            buf.pop();
+            let _ = write!(buf, ":{}\"", record.line);
+        }
+        if legacy {
+            buf.push_str(",\"time\":\"");
+            write_time(buf, record.unixnano, record.utc_offset);
+            buf.push('"');
+        } else {
+            buf.push_str(",\"timestamp\":");
+            timestamp_format.write_timestamp(buf, record, true);
+        }
+        buf.push_str(",\"topic\":");
+        write_json_str(buf, record.topic);
+        if include_unixnano(self.include_unixnano) {
+            let _ = write!(buf, ",\"unixnano\":{}", record.unixnano);
+        }
+        buf.push_str("}\n");
+    }
+}
+
+/// Appends `value` as a JSON string, escaped as `serde_json` does
+fn write_json_str(buf: &mut String, value: &str) {
+    let _ = serde_json::to_writer(StringWriter(buf), value);
+}
+
+/// Lets `serde_json` serialize into a `String`
+struct StringWriter<'a>(&'a mut String);
+
+impl std::io::Write for StringWriter<'_> {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        let s = std::str::from_utf8(bytes).map_err(std::io::Error::other)?;
+        self.0.push_str(s);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Human-readable text: `20240915 20:34:30.684:921:877 INFO  [orders] filled venue=KRX qty=7 (src/main.rs:10)`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TextFormatter;
+
+impl TextFormatter {
+    pub fn new() -> TextFormatter {
+        TextFormatter
+    }
+}
+
+impl Formatter for TextFormatter {
+    fn format(&self, record: &Record<'_>, buf: &mut String) {
+        match get_timestamp_format() {
+            TimestampFormat::Legacy => {
+                write_date(buf, record.unixnano, record.utc_offset);
+                buf.push(' ');
+                write_time(buf, record.unixnano, record.utc_offset);
+            }
+            format => format.write_timestamp(buf, record, false),
+        }
+        let _ = write!(buf, " {:<5}", usize_to_level(record.level).to_uppercase());
+        if !record.topic.is_empty() {
+            let _ = write!(buf, " [{}]", record.topic);
+        }
+        if !record.message.is_empty() {
+            buf.push(' ');
+            buf.push_str(record.message);
+        }
+        for (key, value) in record.data.unwrap_or_default() {
+            let _ = write!(buf, " {}=", key);
+            write_text_value(buf, value);
+        }
+        if let Some(queue_latency) = record.queue_latency {
+            let _ = write!(buf, " queue_latency_ns={}", queue_latency);
+        }
+        let _ = writeln!(buf, " ({})", record.src());
+    }
+}
+
+/// Strings are written as they are, other values as JSON
+fn write_text_value(buf: &mut String, value: &serde_json::Value) {
+    match value {
+        serde_json::Value::String(s) => buf.push_str(s),
+        value => {
+            let _ = write!(buf, "{}", value);
+        }
+    }
+}
+
+/// [logfmt](https://brandur.org/logfmt):
+/// `date=20240915 time=20:34:30.684:921:877 offset=9 level=info src=src/main.rs:10 topic=orders msg=filled qty=7`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogfmtFormatter {
+    include_unixnano: Option<bool>,
+}
+
+impl LogfmtFormatter {
+    /// Includes `unixnano` if the logger is set up with `include_unixnano(true)`
+    pub fn new() -> LogfmtFormatter {
+        LogfmtFormatter { include_unixnano: None }
+    }
+
+    /// Includes `unixnano` regardless of the logger setting
+    pub fn with_unixnano(include_unixnano: bool) -> LogfmtFormatter {
+        LogfmtFormatter { include_unixnano: Some(include_unixnano) }
+    }
+}
+
+impl Formatter for LogfmtFormatter {
+    fn format(&self, record: &Record<'_>, buf: &mut String) {
+        match get_timestamp_format() {
+            TimestampFormat::Legacy => {
+                buf.push_str("date=");
+                write_date(buf, record.unixnano, record.utc_offset);
+                buf.push_str(" time=");
+                write_time(buf, record.unixnano, record.utc_offset);
+                let _ = write!(buf, " offset={}", offset_in_hours(record.utc_offset));
+            }
+            format => {
+                buf.push_str("timestamp=");
+                format.write_timestamp(buf, record, false);
+            }
+        }
+        let _ = write!(buf, " level={} src=", usize_to_level(record.level).to_lowercase());
+        write_logfmt_str(buf, &record.src());
+        buf.push_str(" topic=");
+        write_logfmt_str(buf, record.topic);
+        buf.push_str(" msg=");
+        write_logfmt_str(buf, record.message);
+        for (key, value) in record.data.unwrap_or_default() {
+            let _ = write!(buf, " {}=", key);
+            match value {
+                serde_json::Value::String(s) => write_logfmt_str(buf, s),
+                value => write_logfmt_str(buf, &value.to_string()),
+            }
+        }
+        if let Some(queue_latency) = record.queue_latency {
+            let _ = write!(buf, " queue_latency_ns={}", queue_latency);
+        }
+        if include_unixnano(self.include_unixnano) {
+            let _ = write!(buf, " unixnano={}", record.unixnano);
+        }
+        buf.push('\n');
+    }
+}
+
+/// Console layout for local debugging: `20:34:30.684 INFO  orders: filled qty=7 venue=KRX`, with the level colored
+#[derive(Debug, Clone, Copy)]
+pub struct PrettyFormatter {
+    color: bool,
+}
+
+impl Default for PrettyFormatter {
+    fn default() -> Self {
+        PrettyFormatter::new()
+    }
+}
+
+impl PrettyFormatter {
+    /// Colors the output if stdout is a terminal and `NO_COLOR` is not set
+    pub fn new() -> PrettyFormatter {
+        let color = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        PrettyFormatter { color }
+    }
+
+    pub fn with_color(color: bool) -> PrettyFormatter {
+        PrettyFormatter { color }
+    }
+}
+
+impl Formatter for PrettyFormatter {
+    fn format(&self, record: &Record<'_>, buf: &mut String) {
+        write_time_millis(buf, record.unixnano, record.utc_offset);
+        buf.push(' ');
+
+        let level = usize_to_level(record.level).to_uppercase();
+        if self.color {
+            let color = match record.level {
+                1 => "\x1b[31m",
+                2 => "\x1b[33m",
+                3 => "\x1b[32m",
+                4 => "\x1b[34m",
+                _ => "\x1b[35m",
+            };
+            let _ = write!(buf, "{}{:<5}\x1b[0m", color, level);
+        } else {
+            let _ = write!(buf, "{:<5}", level);
+        }
+
+        if !record.topic.is_empty() {
+            if self.color {
+                let _ = write!(buf, " \x1b[1m{}\x1b[0m:", record.topic);
+            } else {
+                let _ = write!(buf, " {}:", record.topic);
+            }
+        }
+        if !record.message.is_empty() {
+            buf.push(' ');
+            buf.push_str(record.message);
+        }
+        for (key, value) in record.data.unwrap_or_default() {
+            if self.color {
+                let _ = write!(buf, " \x1b[2m{}=\x1b[0m", key);
+            } else {
+                let _ = write!(buf, " {}=", key);
+            }
+            write_text_value(buf, value);
+        }
+        buf.push('\n');
+    }
+}
+
+/// Writes `value` bare if it is safe to, otherwise quoted with `"` and `\` escaped
+fn write_logfmt_str(buf: &mut String, value: &str) {
+    let bare = !value.is_empty()
+        && value.chars().all(|c| c > ' ' && c != '"' && c != '=' && c != '\\' && !c.is_control());
+    if bare {
+        buf.push_str(value);
+        return;
+    }
+
+    buf.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record<'a>(data: Option<&'a [(&'a str, serde_json::Value)]>) -> Record<'a> {
+        Record {
+            level: 3,
+            topic: "orders",
+            message: "px 101.25 qty 7",
+            data,
+            file: "src/main.rs",
+            line: 10,
+            module_path: "main",
+            unixnano: 1_726_400_070_684_921_877,
+            utc_offset: 9 * 3600,
+            queue_latency: None,
+        }
+    }
+
+    #[test]
+    fn test_json_layout() {
+        let data = [("venue", serde_json::json!("KRX")), ("filled", serde_json::json!(true))];
+        let line = format_to_string(&JsonFormatter::with_unixnano(true), &record(Some(&data)));
+        assert_eq!(
+            line,
+            "{\"data\":{\"filled\":true,\"venue\":\"KRX\"},\"date\":\"20240915\",\"level\":\"Info\",\"message\":\"px 101.25 qty 7\",\
+             \"offset\":9,\"src\":\"src/main.rs:10\",\"time\":\"20:34:30.684:921:877\",\"topic\":\"orders\",\"unixnano\":1726400070684921877}\n"
+        );
+
+        let line = format_to_string(&JsonFormatter::with_unixnano(false), &record(Some(&[])));
+        assert!(line.starts_with("{\"data\":\"\",\"date\""));
+        let line = format_to_string(&JsonFormatter::with_unixnano(false), &record(None));
+        assert!(line.starts_with("{\"date\""));
+        assert!(!line.contains("unixnano"));
+
+        // escaped as serde_json does
+        let mut escaped = record(None);
+        escaped.message = "say \"hi\"\n\t\u{1}\\ 안녕";
+        escaped.file = "src\\main.rs";
+        let line = format_to_string(&JsonFormatter::with_unixnano(false), &escaped);
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["message"], escaped.message);
+        assert_eq!(value["src"], "src\\main.rs:10");
+        assert_eq!(line.trim_end(), value.to_string());
+
+        let mut queued = record(None);
+        queued.queue_latency = Some(1_250);
+        let line = format_to_string(&JsonFormatter::with_unixnano(false), &queued);
+        assert!(line.contains("\"offset\":9,\"queue_latency_ns\":1250,\"src\""));
+        let line = format_to_string(&LogfmtFormatter::with_unixnano(false), &queued);
+        assert!(line.ends_with(" queue_latency_ns=1250\n"));
+    }
+
+    #[test]
+    fn test_timestamp_format() {
+        // the global format is left alone, as the other tests run in parallel
+        let timestamp = |format: TimestampFormat| {
+            let mut buf = String::new();
+            format.write_timestamp(&mut buf, &record(None), true);
+            buf
+        };
+        assert_eq!(timestamp(TimestampFormat::Legacy), "");
+        assert_eq!(timestamp(TimestampFormat::Rfc3339 { digits: 3 }), "\"2024-09-15T20:34:30.684+09:00\"");
+        assert_eq!(timestamp(TimestampFormat::EpochMillis), "1726400070684");
+        assert_eq!(timestamp(TimestampFormat::EpochMicros), "1726400070684921");
+        assert_eq!(timestamp(TimestampFormat::EpochNanos), "1726400070684921877");
+
+        assert_eq!("RFC3339".parse(), Ok(TimestampFormat::Rfc3339 { digits: 9 }));
+        assert_eq!("rfc3339:0".parse(), Ok(TimestampFormat::Rfc3339 { digits: 0 }));
+        assert_eq!("epoch_micros".parse(), Ok(TimestampFormat::EpochMicros));
+        assert!("rfc3339:10".parse::<TimestampFormat>().is_err());
+        assert!("iso".parse::<TimestampFormat>().is_err());
+        for digits in 0..=9 {
+            let format = TimestampFormat::Rfc3339 { digits };
+            assert_eq!(TimestampFormat::from_u8(format.as_u8()), format);
+        }
+        assert_eq!(TimestampFormat::from_u8(TimestampFormat::EpochNanos.as_u8()), TimestampFormat::EpochNanos);
+    }
+
+    #[test]
+    fn test_text_layout() {
+        let data = [("venue", serde_json::json!("KRX")), ("qty", serde_json::json!(7))];
+        let line = format_to_string(&TextFormatter, &record(Some(&data)));
+        assert_eq!(line, "20240915 20:34:30.684:921:877 INFO  [orders] px 101.25 qty 7 venue=KRX qty=7 (src/main.rs:10)\n");
+    }
+
+    #[test]
+    fn test_pretty_layout() {
+        let data = [("venue", serde_json::json!("KRX"))];
+        let line = format_to_string(&PrettyFormatter::with_color(false), &record(Some(&data)));
+        assert_eq!(line, "20:34:30.684 INFO  orders: px 101.25 qty 7 venue=KRX\n");
+
+        let line = format_to_string(&PrettyFormatter::with_color(true), &record(None));
+        assert_eq!(line, "20:34:30.684 \x1b[32mINFO \x1b[0m \x1b[1morders\x1b[0m: px 101.25 qty 7\n");
+    }
+
+    #[test]
+    fn test_logfmt_layout() {
+        let data = [("venue", serde_json::json!("K R X")), ("qty", serde_json::json!(7)), ("note", serde_json::json!("a\"b"))];
+        let line = format_to_string(&LogfmtFormatter::with_unixnano(false), &record(Some(&data)));
+        assert_eq!(
+            line,
+            "date=20240915 time=20:34:30.684:921:877 offset=9 level=info src=src/main.rs:10 topic=orders \
+             msg=\"px 101.25 qty 7\" venue=\"K R X\" qty=7 note=\"a\\\"b\"\n"
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! Per-topic and per-module log levels.
+//!
+//! A filter is a comma separated list of directives such as `orders=debug,md=warn,*=info`. A directive applies to
+//! the records whose topic equals its target, or whose module path is its target or lies under it (`my_app::md`
+//! also matches `my_app::md::book`). The topic takes precedence over the module path, and a longer module path over
+//! a shorter one. `*` (or a bare level) sets the level of everything else, like `set_level`.
+//!
+//! Each `flash_xxx_ct!` and `flash_xxx_bin!` callsite resolves its level once and caches it, so that checking it
+//! stays a single atomic load. The cached levels are resolved again when the filter or the level changes.
+//!
+//! As the level is resolved without evaluating the topic, topic directives only match `flash_xxx_ct!` topics written
+//! as string literals. A topic passed as a `const`, a variable or any other expression is matched by module path
+//! directives and `*` only.
+use crate::compile_time::{self, MAX_LEVEL};
+use crate::logger::LogLevel;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// A callsite whose level is not resolved yet
+const UNRESOLVED: usize = usize::MAX;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Directive {
+    target: String,
+    level: LogLevel,
+}
+
+/// Levels by topic and by module path, parsed from directives like `orders=debug,md=warn,*=info`.
+/// For `flash_xxx_ct!`, topic directives only match topics written as string literals, e.g. `flash_info_ct!("orders"; ...)`
+/// but not `flash_info_ct!(ORDERS; ...)` with `const ORDERS: &str = "orders"`; see the module documentation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LevelFilter {
+    directives: Vec<Directive>,
+    default: Option<LogLevel>,
+}
+
+impl LevelFilter {
+    /// Parses comma separated `target=level` directives. Levels are `off`, `error`, `warn`, `info`, `debug` and
+    /// `trace`, in any case.
+    pub fn parse(directives: &str) -> Result<LevelFilter, String> {
+        let mut filter = LevelFilter::default();
+        for directive in directives.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let (target, level) = match directive.split_once('=') {
+                Some((target, level)) => (target.trim(), level.trim()),
+                None => ("*", directive),
+            };
+            let level = level
+                .parse::<LogLevel>()
+                .map_err(|_| format!("Invalid level {:?} in the level filter directive {:?}", level, directive))?;
+            if target.is_empty() {
+                return Err(format!("Missing target in the level filter directive {:?}", directive));
+            }
+            if target == "*" {
+                filter.default = Some(level);
+            } else {
+                filter.directives.retain(|d| d.target != target);
+                filter.directives.push(Directive { target: target.to_string(), level });
+            }
+        }
+        Ok(filter)
+    }
+
+    /// Level set by `*`, if any
+    pub fn default_level(&self) -> Option<LogLevel> {
+        self.default
+    }
+
+    /// Level of the directive matching `topic` or `module_path`, if any
+    pub fn level_for(&self, topic: Option<&str>, module_path: &str) -> Option<LogLevel> {
+        if let Some(topic) = topic.filter(|t| !t.is_empty()) {
+            if let Some(directive) = self.directives.iter().find(|d| d.target == topic) {
+                return Some(directive.level);
+            }
+        }
+        self.directives
+            .iter()
+            .filter(|d| {
+                module_path == d.target
+                    || module_path.strip_prefix(d.target.as_str()).is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|d| d.target.len())
+            .map(|d| d.level)
+    }
+}
+
+/// Level of one callsite, resolved on first use and cached
+pub struct CallsiteLevel {
+    level: AtomicUsize,
+    topic: &'static str,
+    /// `topic` holds the tokens of the topic expression rather than the topic
+    topic_tokens: bool,
+    module_path: &'static str,
+}
+
+impl CallsiteLevel {
+    pub const fn new(topic: &'static str, module_path: &'static str) -> CallsiteLevel {
+        CallsiteLevel { level: AtomicUsize::new(UNRESOLVED), topic, topic_tokens: false, module_path }
+    }
+
+    /// Takes the topic expression as written, e.g. `stringify!($topic)`. Only string literal topics can be matched by
+    /// topic directives, as other expressions, `const` items and variables included, are evaluated later by the
+    /// logger thread.
+    pub const fn with_topic_tokens(tokens: &'static str, module_path: &'static str) -> CallsiteLevel {
+        CallsiteLevel { level: AtomicUsize::new(UNRESOLVED), topic: tokens, topic_tokens: true, module_path }
+    }
+
+    #[inline(always)]
+    pub fn enabled(&'static self, level: usize) -> bool {
+        match self.level.load(Ordering::Relaxed) {
+            UNRESOLVED => level <= self.register(),
+            resolved => level <= resolved,
+        }
+    }
+
+    fn topic(&self) -> Option<&str> {
+        if !self.topic_tokens {
+            return Some(self.topic);
+        }
+        self.topic
+            .strip_prefix('"')
+            .and_then(|t| t.strip_suffix('"'))
+            .filter(|t| !t.contains(['"', '\\']))
+    }
+
+    fn resolve(&self, filter: &LevelFilter) -> usize {
+        let level = match filter.level_for(self.topic(), self.module_path) {
+            Some(level) => level.as_usize(),
+            None => compile_time::runtime_level(),
+        };
+        level.min(MAX_LEVEL)
+    }
+
+    #[cold]
+    fn register(&'static self) -> usize {
+        let mut state = STATE.lock().expect("Failed to lock the level filter");
+        // another thread may have registered it meanwhile
+        let level = self.level.load(Ordering::Relaxed);
+        if level != UNRESOLVED {
+            return level;
+        }
+        let level = self.resolve(&state.filter);
+        self.level.store(level, Ordering::Relaxed);
+        state.callsites.push(self);
+        level
+    }
+}
+
+struct State {
+    filter: LevelFilter,
+    callsites: Vec<&'static CallsiteLevel>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    filter: LevelFilter { directives: Vec::new(), default: None },
+    callsites: Vec::new(),
+});
+
+/// Resolves the level of every registered callsite again
+pub(crate) fn refresh_callsites() {
+    let state = STATE.lock().expect("Failed to lock the level filter");
+    for callsite in &state.callsites {
+        callsite.level.store(callsite.resolve(&state.filter), Ordering::Relaxed);
+    }
+}
+
+/// Replaces the level filter, e.g. `set_filter("orders=debug,md=warn,*=info")`. Can be called at any time.
+/// Levels above the compile-time `MAX_LEVEL` stay disabled.
+pub fn set_filter(directives: &str) -> Result<(), String> {
+    let filter = LevelFilter::parse(directives)?;
+    if let Some(level) = filter.default_level() {
+        compile_time::store_runtime_level(level);
+    }
+    STATE.lock().expect("Failed to lock the level filter").filter = filter;
+    refresh_callsites();
+    Ok(())
+}
+
+/// Removes every directive. The level set by `set_level` still applies.
+pub fn clear_filter() {
+    STATE.lock().expect("Failed to lock the level filter").filter = LevelFilter::default();
+    refresh_callsites();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_filter() {
+        let filter = LevelFilter::parse("orders=debug, my_app::md=warn,my_app::md::book=trace,*=INFO").unwrap();
+        assert_eq!(filter.default_level(), Some(LogLevel::Info));
+        assert_eq!(filter.level_for(Some("orders"), "my_app"), Some(LogLevel::Debug));
+        assert_eq!(filter.level_for(Some("orders"), "my_app::md"), Some(LogLevel::Debug));
+        assert_eq!(filter.level_for(Some("fills"), "my_app::md"), Some(LogLevel::Warn));
+        assert_eq!(filter.level_for(None, "my_app::md::feed"), Some(LogLevel::Warn));
+        assert_eq!(filter.level_for(None, "my_app::md::book"), Some(LogLevel::Trace));
+        assert_eq!(filter.level_for(None, "my_app::mdx"), None);
+        assert_eq!(filter.level_for(Some(""), "my_app"), None);
+
+        assert_eq!(LevelFilter::parse("warn").unwrap().default_level(), Some(LogLevel::Warn));
+        assert_eq!(LevelFilter::parse("").unwrap(), LevelFilter::default());
+        assert!(LevelFilter::parse("orders=loud").is_err());
+        assert!(LevelFilter::parse("=info").is_err());
+    }
+
+    #[test]
+    fn test_callsite_topic() {
+        static LITERAL: CallsiteLevel = CallsiteLevel::with_topic_tokens("\"orders\"", "my_app");
+        static EXPRESSION: CallsiteLevel = CallsiteLevel::with_topic_tokens("Hello :: World", "my_app");
+        // a `const` topic is not evaluated either
+        static CONST: CallsiteLevel = CallsiteLevel::with_topic_tokens("ORDERS", "my_app");
+        static TOPIC: CallsiteLevel = CallsiteLevel::new("orders", "my_app");
+        assert_eq!(LITERAL.topic(), Some("orders"));
+        assert_eq!(EXPRESSION.topic(), None);
+        assert_eq!(CONST.topic(), None);
+        assert_eq!(TOPIC.topic(), Some("orders"));
+    }
+}
@@ -20,6 +20,8 @@
 //! - **Lazy String**: String interpolation in `flash_xxx!` macros is inherently lazy.
 //! - **Compile-Time Filtering**: Using `flash_xxx_ct!` macros and feature flags for efficient filtering at compile time.
 //! - **JSON Output**: Log messages are printed in `JSON` format for easy parsing and analysis.
+//! - **Pluggable Formatters**: Plain text, logfmt or your own `Formatter` instead of JSON.
+//! - **Multiple Sinks**: Send each level to its own destinations, e.g. errors to stderr and warnings to an alerts file.
 //! - **Customizable**: Flexible configuration options for file output, console reporting, buffer size, and more.
 //! - **Timezone Support**: Ability to set local or custom timezones for log timestamps.
 //! 
@@ -135,26 +137,68 @@ pub mod logger_v2;
 pub mod logger;
 pub mod rolling_file;
 pub mod compile_time;
+pub mod ring_buffer;
+pub mod binary;
+pub mod binary_file;
+pub mod formatter;
+pub mod sink;
+pub mod compression;
+pub mod config;
+pub mod error_policy;
+pub mod level_filter;
+pub mod timezone;
 
 pub use crate::timer::{
     get_unix_nano,
     convert_unix_nano_to_date_and_time,
+    convert_unix_nano_to_date_and_time_with_offset_secs,
 };
 pub type UnixNano = u64;
 pub use crate::logger::{
     LazyMessage, 
     LogLevel, 
     LogMessage, 
+    LogSender,
+    LogTransport,
+    OverflowPolicy,
     TimeZone, 
     Logger,
     LOG_SENDER,
     TIMEZONE,
     MAX_LOG_LEVEL,
 };
+pub use crate::formatter::{
+    Formatter,
+    JsonFormatter,
+    LogfmtFormatter,
+    LogRecord,
+    PrettyFormatter,
+    Record,
+    TextFormatter,
+    TimestampFormat,
+    get_timestamp_format,
+    set_timestamp_format,
+};
+pub use crate::compile_time::{get_level, set_level};
+pub use crate::compression::Compression;
+pub use crate::config::LoggerConfig;
+pub use crate::error_policy::{ErrorPolicy, LoggerHealth};
+pub use crate::level_filter::{clear_filter, set_filter, LevelFilter};
+pub use crate::timezone::{utc_offset_at, Zone};
+pub use crate::sink::{
+    FileSink,
+    FlushPolicy,
+    Sink,
+    SinkConfig,
+    StderrSink,
+    StdoutSink,
+};
 pub use rolling_file::{
+    FileNamePattern,
     RollingConfig,
     RollingFileWriter,
     RollingPeriod,
+    current_log_file_path,
     get_initial_log_file_path,
 };
 pub use serde_json;
\ No newline at end of file
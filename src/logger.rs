@@ -1,24 +1,45 @@
 use crate::flash_trace;
-use crate::timer::get_unix_nano;
+use crate::timer::{get_unix_nano, set_callsite_timestamp, set_queue_latency};
+pub use crate::timezone::TimeZone;
+use crate::timezone;
+use crate::binary::BinaryRecord;
+use crate::compression::{wait_for_compression, Compression};
+use crate::config::{self, LoggerConfig};
+use crate::error_policy::{self, ErrorCallback, ErrorPolicy, LoggerHealth, ERROR_CALLBACK, ERROR_POLICY};
+use crate::formatter::{
+    format_to_string, set_timestamp_format, Formatter, LogRecord, TimestampFormat, CONSOLE_FORMATTER, FORMATTER,
+};
+use crate::binary_file::{BinaryFileWriter, BINARY_FILE_EXTENSION};
+use crate::level_filter::{set_filter, LevelFilter};
+use crate::ring_buffer::RingBuffer;
+use crate::sink::{FileSink, FlushPolicy, Sink, SinkConfig, StderrSink, StdoutSink};
 use crate::rolling_file::{
     RollingFileWriter,
     RollingConfig,
     RollingPeriod,
+    FileNamePattern,
+    DEFAULT_FILE_EXTENSION,
+    DEFAULT_FILE_NAME_PATTERN,
     set_initial_log_file_path,
 };
 //
 //use anyhow::{anyhow, Ok, Result};
-use chrono;
 use core_affinity;
-use crossbeam_channel::{unbounded, Sender};
+use crossbeam_channel::{unbounded, Receiver, RecvError, SendError, Sender, TrySendError};
+use crossbeam_utils::Backoff;
 use once_cell::sync::Lazy;
-use std::path::PathBuf;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::mem::{ManuallyDrop, MaybeUninit};
+use std::path::{Path, PathBuf};
 use std::{
     sync::{
         atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering, AtomicU64},
+        Arc,
         Mutex,
     },
     thread,
+    time::Duration,
 };
 
 pub static LOG_MESSAGE_BUFFER_SIZE: Lazy<AtomicUsize> = Lazy::new(|| AtomicUsize::new(0));
@@ -28,109 +49,173 @@ pub static LOG_MESSAGE_FLUSH_INTERVAL: Lazy<AtomicU64> = Lazy::new(|| AtomicU64:
 
 pub static INCLUDE_UNIXNANO: Lazy<AtomicBool> = Lazy::new(|| AtomicBool::new(false));
 pub static MAX_LOG_LEVEL: Lazy<AtomicUsize> = Lazy::new(|| AtomicUsize::new(LogLevel::NIL.as_usize()));
-pub static TIMEZONE: Lazy<AtomicI32> = Lazy::new(|| AtomicI32::new(TimeZone::Local as i32));
+/// Whole hours of the offset from UTC when `with_timezone` was called. Timestamps use `timezone::utc_offset_at`,
+/// which follows daylight saving time and minute offsets.
+pub static TIMEZONE: Lazy<AtomicI32> = Lazy::new(|| AtomicI32::new(0));
 pub static CONSOLE_REPORT: Lazy<AtomicBool> = Lazy::new(|| AtomicBool::new(false));
 pub static FILE_REPORT: Lazy<AtomicBool> = Lazy::new(|| AtomicBool::new(false));
 pub static LOGGER_HANDLER: Lazy<Mutex<Option<thread::JoinHandle<()>>>> =Lazy::new(|| Mutex::new(None));
 pub static LOGGER_CORE: Lazy<AtomicI32> = Lazy::new(|| AtomicI32::new(-1)); // -1 means that setting affinity to any remaining core
+pub static RING_BUFFER_CAPACITY: Lazy<AtomicUsize> = Lazy::new(|| AtomicUsize::new(0)); // 0 means the unbounded channel is used
+pub static OVERFLOW_POLICY: Lazy<AtomicUsize> = Lazy::new(|| AtomicUsize::new(OverflowPolicy::Block.as_usize()));
+pub static THREAD_BATCH_SIZE: Lazy<AtomicUsize> = Lazy::new(|| AtomicUsize::new(0)); // 0 means messages are sent one by one
+/// Messages dropped since the last summary record, indexed by level (0 is used when the level is unknown)
+pub static DROPPED_MESSAGES: [AtomicU64; 6] = [const { AtomicU64::new(0) }; 6];
+/// Set by `Logger::with_reopen_on_sighup`, the handler is installed by `Logger::launch`
+#[cfg(all(unix, feature = "sighup"))]
+static REOPEN_ON_SIGHUP: AtomicBool = AtomicBool::new(false);
+/// Records queued by `queue_internal_record`
+static INTERNAL_RECORDS: Mutex<Vec<LogRecord>> = Mutex::new(Vec::new());
+static HAS_INTERNAL_RECORDS: AtomicBool = AtomicBool::new(false);
+
+pub static LOG_SENDER: Lazy<LogSender> = Lazy::new(|| {
+    let (sender, receiver) = match RING_BUFFER_CAPACITY.load(Ordering::SeqCst) {
+        0 => {
+            let (sender, receiver) = unbounded();
+            (LogSender::Channel(sender), LogReceiver::Channel(receiver))
+        }
+        capacity => {
+            let ring = Arc::new(RingBuffer::with_eviction(capacity, is_evictable));
+            (LogSender::RingBuffer(ring.clone()), LogReceiver::RingBuffer(ring))
+        }
+    };
 
-pub static LOG_SENDER: Lazy<Sender<LogMessage>> = Lazy::new(|| {
-    let (sender, receiver) = unbounded();
-
-    let mut message_queue: Vec<String> = Vec::with_capacity(LOG_MESSAGE_BUFFER_SIZE.load(Ordering::SeqCst).max(10));
+    let mut outputs = Outputs::new();
     let mut last_flush_time = get_unix_nano();
 
     let mut msg_buffer_size = LOG_MESSAGE_BUFFER_SIZE.load(Ordering::SeqCst);
     let mut msg_flush_interval = LOG_MESSAGE_FLUSH_INTERVAL.load(Ordering::SeqCst);
-    let mut file_report = FILE_REPORT.load(Ordering::Relaxed);
-    let mut console_report = CONSOLE_REPORT.load(Ordering::Relaxed);
 
     let affinity_core = LOGGER_CORE.load(Ordering::SeqCst);
 
     *LOGGER_HANDLER.lock().expect("Logger hander lock") = Some(thread::spawn(move || {
-        let mut rolling_writer: Option<RollingFileWriter> = None;
+        let mut binary_writer: Option<BinaryFileWriter> = None;
+        let mut last_binary_flush_time = get_unix_nano();
         while let Ok(msg) = receiver.recv() {
             match msg {
                 LogMessage::LazyMessage(lazy_message) => {
-                    let message = lazy_message.eval();
+                    outputs.push(lazy_message.evaluate());
                     let current_timestamp = get_unix_nano();
-                    message_queue.push(message);
-
-                    if msg_buffer_size == 0 || msg_flush_interval == 0 || (message_queue.len() >= msg_buffer_size) || (current_timestamp >= msg_flush_interval + last_flush_time) {
-                        let output = message_queue.join("");
+                    if receiver.is_empty() {
+                        outputs.push_internal_records();
+                    }
 
-                        if file_report {
-                            if let Some(ref mut writer) = rolling_writer {    
-                                writer.write_all(output.as_bytes()).unwrap();
-                            }
+                    if msg_buffer_size == 0 || msg_flush_interval == 0 || (outputs.pending() >= msg_buffer_size) || (current_timestamp >= msg_flush_interval + last_flush_time) {
+                        outputs.write();
+                        last_flush_time = current_timestamp;
+                    }
+                }
+                LogMessage::Binary(record) if binary_writer.is_some() => {
+                    let current_timestamp = get_unix_nano();
+                    let log_record = binary_log_record(&record);
+                    if let Some(ref mut writer) = binary_writer {
+                        if error_policy::attempt("binary file", || writer.write_record(&record, log_record.unixnano)).is_none() {
+                            let formatter = FORMATTER.lock().expect("Failed to lock FORMATTER").clone();
+                            let line = format_to_string(&*formatter, &log_record.as_record());
+                            error_policy::fall_back(line.as_bytes(), 1);
                         }
-                        
-                        if console_report { println!("{}", output); }
-
-                        message_queue.clear();
+                        if msg_flush_interval == 0 || current_timestamp >= msg_flush_interval + last_binary_flush_time {
+                            flush_binary(writer, false);
+                            last_binary_flush_time = current_timestamp;
+                        }
+                    }
+                    outputs.push_to(Evaluated::Record(log_record), true);
+                    if msg_buffer_size == 0 || msg_flush_interval == 0 || (outputs.pending() >= msg_buffer_size) || (current_timestamp >= msg_flush_interval + last_flush_time) {
+                        outputs.write();
+                        last_flush_time = current_timestamp;
+                    }
+                }
+                LogMessage::Binary(record) => {
+                    let current_timestamp = get_unix_nano();
+                    outputs.push(Evaluated::Record(binary_log_record(&record)));
+                    if receiver.is_empty() {
+                        outputs.push_internal_records();
+                    }
 
+                    if msg_buffer_size == 0 || msg_flush_interval == 0 || (outputs.pending() >= msg_buffer_size) || (current_timestamp >= msg_flush_interval + last_flush_time) {
+                        outputs.write();
                         last_flush_time = current_timestamp;
                     }
                 }
-                LogMessage::FlushingMessage(lazy_message) => {
-                    let message = lazy_message.eval();
-                    message_queue.push(message);
+                LogMessage::Batch(messages) => {
+                    for message in messages {
+                        outputs.push(message.evaluate());
+                    }
+                    let current_timestamp = get_unix_nano();
+                    if receiver.is_empty() {
+                        outputs.push_internal_records();
+                    }
 
-                    let output = message_queue.join("");
-                    if file_report {
-                        if let Some(ref mut writer) = rolling_writer {
-                            writer.write_all(output.as_bytes()).unwrap();
-                        }
+                    if msg_buffer_size == 0 || msg_flush_interval == 0 || (outputs.pending() >= msg_buffer_size) || (current_timestamp >= msg_flush_interval + last_flush_time) {
+                        outputs.write();
+                        last_flush_time = current_timestamp;
                     }
-                    if console_report {
-                        println!("{}", output);
+                }
+                LogMessage::FlushingMessage(lazy_message) => {
+                    outputs.push(lazy_message.evaluate());
+                    outputs.push_internal_records();
+                    if let Some(ref mut writer) = binary_writer {
+                        flush_binary(writer, false);
                     }
 
-                    message_queue.clear();
+                    outputs.write();
                     last_flush_time = get_unix_nano();
                 }
                 LogMessage::StaticString(message) => {
-                    let buffer_size = message_queue.len();
+                    let buffer_size = outputs.pending();
                     let timestamp = get_unix_nano();
-                    message_queue.push(message.to_string());
+                    outputs.push(Evaluated::Text(0, message.to_string()));
 
                     if (buffer_size + message.len() >= msg_buffer_size)
                         || (timestamp >= msg_flush_interval + last_flush_time)
                     {
-                        let output = message_queue.join("");
-                        if file_report {
-                            if let Some(ref mut writer) = rolling_writer {
-                                writer.write_all(output.as_bytes()).unwrap();
-                            }
-                        }
-
-                        if console_report {
-                            println!("{}", output);
-                        }
+                        outputs.write();
                     }
                 }
                 LogMessage::SetFile(config) => {
-                    if let Some(ref mut writer) = rolling_writer {
-                        writer.flush().expect("Failed to flush log file writer");
-                        let _ = writer.sync_all();
+                    if let Some(file) = outputs.file_mut() {
+                        file.write();
+                        file.sync();
+                    } else if let Some(sink) = open_file("log file", || RollingFileWriter::new(config.clone())) {
+                        outputs.set_file(sink);
+                    }
+                }
+                LogMessage::AddRoute(topic, config) => {
+                    if let Some(sink) = open_file("topic file", || RollingFileWriter::open(config.clone(), false)) {
+                        outputs.add_route(topic, sink);
+                    }
+                }
+                LogMessage::AddSink(config) => {
+                    outputs.add_sink(config);
+                }
+                LogMessage::SetBinaryFile(config) => {
+                    if let Some(ref mut writer) = binary_writer {
+                        flush_binary(writer, true);
                     } else {
-                        let writer = RollingFileWriter::new(config).expect("Failed to create RollingFileWriter");
-                        rolling_writer = Some(writer);
+                        // without a binary file, binary records are written as lines like the other messages
+                        binary_writer = error_policy::attempt("binary file", || BinaryFileWriter::new(config.clone()));
                     }
                 }
                 LogMessage::Flush => {
-                    let output = message_queue.join("");
-                    if file_report {
-                        if let Some(ref mut writer) = rolling_writer {
-                            writer.write_all(output.as_bytes()).unwrap();
-                            writer.flush().expect("Failed to flush log file writer");
-                            let _ = writer.sync_all();
-                        }
+                    outputs.push_internal_records();
+                    if let Some(ref mut writer) = binary_writer {
+                        flush_binary(writer, true);
                     }
-                    if console_report {
-                        println!("{}", output);
+                    outputs.write();
+                    outputs.sync();
+                    last_flush_time = get_unix_nano();
+                }
+                LogMessage::Reopen => {
+                    outputs.push_internal_records();
+                    if let Some(ref mut writer) = binary_writer {
+                        if let Err(e) = writer.flush().and_then(|()| writer.reopen()) {
+                            queue_internal_record(reopen_failed("binary file", &e));
+                        }
                     }
-                    message_queue.clear();
+                    // queued lines go to the old file, later ones to the reopened one
+                    outputs.write();
+                    outputs.reopen();
+                    outputs.push_internal_records();
                     last_flush_time = get_unix_nano();
                 }
                 LogMessage::SetCore => {
@@ -151,24 +236,22 @@ pub static LOG_SENDER: Lazy<Sender<LogMessage>> = Lazy::new(|| {
                     }
                 }
                 LogMessage::Close => {
-                    let output = message_queue.join("");
-                    if file_report {
-                        if let Some(ref mut writer) = rolling_writer {
-                            writer.write_all(output.as_bytes()).unwrap();
-                            writer.flush().expect("Failed to flush log file writer in Close");
-                            let _ = writer.sync_all();
-                        }
-                    }
-                    if console_report {
-                        println!("{}", output);
+                    outputs.push_internal_records();
+                    if let Some(ref mut writer) = binary_writer {
+                        flush_binary(writer, true);
                     }
+                    outputs.write();
+                    // records raised while writing, e.g. files removed when rolling
+                    outputs.push_internal_records();
+                    outputs.write();
+                    outputs.sync();
+                    receiver.close();
                     break;
                 }
                 LogMessage::SetConfig => {
                     msg_buffer_size = LOG_MESSAGE_BUFFER_SIZE.load(Ordering::Relaxed);
                     msg_flush_interval = LOG_MESSAGE_FLUSH_INTERVAL.load(Ordering::Relaxed);
-                    file_report = FILE_REPORT.load(Ordering::Relaxed);
-                    console_report = CONSOLE_REPORT.load(Ordering::Relaxed);
+                    outputs.load_config();
                 }
             }
         }
@@ -176,31 +259,695 @@ pub static LOG_SENDER: Lazy<Sender<LogMessage>> = Lazy::new(|| {
     sender
 });
 
-pub enum TimeZone {
-    Local,
-    Seoul,
-    Japan,
-    NewYork,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SinkKind {
+    /// Set by `with_file`, formatted with `FORMATTER`
+    File,
+    /// File of a topic set by `with_topic_file`, formatted with `FORMATTER`
+    Route,
+    /// Present while `with_console_report(true)`, formatted with `CONSOLE_FORMATTER`
+    Console,
+    /// Added by `with_sink`
+    Custom,
+}
+
+/// A sink in the logger thread with the lines queued for it
+struct SinkSlot {
+    kind: SinkKind,
+    /// Topic written to a `Route` slot
+    topic: Option<String>,
+    sink: Box<dyn Sink>,
+    level: usize,
+    formatter: Arc<dyn Formatter>,
+    /// false if the formatter follows `FORMATTER` or `CONSOLE_FORMATTER`
+    own_formatter: bool,
+    flush_policy: FlushPolicy,
+    queue: String,
+    queued: usize,
+}
+
+impl SinkSlot {
+    fn builtin(kind: SinkKind, sink: Box<dyn Sink>) -> SinkSlot {
+        SinkSlot {
+            kind,
+            topic: None,
+            sink,
+            level: LogLevel::Trace.as_usize(),
+            formatter: match kind {
+                SinkKind::Console => CONSOLE_FORMATTER.lock().expect("Failed to lock CONSOLE_FORMATTER").clone(),
+                _ => FORMATTER.lock().expect("Failed to lock FORMATTER").clone(),
+            },
+            own_formatter: false,
+            flush_policy: FlushPolicy::Buffered,
+            queue: String::new(),
+            queued: 0,
+        }
+    }
+
+    fn custom(config: SinkConfig) -> SinkSlot {
+        let own_formatter = config.formatter.is_some();
+        SinkSlot {
+            kind: SinkKind::Custom,
+            topic: None,
+            sink: config.sink,
+            level: config.level.as_usize(),
+            formatter: config.formatter.unwrap_or_else(|| FORMATTER.lock().expect("Failed to lock FORMATTER").clone()),
+            own_formatter,
+            flush_policy: config.flush_policy,
+            queue: String::new(),
+            queued: 0,
+        }
+    }
+
+    /// Messages without a level (0) are accepted by every sink. The default file takes the topics without a route.
+    #[inline]
+    fn accepts(&self, level: usize, topic: Option<&str>, routed: bool) -> bool {
+        level <= self.level
+            && match self.kind {
+                SinkKind::File => !routed,
+                SinkKind::Route => self.topic.as_deref() == topic,
+                SinkKind::Console | SinkKind::Custom => true,
+            }
+    }
+
+    #[inline]
+    fn is_file(&self) -> bool {
+        matches!(self.kind, SinkKind::File | SinkKind::Route)
+    }
+
+    /// Destination passed to the error callback
+    fn name(&self) -> &'static str {
+        match self.kind {
+            SinkKind::File => "log file",
+            SinkKind::Route => "topic file",
+            SinkKind::Console => "console",
+            SinkKind::Custom => "sink",
+        }
+    }
+
+    fn push(&mut self, line: &str) {
+        self.queue.push_str(line);
+        self.queued += 1;
+        if self.flush_policy == FlushPolicy::Immediate {
+            self.write();
+            if let Err(e) = self.sink.flush() {
+                error_policy::report(self.name(), &e);
+            }
+        }
+    }
+
+    /// Lines that cannot be written are handled by the `ErrorPolicy`. Retries resume after the written lines.
+    fn write(&mut self) {
+        if !self.queue.is_empty() {
+            let name = self.name();
+            let (sink, queue) = (&mut self.sink, self.queue.as_bytes());
+            let mut written = 0;
+            if error_policy::attempt(name, || sink.write_resumable(queue, &mut written)).is_none() {
+                match written {
+                    0 => error_policy::fall_back(queue, self.queued),
+                    _ => {
+                        // from the start of the line that was cut off
+                        let start = queue[..written].iter().rposition(|b| *b == b'\n').map_or(0, |end| end + 1);
+                        let rest = &queue[start..];
+                        error_policy::fall_back(rest, rest.iter().filter(|b| **b == b'\n').count());
+                    }
+                }
+            }
+            self.queue.clear();
+        }
+        self.queued = 0;
+    }
+
+    fn sync(&mut self) {
+        if let Err(e) = self.sink.sync() {
+            error_policy::report(self.name(), &e);
+        }
+    }
+}
+
+/// Destinations of the logger thread: the log file, the console and the sinks added by `with_sink`.
+/// Lines are queued per sink until the next flush. A record is formatted once per distinct formatter.
+struct Outputs {
+    slots: Vec<SinkSlot>,
+    /// Topics with their own file
+    routes: Vec<String>,
+    /// Lines formatted for the current record, keyed by the address of the formatter
+    formatted: Vec<(usize, String)>,
+}
+
+impl Outputs {
+    fn new() -> Outputs {
+        let mut outputs = Outputs { slots: Vec::new(), routes: Vec::new(), formatted: Vec::new() };
+        outputs.load_config();
+        outputs
+    }
+
+    fn load_config(&mut self) {
+        let formatter = FORMATTER.lock().expect("Failed to lock FORMATTER").clone();
+        let console_formatter = CONSOLE_FORMATTER.lock().expect("Failed to lock CONSOLE_FORMATTER").clone();
+        for slot in self.slots.iter_mut().filter(|slot| !slot.own_formatter) {
+            slot.formatter = match slot.kind {
+                SinkKind::Console => console_formatter.clone(),
+                _ => formatter.clone(),
+            };
+        }
+
+        let console_report = CONSOLE_REPORT.load(Ordering::Relaxed);
+        let console = self.slots.iter().position(|slot| slot.kind == SinkKind::Console);
+        match (console_report, console) {
+            (true, None) => self.slots.push(SinkSlot::builtin(SinkKind::Console, Box::new(StdoutSink))),
+            (false, Some(index)) => {
+                let mut slot = self.slots.remove(index);
+                slot.write();
+            }
+            _ => {}
+        }
+    }
+
+    fn file_mut(&mut self) -> Option<&mut SinkSlot> {
+        self.slots.iter_mut().find(|slot| slot.kind == SinkKind::File)
+    }
+
+    fn set_file(&mut self, sink: Box<dyn Sink>) {
+        self.slots.insert(0, SinkSlot::builtin(SinkKind::File, sink));
+    }
+
+    fn add_route(&mut self, topic: String, sink: Box<dyn Sink>) {
+        let mut slot = SinkSlot::builtin(SinkKind::Route, sink);
+        slot.topic = Some(topic.clone());
+        self.slots.push(slot);
+        self.routes.push(topic);
+    }
+
+    fn add_sink(&mut self, config: SinkConfig) {
+        self.slots.push(SinkSlot::custom(config));
+    }
+
+    #[inline]
+    fn push(&mut self, message: Evaluated) {
+        self.push_to(message, false);
+    }
+
+    /// Queues the message for every sink that accepts its level and topic. `skip_file` leaves out the log files,
+    /// for records already written to the binary file.
+    fn push_to(&mut self, message: Evaluated, skip_file: bool) {
+        match message {
+            Evaluated::Text(level, text) => {
+                for slot in self.slots.iter_mut() {
+                    if slot.accepts(level, None, false) && !(skip_file && slot.is_file()) {
+                        slot.push(&text);
+                    }
+                }
+            }
+            Evaluated::Record(record) => {
+                let record = record.as_record();
+                let routed = self.routes.iter().any(|topic| topic == record.topic);
+                let formatted = &mut self.formatted;
+                formatted.clear();
+                for slot in self.slots.iter_mut() {
+                    if !slot.accepts(record.level, Some(record.topic), routed) || (skip_file && slot.is_file()) {
+                        continue;
+                    }
+                    let key = Arc::as_ptr(&slot.formatter) as *const () as usize;
+                    let index = match formatted.iter().position(|(k, _)| *k == key) {
+                        Some(index) => index,
+                        None => {
+                            formatted.push((key, format_to_string(&*slot.formatter, &record)));
+                            formatted.len() - 1
+                        }
+                    };
+                    slot.push(&formatted[index].1);
+                }
+            }
+        }
+    }
+
+    /// Queues the summary of dropped messages and the records raised outside of `LazyMessage`s
+    fn push_internal_records(&mut self) {
+        if let Some(summary) = take_dropped_summary() {
+            self.push(Evaluated::Record(summary));
+        }
+        for record in take_internal_records() {
+            self.push(Evaluated::Record(record));
+        }
+    }
+
+    /// Largest number of lines queued for a sink
+    #[inline]
+    fn pending(&self) -> usize {
+        self.slots.iter().map(|slot| slot.queued).max().unwrap_or(0)
+    }
+
+    fn write(&mut self) {
+        for slot in self.slots.iter_mut() {
+            slot.write();
+        }
+    }
+
+    /// Reopens every sink, e.g. the log files after logrotate moved them
+    fn reopen(&mut self) {
+        for slot in self.slots.iter_mut() {
+            if let Err(e) = slot.sink.reopen() {
+                queue_internal_record(reopen_failed(slot.name(), &e));
+            }
+        }
+    }
+
+    /// Flushes every sink to its destination
+    fn sync(&mut self) {
+        for slot in self.slots.iter_mut() {
+            slot.sync();
+        }
+    }
+}
+
+/// Builds the summary record for messages dropped by the overflow policy and resets the counters.
+/// Returns `None` if nothing has been dropped since the last summary.
+fn take_dropped_summary() -> Option<LogRecord> {
+    let mut counts = [0u64; 6];
+    for (count, dropped) in counts.iter_mut().zip(DROPPED_MESSAGES.iter()) {
+        *count = dropped.swap(0, Ordering::Relaxed);
+    }
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+        return None;
+    }
+
+    let mut data = Vec::new();
+    for (level, count) in counts.iter().enumerate().rev() {
+        if *count > 0 {
+            let key = match level {
+                0 => "Unknown",
+                level => crate::compile_time::usize_to_level(level),
+            };
+            data.push((key, serde_json::json!(count)));
+        }
+    }
+
+    Some(internal_record(
+        LogLevel::Warn,
+        &format!("{} messages dropped", total),
+        data,
+    ))
+}
+
+/// Queues a record to be written by the logger thread after its current message, without going through
+/// `LOG_SENDER`. Used by the logger thread itself, which must not wait for a slot only it can free.
+pub(crate) fn queue_internal_record(record: LogRecord) {
+    INTERNAL_RECORDS.lock().expect("Failed to lock INTERNAL_RECORDS").push(record);
+    HAS_INTERNAL_RECORDS.store(true, Ordering::Release);
+}
+
+fn take_internal_records() -> Vec<LogRecord> {
+    if !HAS_INTERNAL_RECORDS.swap(false, Ordering::Acquire) {
+        return Vec::new();
+    }
+    std::mem::take(&mut *INTERNAL_RECORDS.lock().expect("Failed to lock INTERNAL_RECORDS"))
+}
+
+#[cfg(all(unix, feature = "sighup"))]
+fn install_sighup_handler() {
+    static INSTALLED: std::sync::Once = std::sync::Once::new();
+    INSTALLED.call_once(|| {
+        let result = signal_hook::iterator::Signals::new([signal_hook::consts::SIGHUP]).and_then(|mut signals| {
+            thread::Builder::new().name("flashlog-sighup".to_string()).spawn(move || {
+                for _ in signals.forever() {
+                    Logger::reopen();
+                }
+            })
+        });
+        if let Err(e) = result {
+            queue_internal_record(internal_record(
+                LogLevel::Error,
+                "failed to install the SIGHUP handler",
+                vec![("error", serde_json::json!(e.to_string()))],
+            ));
+        }
+    });
+}
+
+/// Opens a log file as `ErrorPolicy` allows. With `ErrorPolicy::FallbackToStderr`, stderr takes its place if it
+/// cannot be opened; otherwise its lines are not written.
+fn open_file(destination: &str, open: impl FnMut() -> std::io::Result<RollingFileWriter>) -> Option<Box<dyn Sink>> {
+    match error_policy::attempt(destination, open) {
+        Some(writer) => Some(Box::new(FileSink::from_writer(writer))),
+        None if error_policy::falls_back_to_stderr() => Some(Box::new(StderrSink)),
+        None => None,
+    }
+}
+
+fn flush_binary(writer: &mut BinaryFileWriter, sync: bool) {
+    let result = writer.flush().and_then(|()| if sync { writer.sync_all() } else { Ok(()) });
+    if let Err(e) = result {
+        error_policy::report("binary file", &e);
+    }
+}
+
+fn reopen_failed(sink: &str, error: &std::io::Error) -> LogRecord {
+    internal_record(
+        LogLevel::Error,
+        "failed to reopen",
+        vec![("sink", serde_json::json!(sink)), ("error", serde_json::json!(error.to_string()))],
+    )
+}
+
+/// Decodes a `flash_xxx_bin!` record, timestamped at the callsite or now
+fn binary_log_record(record: &BinaryRecord) -> LogRecord {
+    let (unixnano, queue_latency) = record.time().resolve();
+    let mut log_record = record.to_log_record(unixnano);
+    log_record.queue_latency = queue_latency;
+    log_record
+}
+
+/// Record produced by flashlog itself, with `flashlog` as the topic and source
+pub(crate) fn internal_record(
+    level: LogLevel,
+    message: &str,
+    data: Vec<(&'static str, serde_json::Value)>,
+) -> LogRecord {
+    LogRecord {
+        level: level.as_usize(),
+        topic: "flashlog".to_string(),
+        message: message.to_string(),
+        data: Some(data),
+        file: "flashlog",
+        line: 0,
+        module_path: module_path!(),
+        unixnano: get_unix_nano(),
+        queue_latency: None,
+    }
+}
+
+/// What a logging thread does when the ring buffer is full.
+/// The unbounded channel transport never becomes full, so only a stopped logger thread drops messages there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Wait until the logger thread frees a slot (default)
+    Block,
+    /// Discard the message being logged
+    DropNewest,
+    /// Discard the oldest queued message to make room for the new one.
+    /// Control messages are kept in place; when one is the oldest, the new message is discarded instead.
+    DropOldest,
+    /// Discard messages less severe than the given level, and wait for the rest
+    DropBelowLevel(LogLevel),
+}
+
+impl OverflowPolicy {
+    #[inline]
+    pub fn as_usize(&self) -> usize {
+        match self {
+            OverflowPolicy::Block => 0,
+            OverflowPolicy::DropNewest => 1,
+            OverflowPolicy::DropOldest => 2,
+            OverflowPolicy::DropBelowLevel(level) => 3 + level.as_usize(),
+        }
+    }
+
+    #[inline]
+    pub fn from_usize(policy: usize) -> OverflowPolicy {
+        match policy {
+            1 => OverflowPolicy::DropNewest,
+            2 => OverflowPolicy::DropOldest,
+            3..=8 => OverflowPolicy::DropBelowLevel(LogLevel::from_usize(policy - 3).unwrap_or(LogLevel::Trace)),
+            _ => OverflowPolicy::Block,
+        }
+    }
+}
+
+#[inline]
+fn count_dropped(msg: &LogMessage) {
+    match msg {
+        LogMessage::LazyMessage(lazy_message) | LogMessage::FlushingMessage(lazy_message) => {
+            DROPPED_MESSAGES[lazy_message.level.min(5)].fetch_add(1, Ordering::Relaxed);
+        }
+        LogMessage::Batch(messages) => {
+            for lazy_message in messages {
+                DROPPED_MESSAGES[lazy_message.level.min(5)].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        LogMessage::Binary(record) => {
+            DROPPED_MESSAGES[record.callsite().level.min(5)].fetch_add(1, Ordering::Relaxed);
+        }
+        _ => {
+            DROPPED_MESSAGES[0].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Whether `OverflowPolicy::DropOldest` may discard the message; control messages are never dropped
+#[inline]
+fn is_evictable(msg: &LogMessage) -> bool {
+    matches!(
+        msg,
+        LogMessage::LazyMessage(_) | LogMessage::FlushingMessage(_) | LogMessage::Batch(_) | LogMessage::Binary(_) | LogMessage::StaticString(_)
+    )
+}
+
+/// Most severe level in the message, 0 if unknown
+#[inline]
+fn message_level(msg: &LogMessage) -> usize {
+    match msg {
+        LogMessage::LazyMessage(lazy_message) | LogMessage::FlushingMessage(lazy_message) => lazy_message.level,
+        LogMessage::Batch(messages) => messages.iter().map(|m| m.level).filter(|l| *l > 0).min().unwrap_or(0),
+        LogMessage::Binary(record) => record.callsite().level,
+        _ => 0,
+    }
+}
+
+/// Messages staged by the current thread when `with_thread_batch_size` is set.
+/// Remaining messages are published when the thread exits.
+struct ThreadBatch {
+    messages: Vec<LazyMessage>,
+}
+
+impl Drop for ThreadBatch {
+    fn drop(&mut self) {
+        if !self.messages.is_empty() {
+            LOG_SENDER.dispatch(LogMessage::Batch(std::mem::take(&mut self.messages)));
+        }
+    }
+}
+
+thread_local! {
+    static THREAD_BATCH: RefCell<ThreadBatch> = const { RefCell::new(ThreadBatch { messages: Vec::new() }) };
+}
+
+/// Publishes the messages staged by the current thread to the logger thread. Called by `flush!`.
+pub fn flush_thread_batch() {
+    let messages = THREAD_BATCH
+        .try_with(|batch| std::mem::take(&mut batch.borrow_mut().messages))
+        .unwrap_or_default();
+    if !messages.is_empty() {
+        LOG_SENDER.dispatch(LogMessage::Batch(messages));
+    }
+}
+
+/// Transport between the logging threads and the logger thread.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogTransport {
+    /// Unbounded crossbeam channel (default)
+    Channel,
+    /// Pre-allocated lock-free ring buffer with the given capacity (rounded up to a power of two).
+    /// A sender waits for a free slot when the buffer is full.
+    RingBuffer(usize),
+}
+
+pub enum LogSender {
+    Channel(Sender<LogMessage>),
+    RingBuffer(Arc<RingBuffer<LogMessage>>),
 }
 
-impl TimeZone {
+impl LogSender {
+    /// Sends a message without waiting. Fails if the queue is full or the logger thread has stopped.
+    #[inline]
+    #[allow(clippy::result_large_err)]
+    pub fn try_send(&self, msg: LogMessage) -> Result<(), TrySendError<LogMessage>> {
+        match self {
+            LogSender::Channel(sender) => sender.try_send(msg),
+            LogSender::RingBuffer(ring) => {
+                if ring.is_closed() {
+                    return Err(TrySendError::Disconnected(msg));
+                }
+                ring.push(msg).map_err(TrySendError::Full)
+            }
+        }
+    }
+
+    /// Sends a message, waiting for a free slot if the ring buffer is full.
+    /// Fails only if the logger thread has stopped.
     #[inline]
-    pub fn as_offset_hour(&self) -> i32 {
+    #[allow(clippy::result_large_err)]
+    pub fn send(&self, msg: LogMessage) -> Result<(), SendError<LogMessage>> {
         match self {
-            TimeZone::Local => {
-                let local = chrono::Local::now();
-                let offset = local.offset().local_minus_utc() / 3600;
-                offset
+            LogSender::Channel(sender) => sender.send(msg),
+            LogSender::RingBuffer(ring) => {
+                let backoff = Backoff::new();
+                let mut msg = msg;
+                loop {
+                    if ring.is_closed() {
+                        return Err(SendError(msg));
+                    }
+                    match ring.push(msg) {
+                        Ok(()) => return Ok(()),
+                        Err(returned) => {
+                            msg = returned;
+                            backoff.snooze();
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sends a log message according to the configured `OverflowPolicy`.
+    /// It never panics: messages that cannot be delivered are counted and reported in a summary record.
+    /// If thread batching is enabled, the message is staged in the current thread's batch instead.
+    #[inline]
+    pub fn send_log(&self, msg: LogMessage) {
+        let batch_size = THREAD_BATCH_SIZE.load(Ordering::Relaxed);
+        if batch_size == 0 {
+            self.dispatch(msg);
+        } else {
+            self.stage(msg, batch_size);
+        }
+    }
+
+    fn stage(&self, msg: LogMessage, batch_size: usize) {
+        let lazy_message = match msg {
+            LogMessage::LazyMessage(lazy_message) => lazy_message,
+            msg => {
+                // keep the thread's order: staged messages go first
+                flush_thread_batch();
+                self.dispatch(msg);
+                return;
+            }
+        };
+
+        let mut slot = Some(lazy_message);
+        let full_batch = THREAD_BATCH.try_with(|batch| {
+            let mut batch = batch.borrow_mut();
+            batch.messages.extend(slot.take());
+            if batch.messages.len() >= batch_size {
+                Some(std::mem::replace(&mut batch.messages, Vec::with_capacity(batch_size)))
+            } else {
+                None
+            }
+        });
+
+        match (full_batch, slot) {
+            (Ok(Some(messages)), _) => self.dispatch(LogMessage::Batch(messages)),
+            (Err(_), Some(lazy_message)) => self.dispatch(LogMessage::LazyMessage(lazy_message)), // thread-local already destroyed
+            _ => {}
+        }
+    }
+
+    #[inline]
+    fn dispatch(&self, msg: LogMessage) {
+        let msg = match self.try_send(msg) {
+            Ok(()) => return,
+            Err(TrySendError::Disconnected(msg)) => {
+                count_dropped(&msg);
+                return;
             }
-            TimeZone::Seoul => 9,
-            TimeZone::Japan => 9,
-            TimeZone::NewYork => -4,
+            Err(TrySendError::Full(msg)) => msg,
+        };
+        self.send_log_cold(msg);
+    }
+
+    #[cold]
+    fn send_log_cold(&self, msg: LogMessage) {
+        match OverflowPolicy::from_usize(OVERFLOW_POLICY.load(Ordering::Relaxed)) {
+            OverflowPolicy::Block => {
+                if let Err(SendError(msg)) = self.send(msg) {
+                    count_dropped(&msg);
+                }
+            }
+            OverflowPolicy::DropNewest => count_dropped(&msg),
+            OverflowPolicy::DropBelowLevel(threshold) => {
+                if message_level(&msg) > threshold.as_usize() {
+                    count_dropped(&msg);
+                } else if let Err(SendError(msg)) = self.send(msg) {
+                    count_dropped(&msg);
+                }
+            }
+            OverflowPolicy::DropOldest => {
+                let LogSender::RingBuffer(ring) = self else {
+                    count_dropped(&msg);
+                    return;
+                };
+                let mut msg = msg;
+                loop {
+                    match ring.push(msg) {
+                        Ok(()) => return,
+                        Err(returned) => msg = returned,
+                    }
+                    if ring.is_closed() {
+                        count_dropped(&msg);
+                        return;
+                    }
+                    match ring.pop_evictable() {
+                        Some(oldest) => count_dropped(&oldest),
+                        // a control message is at the head: keep it in place and drop the new message instead
+                        None if !ring.is_empty() => {
+                            count_dropped(&msg);
+                            return;
+                        }
+                        None => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// Number of messages waiting for the logger thread
+    #[inline]
+    pub fn len(&self) -> usize {
+        match self {
+            LogSender::Channel(sender) => sender.len(),
+            LogSender::RingBuffer(ring) => ring.len(),
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+enum LogReceiver {
+    Channel(Receiver<LogMessage>),
+    RingBuffer(Arc<RingBuffer<LogMessage>>),
+}
+
+impl LogReceiver {
+    fn recv(&self) -> Result<LogMessage, RecvError> {
+        match self {
+            LogReceiver::Channel(receiver) => receiver.recv(),
+            // senders unpark the logger thread, the timeout is only a backstop
+            LogReceiver::RingBuffer(ring) => ring.pop_wait(Duration::from_millis(10)).ok_or(RecvError),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            LogReceiver::Channel(receiver) => receiver.is_empty(),
+            LogReceiver::RingBuffer(ring) => ring.is_empty(),
+        }
+    }
+
+    fn close(&self) {
+        if let LogReceiver::RingBuffer(ring) = self {
+            ring.close();
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum LogLevel {
+    #[serde(rename = "off", alias = "nil")]
     NIL = 0,
     Error = 1,
     Warn = 2,
@@ -238,6 +985,23 @@ impl LogLevel {
     }
 }
 
+impl std::str::FromStr for LogLevel {
+    type Err = &'static str;
+
+    /// Parses `off`, `error`, `warn`, `info`, `debug` or `trace`, in any case
+    fn from_str(level: &str) -> Result<LogLevel, &'static str> {
+        match level.to_ascii_lowercase().as_str() {
+            "off" | "nil" => Ok(LogLevel::NIL),
+            "error" => Ok(LogLevel::Error),
+            "warn" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            _ => Err("Invalid log level"),
+        }
+    }
+}
+
 impl std::fmt::Display for LogLevel {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -368,13 +1132,13 @@ macro_rules! log_fn_json {
                         stringify!($key): $key,
                     )+
                 });
-                let timezone = $crate::TIMEZONE.load(std::sync::atomic::Ordering::Relaxed);
-                let (date, time) = $crate::convert_unix_nano_to_date_and_time(unixnano, timezone);
+                let timezone = $crate::timezone::utc_offset_at(unixnano);
+                let (date, time) = $crate::convert_unix_nano_to_date_and_time_with_offset_secs(unixnano, timezone);
                 let json_msg = match include_unixnano {
                     false => $crate::serde_json::json!({
                         "date": date,
                         "time": time,
-                        "offset": timezone,
+                        "offset": $crate::timezone::offset_in_hours(timezone),
                         "level": $level.to_string(),
                         "src": format!("{}:{}", file!(), line!()),
                         "topic": $topic,
@@ -383,7 +1147,7 @@ macro_rules! log_fn_json {
                     true => $crate::serde_json::json!({
                         "date": date,
                         "time": time,
-                        "offset": timezone,
+                        "offset": $crate::timezone::offset_in_hours(timezone),
                         "level": $level.to_string(),
                         "src": format!("{}:{}", file!(), line!()),
                         "topic": $topic,
@@ -395,13 +1159,13 @@ macro_rules! log_fn_json {
                 json_msg.to_string() + "\n"
             };
 
-            $crate::LOG_SENDER.try_send($crate::LogMessage::LazyMessage($crate::LazyMessage::new(func))).unwrap();
+            $crate::LOG_SENDER.send_log($crate::LogMessage::LazyMessage($crate::LazyMessage::with_level($level.as_usize(), func)));
         }
     }};
 
     // In case of structs
     ($level:expr, $topic:expr, $struct:expr) => {{
-        if $level <= $crate::LogLevel::from_usize($crate::LOG_LEVEL.load(std::sync::atomic::Ordering::Relaxed)).expect("Invalid log level") {
+        if $level <= $crate::LogLevel::from_usize($crate::MAX_LOG_LEVEL.load(std::sync::atomic::Ordering::Relaxed)).expect("Invalid log level") {
             let unixnano = $crate::get_unix_nano();
             let include_unixnano = $crate::logger::INCLUDE_UNIXNANO.load(std::sync::atomic::Ordering::Relaxed);
             #[allow(non_snake_case)]
@@ -410,13 +1174,13 @@ macro_rules! log_fn_json {
                 let json_obj = $crate::serde_json::to_value(struct_clone).unwrap_or_else(|e| {
                     $crate::serde_json::json!({ "error": format!("serialization error: {}", e) })
                 });
-                let timezone = $crate::TIMEZONE.load(std::sync::atomic::Ordering::Relaxed);
-                let (date, time) = $crate::convert_unix_nano_to_date_and_time(unixnano, timezone);
+                let timezone = $crate::timezone::utc_offset_at(unixnano);
+                let (date, time) = $crate::convert_unix_nano_to_date_and_time_with_offset_secs(unixnano, timezone);
                 let json_msg = match include_unixnano {
                     false => $crate::serde_json::json!({
                         "date": date,
                         "time": time,
-                        "offset": timezone,
+                        "offset": $crate::timezone::offset_in_hours(timezone),
                         "level": $level.to_string(),
                         "src": format!("{}:{}", file!(), line!()),
                         "topic": $topic,
@@ -425,7 +1189,7 @@ macro_rules! log_fn_json {
                     true => $crate::serde_json::json!({
                         "date": date,
                         "time": time,
-                        "offset": timezone,
+                        "offset": $crate::timezone::offset_in_hours(timezone),
                         "level": $level.to_string(),
                         "src": format!("{}:{}", file!(), line!()),
                         "topic": $topic,
@@ -437,7 +1201,7 @@ macro_rules! log_fn_json {
                 json_msg.to_string() + "\n"
             };
 
-            $crate::LOG_SENDER.try_send($crate::LogMessage::LazyMessage($crate::LazyMessage::new(func))).unwrap();
+            $crate::LOG_SENDER.send_log($crate::LogMessage::LazyMessage($crate::LazyMessage::with_level($level.as_usize(), func)));
         }
     }};
 }
@@ -498,13 +1262,13 @@ macro_rules! flushing_log_fn_json {
                         stringify!($key): $value,
                     )+
                 });
-                let timezone = $crate::TIMEZONE.load(std::sync::atomic::Ordering::Relaxed);
-                let (date, time) = $crate::convert_unix_nano_to_date_and_time(unixnano, timezone);
+                let timezone = $crate::timezone::utc_offset_at(unixnano);
+                let (date, time) = $crate::convert_unix_nano_to_date_and_time_with_offset_secs(unixnano, timezone);
                 let json_msg = match include_unixnano {
                     true => $crate::serde_json::json!({
                         "date": date,
                         "time": time,
-                        "offset": timezone,
+                        "offset": $crate::timezone::offset_in_hours(timezone),
                         "level": $level.to_string(),
                         "src": format!("{}:{}", file!(), line!()),
                         "topic": $topic,
@@ -514,7 +1278,7 @@ macro_rules! flushing_log_fn_json {
                     false => $crate::serde_json::json!({
                         "date": date,
                         "time": time,
-                        "offset": timezone,
+                        "offset": $crate::timezone::offset_in_hours(timezone),
                         "level": $level.to_string(),
                         "src": format!("{}:{}", file!(), line!()),
                         "topic": $topic,
@@ -524,27 +1288,27 @@ macro_rules! flushing_log_fn_json {
                 json_msg.to_string() + "\n"
             };
 
-            $crate::LOG_SENDER.try_send($crate::LogMessage::FlushingMessage($crate::LazyMessage::new(func))).unwrap();
+            $crate::LOG_SENDER.send_log($crate::LogMessage::FlushingMessage($crate::LazyMessage::with_level($level.as_usize(), func)));
         }
     }};
 
     // In case of structs
     ($level:expr, $topic:expr, $struct:expr) => {{
-        if $level <= $crate::LogLevel::from_usize($crate::LOG_LEVEL.load(std::sync::atomic::Ordering::Relaxed)).unwrap() {
+        if $level <= $crate::LogLevel::from_usize($crate::MAX_LOG_LEVEL.load(std::sync::atomic::Ordering::Relaxed)).unwrap() {
             let unixnano = $crate::get_unix_nano();
             let include_unixnano = $crate::logger::INCLUDE_UNIXNANO.load(std::sync::atomic::Ordering::Relaxed);
             let func = move || {
                 let json_obj = $crate::serde_json::to_value($struct).unwrap_or_else(|e| {
                     $crate::serde_json::json!({ "error": format!("serialization error: {}", e) })
                 });
-                let timezone = $crate::TIMEZONE.load(std::sync::atomic::Ordering::Relaxed);
-                let (date, time) = $crate::convert_unix_nano_to_date_and_time(timestamp, timezone);
+                let timezone = $crate::timezone::utc_offset_at(unixnano);
+                let (date, time) = $crate::convert_unix_nano_to_date_and_time_with_offset_secs(unixnano, timezone);
                 match include_unixnano {
                     true => {
                         let json_msg = $crate::serde_json::json!({
                             "date": date,
                             "time": time,
-                            "offset": timezone,
+                            "offset": $crate::timezone::offset_in_hours(timezone),
                             "level": $level.to_string(),
                             "src": format!("{}:{}", file!(), line!()),
                             "topic": $topic,
@@ -557,7 +1321,7 @@ macro_rules! flushing_log_fn_json {
                         let json_msg = $crate::serde_json::json!({
                             "date": date,
                             "time": time,
-                            "offset": timezone,
+                            "offset": $crate::timezone::offset_in_hours(timezone),
                             "level": $level.to_string(),
                             "src": format!("{}:{}", file!(), line!()),
                             "topic": $topic,
@@ -567,7 +1331,7 @@ macro_rules! flushing_log_fn_json {
                     }
                 }
             };
-            $crate::LOG_SENDER.try_send($crate::LogMessage::FlushingMessage($crate::LazyMessage::new(func))).unwrap();
+            $crate::LOG_SENDER.send_log($crate::LogMessage::FlushingMessage($crate::LazyMessage::with_level($level.as_usize(), func)));
         }
     }};
 }
@@ -583,18 +1347,33 @@ impl Drop for LoggerGuard {
 
 pub struct Logger {
     file_config: Option<RollingConfig>,
+    binary_file_config: Option<RollingConfig>,
+    routes: Vec<(String, RollingConfig)>,
+    sinks: Vec<SinkConfig>,
+    /// Config file reread while the logger runs, see `with_config_watch`
+    config_watch: Option<(PathBuf, Duration)>,
 }
 
 
 #[derive(Debug)]
 pub enum LoggerError {
     UnsetFile,
+    InvalidFileNamePattern(String),
+    InvalidLevelFilter(String),
+    InvalidEnvVar(String),
+    InvalidConfig(String),
+    Io(std::io::Error),
 }
 
 impl std::fmt::Display for LoggerError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             LoggerError::UnsetFile => write!(f, "File config is not set. Use with_file first"),
+            LoggerError::InvalidFileNamePattern(message) => write!(f, "{}", message),
+            LoggerError::InvalidLevelFilter(message) => write!(f, "{}", message),
+            LoggerError::InvalidEnvVar(message) => write!(f, "{}", message),
+            LoggerError::InvalidConfig(message) => write!(f, "{}", message),
+            LoggerError::Io(e) => write!(f, "{}", e),
         }
     }
 }
@@ -603,17 +1382,144 @@ impl std::error::Error for LoggerError {}
 
 impl Logger {
     pub fn finalize() {
-        let _ = LOG_SENDER.try_send(LogMessage::Close);
+        flush_thread_batch();
+        let _ = LOG_SENDER.send(LogMessage::Close);
         if let Some(handler) = LOGGER_HANDLER.lock().expect("Failed to lock LOGGER_HANDLER").take() {
             let _ = handler.join();
         }
+        wait_for_compression();
+    }
+
+    /// Reopens the log files at their current paths, for external rotation such as logrotate: messages logged
+    /// before the call are written to the moved file, later ones to a new file at the original path.
+    /// Sinks added with `with_sink` are reopened too. The result is not waited for.
+    pub fn reopen() {
+        flush_thread_batch();
+        let _ = LOG_SENDER.send(LogMessage::Reopen);
+    }
+
+    /// Calls `Logger::reopen` on SIGHUP, the signal logrotate's `postrotate` usually sends.
+    /// The signal is handled in a thread of its own, installed by `launch`.
+    #[cfg(all(unix, feature = "sighup"))]
+    pub fn with_reopen_on_sighup(self) -> Logger {
+        REOPEN_ON_SIGHUP.store(true, Ordering::Relaxed);
+        self
+    }
+
+    /// Whether the logger thread is running, and the I/O errors and lost lines so far
+    pub fn health() -> LoggerHealth {
+        error_policy::health()
     }
 
     pub fn initialize() -> Logger {
         let _ = get_unix_nano();
         LOG_MESSAGE_BUFFER_SIZE.store(1_000_000, Ordering::Relaxed);
         LOG_MESSAGE_FLUSH_INTERVAL.store(1_000_000, Ordering::Relaxed);
-        Logger { file_config: None }
+        Logger {
+            file_config: None,
+            binary_file_config: None,
+            routes: Vec::new(),
+            sinks: Vec::new(),
+            config_watch: None,
+        }
+    }
+
+    /// `Logger::initialize()` configured by a TOML file, or a JSON file if its name ends with `.json`.
+    /// See `LoggerConfig`.
+    pub fn from_config_file<P: AsRef<Path>>(path: P) -> Result<Logger, LoggerError> {
+        Logger::initialize().with_config(&LoggerConfig::from_file(path)?)
+    }
+
+    pub fn with_config(self, config: &LoggerConfig) -> Result<Logger, LoggerError> {
+        config.apply(self)
+    }
+
+    /// Rereads the config file at `path` every `interval` once the logger is launched. When it changes, the level,
+    /// buffer size, flush interval, console and formatters are applied again. Other settings need a restart.
+    pub fn with_config_watch<P: AsRef<Path>>(mut self, path: P, interval: Duration) -> Logger {
+        self.config_watch = Some((path.as_ref().to_path_buf(), interval));
+        self
+    }
+
+    /// `Logger::initialize()` configured by the `FLASHLOG_xxx` environment variables. See `with_env`.
+    pub fn from_env() -> Result<Logger, LoggerError> {
+        Logger::initialize().with_env()
+    }
+
+    /// Applies the `FLASHLOG_xxx` environment variables that are set and not empty. Other settings keep the values
+    /// given to the builder, and nothing is applied if a variable is invalid.
+    /// - `FLASHLOG_LEVEL`: a level (`info`) or level filter directives (`orders=debug,*=info`), see `set_filter`
+    /// - `FLASHLOG_DIR` and `FLASHLOG_PREFIX`: directory and prefix of the log file. If only one is set and no file
+    ///   is configured, the other is `logs` or `message`.
+    /// - `FLASHLOG_ROLL`: `none`, `secondly`, `minutely`, `hourly`, `daily` or `weekly`. Needs a log file.
+    /// - `FLASHLOG_CONSOLE`: `true` or `false` (also `1`/`0`, `yes`/`no`, `on`/`off`)
+    /// - `FLASHLOG_CORE`: core of the logger thread, `-1` for any
+    pub fn with_env(self) -> Result<Logger, LoggerError> {
+        self.with_vars(|name| std::env::var(name).ok())
+    }
+
+    fn with_vars(mut self, var: impl Fn(&str) -> Option<String>) -> Result<Logger, LoggerError> {
+        let var = |name: &str| var(name).map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+        let invalid = |name: &str, value: &str| {
+            LoggerError::InvalidEnvVar(format!("Invalid value {:?} for {}", value, name))
+        };
+
+        let level = var("FLASHLOG_LEVEL");
+        if let Some(level) = &level {
+            LevelFilter::parse(level)
+                .map_err(|e| LoggerError::InvalidEnvVar(format!("Invalid value for FLASHLOG_LEVEL: {}", e)))?;
+        }
+        let dir = var("FLASHLOG_DIR");
+        let prefix = var("FLASHLOG_PREFIX");
+        let roll = var("FLASHLOG_ROLL")
+            .map(|value| value.parse::<RollingPeriod>().map_err(|_| invalid("FLASHLOG_ROLL", &value)))
+            .transpose()?;
+        let console = var("FLASHLOG_CONSOLE")
+            .map(|value| match value.to_ascii_lowercase().as_str() {
+                "true" | "1" | "yes" | "on" => Ok(true),
+                "false" | "0" | "no" | "off" => Ok(false),
+                _ => Err(invalid("FLASHLOG_CONSOLE", &value)),
+            })
+            .transpose()?;
+        let core = var("FLASHLOG_CORE")
+            .map(|value| value.parse::<i32>().map_err(|_| invalid("FLASHLOG_CORE", &value)))
+            .transpose()?;
+        let has_file = self.file_config.is_some() || self.binary_file_config.is_some();
+        if roll.is_some() && !has_file && dir.is_none() && prefix.is_none() {
+            return Err(LoggerError::UnsetFile);
+        }
+
+        if dir.is_some() || prefix.is_some() {
+            match self.file_config.as_mut() {
+                Some(config) => {
+                    if let Some(dir) = dir {
+                        std::fs::create_dir_all(&dir).map_err(LoggerError::Io)?;
+                        config.base_path = PathBuf::from(dir);
+                    }
+                    if let Some(prefix) = prefix {
+                        config.file_name_prefix = prefix;
+                    }
+                }
+                None => {
+                    self = self
+                        .with_file(dir.as_deref().unwrap_or("logs"), prefix.as_deref().unwrap_or("message"))
+                        .map_err(LoggerError::Io)?;
+                }
+            }
+        }
+        if let Some(roll) = roll {
+            self = self.with_roll_period(roll)?;
+        }
+        if let Some(console) = console {
+            self = self.with_console_report(console);
+        }
+        if let Some(core) = core {
+            self = self.with_logger_core(core);
+        }
+        if let Some(level) = level {
+            set_filter(&level).map_err(LoggerError::InvalidLevelFilter)?;
+        }
+        Ok(self)
     }
 
     pub fn with_file(mut self, file_path: &str, file_name: &str) -> Result<Logger, std::io::Error> {
@@ -624,7 +1530,14 @@ impl Logger {
             file_name_prefix: file_name.to_string(),
             roll_period: Some(RollingPeriod::Daily),
             max_roll_files: Some(10),
+            max_file_size: None,
+            max_age: None,
+            max_total_size: None,
             compress: false,
+            compression: Compression::Gzip,
+            file_extension: DEFAULT_FILE_EXTENSION.to_string(),
+            file_name_pattern: DEFAULT_FILE_NAME_PATTERN.to_string(),
+            current_link: false,
             initial_file_path: None,
         };
 
@@ -634,13 +1547,86 @@ impl Logger {
         Ok(self)
     }
 
+    /// Writes `flash_xxx_bin!` records to compact binary `.flog` files instead of JSON lines.
+    /// Other messages still go to the file set by `with_file`. The roll period, max roll files and compression
+    /// settings apply to both files. Use the `flashlog-decode` binary to turn the files back into JSON.
+    pub fn with_binary_file(mut self, file_path: &str, file_name: &str) -> Result<Logger, std::io::Error> {
+        std::fs::create_dir_all(file_path)?;
+
+        let config = RollingConfig {
+            base_path: PathBuf::from(file_path),
+            file_name_prefix: file_name.to_string(),
+            roll_period: Some(RollingPeriod::Daily),
+            max_roll_files: Some(10),
+            max_file_size: None,
+            max_age: None,
+            max_total_size: None,
+            compress: false,
+            compression: Compression::Gzip,
+            file_extension: BINARY_FILE_EXTENSION.to_string(),
+            file_name_pattern: DEFAULT_FILE_NAME_PATTERN.to_string(),
+            current_link: false,
+            initial_file_path: None,
+        };
+
+        self.binary_file_config = Some(config);
+
+        Ok(self)
+    }
+
+    /// Writes the records of `topic` to their own file instead of the file set by `with_file`.
+    /// The file is rolled daily keeping 10 files; use `with_topic_route` for other settings.
+    pub fn with_topic_file(self, topic: &str, file_path: &str, file_name: &str) -> Result<Logger, std::io::Error> {
+        let config = RollingConfig {
+            base_path: PathBuf::from(file_path),
+            file_name_prefix: file_name.to_string(),
+            roll_period: Some(RollingPeriod::Daily),
+            max_roll_files: Some(10),
+            max_file_size: None,
+            max_age: None,
+            max_total_size: None,
+            compress: false,
+            compression: Compression::Gzip,
+            file_extension: DEFAULT_FILE_EXTENSION.to_string(),
+            file_name_pattern: DEFAULT_FILE_NAME_PATTERN.to_string(),
+            current_link: false,
+            initial_file_path: None,
+        };
+        self.with_topic_route(topic, config)
+    }
+
+    /// Writes the records of `topic` to the file described by `config` instead of the file set by `with_file`.
+    /// `with_roll_period`, `with_max_roll_files` and `with_compress` do not apply to it.
+    pub fn with_topic_route(mut self, topic: &str, config: RollingConfig) -> Result<Logger, std::io::Error> {
+        std::fs::create_dir_all(&config.base_path)?;
+        self.routes.retain(|(routed, _)| routed != topic);
+        self.routes.push((topic.to_string(), config));
+        Ok(self)
+    }
+
+    fn file_configs(&mut self) -> Result<impl Iterator<Item = &mut RollingConfig>, LoggerError> {
+        if self.file_config.is_none() && self.binary_file_config.is_none() {
+            return Err(LoggerError::UnsetFile);
+        }
+        Ok(self.file_config.iter_mut().chain(self.binary_file_config.iter_mut()))
+    }
+
     pub fn with_compress(mut self, compress: bool) -> Result<Logger, LoggerError> {
-        if let Some(ref mut config) = self.file_config {
+        for config in self.file_configs()? {
             config.compress = compress;
-            Ok(self)
-        } else {
-            Err(LoggerError::UnsetFile)
         }
+        Ok(self)
+    }
+
+    /// Compresses old files with `compression` (gzip by default, zstd and lz4 with the cargo features of
+    /// the same name). Files are compressed in a background thread and the outcome is logged under the
+    /// `flashlog` topic.
+    pub fn with_compression(mut self, compression: Compression) -> Result<Logger, LoggerError> {
+        for config in self.file_configs()? {
+            config.compress = true;
+            config.compression = compression;
+        }
+        Ok(self)
     }
 
     pub fn with_logger_core(self, core: i32) -> Logger {
@@ -649,12 +1635,10 @@ impl Logger {
     }
 
     pub fn with_roll_period(mut self, period: RollingPeriod) -> Result<Logger, LoggerError> {
-        if let Some(ref mut config) = self.file_config {
-            config.roll_period = Some(period);
-            Ok(self)
-        } else {
-            Err(LoggerError::UnsetFile)
+        for config in self.file_configs()? {
+            config.roll_period = Some(period.clone());
         }
+        Ok(self)
     }
 
     pub fn include_unixnano(self, include: bool) -> Logger {
@@ -662,13 +1646,87 @@ impl Logger {
         self
     }
 
+    /// Sets how the built-in formatters write timestamps, e.g. `TimestampFormat::Rfc3339 { digits: 3 }` for
+    /// `2024-09-15T20:34:30.684+09:00`. `TimestampFormat::Legacy` by default.
+    pub fn with_timestamp_format(self, format: TimestampFormat) -> Logger {
+        set_timestamp_format(format);
+        self
+    }
+
+    /// Timestamps the `flash_xxx_ct!`, `flash_xxx!` and `flash_xxx_bin!` messages with a raw clock reading taken at
+    /// the callsite, which costs a few nanoseconds, instead of the time the logger thread evaluates them. Timestamps
+    /// then no longer drift by the time messages spend in the queue.
+    pub fn with_callsite_timestamp(self, enabled: bool) -> Logger {
+        set_callsite_timestamp(enabled);
+        self
+    }
+
+    /// Takes timestamps at the callsite like `with_callsite_timestamp`, and adds the nanoseconds each message spent
+    /// between the log call and its evaluation by the logger thread as a `queue_latency_ns` field
+    pub fn with_queue_latency(self, enabled: bool) -> Logger {
+        set_queue_latency(enabled);
+        self
+    }
+
     pub fn with_max_roll_files(mut self, max_roll_files: usize) -> Result<Logger, LoggerError> {
-        if let Some(ref mut config) = self.file_config {
+        for config in self.file_configs()? {
             config.max_roll_files = Some(max_roll_files);
-            Ok(self)
-        } else {
-            Err(LoggerError::UnsetFile)
         }
+        Ok(self)
+    }
+
+    /// Adds a destination with its own level, formatter and flush policy, next to the file and the console.
+    /// Sinks are written in the order they are added.
+    pub fn with_sink(mut self, sink: SinkConfig) -> Logger {
+        self.sinks.push(sink);
+        self
+    }
+
+    /// Names the log files by `pattern` (without the extension), e.g. `"{hostname}-{prefix}-{date}-{seq}"`.
+    /// See `RollingConfig::file_name_pattern` for the placeholders. The default is `"{prefix}-{period}"`.
+    pub fn with_file_name_pattern(mut self, pattern: &str) -> Result<Logger, LoggerError> {
+        FileNamePattern::parse(pattern).map_err(|e| LoggerError::InvalidFileNamePattern(e.to_string()))?;
+        for config in self.file_configs()? {
+            config.file_name_pattern = pattern.to_string();
+        }
+        Ok(self)
+    }
+
+    /// Rolls the log files when they would grow past `max_file_size` bytes, in addition to the roll period.
+    /// With `RollingPeriod::None`, files are rolled by size only.
+    pub fn with_max_file_size(mut self, max_file_size: u64) -> Result<Logger, LoggerError> {
+        for config in self.file_configs()? {
+            config.max_file_size = Some(max_file_size);
+        }
+        Ok(self)
+    }
+
+    /// Keeps a `{prefix}.current.log` symbolic link (`.current.flog` for binary files) next to the log files,
+    /// pointing at the file being written, e.g. for `tail -F logs/message.current.log`.
+    pub fn with_current_link(mut self, current_link: bool) -> Result<Logger, LoggerError> {
+        for config in self.file_configs()? {
+            config.current_link = current_link;
+        }
+        Ok(self)
+    }
+
+    /// Removes rolled files, plain and compressed, last written longer ago than `max_age`.
+    /// Checked when a file rolls and when the logger starts. Each removal is logged under the `flashlog` topic.
+    pub fn with_max_age(mut self, max_age: Duration) -> Result<Logger, LoggerError> {
+        for config in self.file_configs()? {
+            config.max_age = Some(max_age);
+        }
+        Ok(self)
+    }
+
+    /// Removes the oldest rolled files while the log files take more than `max_total_size` bytes.
+    /// The limit applies to the JSON and the binary files separately, and the current file is never removed.
+    /// Checked when a file rolls and when the logger starts. Each removal is logged under the `flashlog` topic.
+    pub fn with_max_total_size(mut self, max_total_size: u64) -> Result<Logger, LoggerError> {
+        for config in self.file_configs()? {
+            config.max_total_size = Some(max_total_size);
+        }
+        Ok(self)
     }
 
     pub fn with_console_report(self, console_report: bool) -> Logger {
@@ -692,8 +1750,94 @@ impl Logger {
         self
     }
 
+    /// Sets levels by topic and module path, e.g. `orders=debug,md=warn,*=info`. See `set_filter`.
+    pub fn with_level_filter(self, directives: &str) -> Result<Logger, LoggerError> {
+        set_filter(directives).map_err(LoggerError::InvalidLevelFilter)?;
+        Ok(self)
+    }
+
+    /// Sets the timezone of the timestamps, UTC by default. The offset is looked up for each record, following the
+    /// daylight saving time transitions of `TimeZone::Local` and IANA zones. An unknown `TimeZone::Named` keeps the
+    /// previous timezone and is reported as a `flashlog` record.
     pub fn with_timezone(self, timezone: TimeZone) -> Logger {
-        TIMEZONE.store(timezone.as_offset_hour(), Ordering::Relaxed);
+        match timezone.resolve() {
+            Ok(zone) => {
+                timezone::set_zone(zone);
+                TIMEZONE.store(zone.utc_offset_at(get_unix_nano()) / 3600, Ordering::Relaxed);
+            }
+            Err(e) => queue_internal_record(internal_record(
+                LogLevel::Error,
+                "unknown timezone",
+                vec![("timezone", serde_json::json!(format!("{:?}", timezone))), ("error", serde_json::json!(e))],
+            )),
+        }
+        self
+    }
+
+    /// Sets how records are written to the file and the console: `JsonFormatter` (the default), `TextFormatter`,
+    /// `LogfmtFormatter`, `PrettyFormatter` or any type implementing `Formatter`.
+    /// The deprecated `log_xxx!` macros always write JSON.
+    pub fn with_formatter<F: Formatter + 'static>(self, formatter: F) -> Logger {
+        let formatter: Arc<dyn Formatter> = Arc::new(formatter);
+        *FORMATTER.lock().expect("Failed to lock FORMATTER") = formatter.clone();
+        *CONSOLE_FORMATTER.lock().expect("Failed to lock CONSOLE_FORMATTER") = formatter;
+        self
+    }
+
+    /// Sets how records are written to the log file, leaving the console formatter as it is
+    pub fn with_file_formatter<F: Formatter + 'static>(self, formatter: F) -> Logger {
+        *FORMATTER.lock().expect("Failed to lock FORMATTER") = Arc::new(formatter);
+        self
+    }
+
+    /// Sets how records are printed when `with_console_report(true)` is set, leaving the file formatter as it is.
+    /// `PrettyFormatter::new()` gives colored, human-readable lines when stdout is a terminal.
+    pub fn with_console_formatter<F: Formatter + 'static>(self, formatter: F) -> Logger {
+        *CONSOLE_FORMATTER.lock().expect("Failed to lock CONSOLE_FORMATTER") = Arc::new(formatter);
+        self
+    }
+
+    /// Selects the transport to the logger thread. It must be set before the first log message,
+    /// since the transport is created together with the logger thread.
+    pub fn with_transport(self, transport: LogTransport) -> Logger {
+        let capacity = match transport {
+            LogTransport::Channel => 0,
+            LogTransport::RingBuffer(capacity) => capacity.max(2),
+        };
+        RING_BUFFER_CAPACITY.store(capacity, Ordering::SeqCst);
+        self
+    }
+
+    /// Stages messages in a per-thread buffer and hands them to the logger thread in batches of `size`,
+    /// which cuts cross-core traffic in tight logging loops. A thread's partial batch is published by
+    /// `flush!()` or when the thread exits, so the flush interval does not apply to it. 0 disables batching.
+    pub fn with_thread_batch_size(self, size: usize) -> Logger {
+        THREAD_BATCH_SIZE.store(size, Ordering::Relaxed);
+        self
+    }
+
+    /// Sets what the logger thread does when writing to a log file or sink fails.
+    /// The default writes the lines to stderr instead. The logger thread keeps running either way.
+    pub fn with_error_policy(self, policy: ErrorPolicy) -> Logger {
+        *ERROR_POLICY.lock().expect("Failed to lock ERROR_POLICY") = policy;
+        self
+    }
+
+    /// Calls `callback` in the logger thread with the destination (`"log file"`, `"topic file"`, `"binary file"`,
+    /// `"console"` or `"sink"`) and the error, whenever a write, flush or file open fails
+    pub fn with_error_callback<F>(self, callback: F) -> Logger
+    where
+        F: Fn(&str, &std::io::Error) + Send + Sync + 'static,
+    {
+        let callback: ErrorCallback = Arc::new(callback);
+        *ERROR_CALLBACK.lock().expect("Failed to lock ERROR_CALLBACK") = Some(callback);
+        self
+    }
+
+    /// Sets what happens when the ring buffer transport is full. Dropped messages are counted per level
+    /// and reported as a single "N messages dropped" record once the queue drains.
+    pub fn with_overflow_policy(self, policy: OverflowPolicy) -> Logger {
+        OVERFLOW_POLICY.store(policy.as_usize(), Ordering::Relaxed);
         self
     }
 
@@ -702,10 +1846,25 @@ impl Logger {
         let _ = LOG_SENDER.send(LogMessage::SetCore);
         let _ = LOG_SENDER.send(LogMessage::SetConfig);
         if let Some(mut config) = rolling_config {
-            let file_path = set_initial_log_file_path(&config.base_path, &config.file_name_prefix);
-            config.initial_file_path = Some(file_path);
+            config.initial_file_path = set_initial_log_file_path(&config);
             let _ = LOG_SENDER.send(LogMessage::SetFile(config));
         }
+        if let Some(config) = self.binary_file_config {
+            let _ = LOG_SENDER.send(LogMessage::SetBinaryFile(config));
+        }
+        for (topic, config) in self.routes {
+            let _ = LOG_SENDER.send(LogMessage::AddRoute(topic, config));
+        }
+        for sink in self.sinks {
+            let _ = LOG_SENDER.send(LogMessage::AddSink(sink));
+        }
+        #[cfg(all(unix, feature = "sighup"))]
+        if REOPEN_ON_SIGHUP.load(Ordering::Relaxed) {
+            install_sighup_handler();
+        }
+        if let Some((path, interval)) = self.config_watch {
+            config::watch(path, interval);
+        }
         LoggerGuard {}
     }
 }
@@ -713,16 +1872,93 @@ impl Logger {
 pub enum LogMessage {
     LazyMessage(LazyMessage),
     FlushingMessage(LazyMessage),
+    /// Messages staged in a thread-local buffer, evaluated in order by the logger thread
+    Batch(Vec<LazyMessage>),
+    /// Arguments encoded by `flash_xxx_bin!`, formatted by the logger thread
+    Binary(BinaryRecord),
     StaticString(&'static str),
     SetFile(RollingConfig),
+    SetBinaryFile(RollingConfig),
+    /// File of a topic added by `Logger::with_topic_file`
+    AddRoute(String, RollingConfig),
+    /// Sink added by `Logger::with_sink`
+    AddSink(SinkConfig),
     Flush,
+    /// Sent by `Logger::reopen`: writes the queued lines, then reopens the log files at their current paths
+    Reopen,
     SetCore,
     SetConfig,
     Close,
 }
 
+/// Output of a `LazyMessage` closure
+pub(crate) enum Evaluated {
+    /// Line formatted by the closure, with the level of the message (0 if unknown)
+    Text(usize, String),
+    Record(LogRecord),
+}
+
+/// Closures up to this many words are stored inline in `LazyMessage` without a heap allocation
+const LAZY_INLINE_WORDS: usize = 16;
+
 pub struct LazyMessage {
-    data: Box<dyn (FnOnce() -> String) + Send + 'static>,
+    level: usize,
+    data: LazyData,
+}
+
+enum LazyData {
+    Inline(InlineFn<String>),
+    Boxed(Box<dyn (FnOnce() -> String) + Send + 'static>),
+    InlineRecord(InlineFn<LogRecord>),
+    BoxedRecord(Box<dyn (FnOnce() -> LogRecord) + Send + 'static>),
+}
+
+/// Type-erased closure stored in place. `call` moves the closure out and runs it, `drop` drops it unrun.
+struct InlineFn<R> {
+    storage: MaybeUninit<[usize; LAZY_INLINE_WORDS]>,
+    call: unsafe fn(*mut u8) -> R,
+    drop: unsafe fn(*mut u8),
+}
+
+// The closure stored in `storage` is required to be `Send` by `LazyMessage::new`
+unsafe impl<R> Send for InlineFn<R> {}
+
+impl<R> InlineFn<R> {
+    /// Stores `f` in place, or gives it back if it does not fit
+    fn new<F: FnOnce() -> R>(f: F) -> Result<InlineFn<R>, F> {
+        let fits = std::mem::size_of::<F>() <= std::mem::size_of::<[usize; LAZY_INLINE_WORDS]>()
+            && std::mem::align_of::<F>() <= std::mem::align_of::<usize>();
+        if !fits {
+            return Err(f);
+        }
+
+        let mut storage = MaybeUninit::<[usize; LAZY_INLINE_WORDS]>::uninit();
+        unsafe { (storage.as_mut_ptr() as *mut F).write(f) };
+        Ok(InlineFn {
+            storage,
+            call: InlineFn::call_inline::<F>,
+            drop: InlineFn::<R>::drop_inline::<F>,
+        })
+    }
+
+    unsafe fn call_inline<F: FnOnce() -> R>(ptr: *mut u8) -> R {
+        (ptr as *mut F).read()()
+    }
+
+    unsafe fn drop_inline<F>(ptr: *mut u8) {
+        std::ptr::drop_in_place(ptr as *mut F);
+    }
+
+    fn eval(self) -> R {
+        let mut this = ManuallyDrop::new(self);
+        unsafe { (this.call)(this.storage.as_mut_ptr() as *mut u8) }
+    }
+}
+
+impl<R> Drop for InlineFn<R> {
+    fn drop(&mut self) {
+        unsafe { (self.drop)(self.storage.as_mut_ptr() as *mut u8) }
+    }
 }
 
 impl LazyMessage {
@@ -730,12 +1966,163 @@ impl LazyMessage {
     where
         F: (FnOnce() -> String) + Send + 'static,
     {
-        LazyMessage {
-            data: Box::new(data),
-        }
+        LazyMessage::with_level(0, data)
+    }
+
+    /// Creates a message tagged with its level, which is used by the overflow policy
+    pub fn with_level<F>(level: usize, data: F) -> LazyMessage
+    where
+        F: (FnOnce() -> String) + Send + 'static,
+    {
+        let data = match InlineFn::new(data) {
+            Ok(inline) => LazyData::Inline(inline),
+            Err(data) => LazyData::Boxed(Box::new(data)),
+        };
+        LazyMessage { level, data }
+    }
+
+    /// Creates a message whose closure builds a structured record, formatted by the logger's `Formatter`
+    pub fn record<F>(level: usize, data: F) -> LazyMessage
+    where
+        F: (FnOnce() -> LogRecord) + Send + 'static,
+    {
+        let data = match InlineFn::new(data) {
+            Ok(inline) => LazyData::InlineRecord(inline),
+            Err(data) => LazyData::BoxedRecord(Box::new(data)),
+        };
+        LazyMessage { level, data }
+    }
+
+    #[inline]
+    pub fn level(&self) -> usize {
+        self.level
     }
 
+    /// Returns true if the closure is stored without a heap allocation
+    pub fn is_inline(&self) -> bool {
+        matches!(self.data, LazyData::Inline(_) | LazyData::InlineRecord(_))
+    }
+
+    /// Evaluates the message, formatting records with the logger's current formatter
     pub fn eval(self) -> String {
-        (self.data)()
+        let formatter = FORMATTER.lock().expect("Failed to lock FORMATTER").clone();
+        self.format(&*formatter)
+    }
+
+    /// Evaluates the message, formatting records with `formatter`.
+    /// Messages built from a plain string closure are returned as they are.
+    pub fn format(self, formatter: &dyn Formatter) -> String {
+        match self.evaluate() {
+            Evaluated::Text(_, text) => text,
+            Evaluated::Record(record) => {
+                format_to_string(formatter, &record.as_record())
+            }
+        }
+    }
+
+    pub(crate) fn evaluate(self) -> Evaluated {
+        match self.data {
+            LazyData::Inline(inline) => Evaluated::Text(self.level, inline.eval()),
+            LazyData::Boxed(data) => Evaluated::Text(self.level, data()),
+            LazyData::InlineRecord(inline) => Evaluated::Record(inline.eval()),
+            LazyData::BoxedRecord(data) => Evaluated::Record(data()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lazy_message_inline_and_boxed() {
+        let small = [1u64; 4];
+        let msg = LazyMessage::new(move || format!("{:?}", small));
+        assert!(msg.is_inline());
+        assert_eq!(msg.eval(), "[1, 1, 1, 1]");
+
+        let large = [2u64; 32];
+        let msg = LazyMessage::new(move || format!("{}", large.iter().sum::<u64>()));
+        assert!(!msg.is_inline());
+        assert_eq!(msg.eval(), "64");
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_deprecated_struct_arms_expand() {
+        // the struct arms of the deprecated macros are only compiled where they are used
+        let value = serde_json::json!({ "venue": "KRX" });
+        crate::log_info!("deprecated", value);
+        crate::flushing_log_info!("deprecated", value);
+    }
+
+    #[test]
+    fn test_with_vars() {
+        let dir = std::env::temp_dir().join("flashlog_test_with_vars");
+        let _ = std::fs::remove_dir_all(&dir);
+        let vars = |vars: Vec<(&'static str, &str)>| {
+            let vars: Vec<(&str, String)> = vars.into_iter().map(|(name, value)| (name, value.to_string())).collect();
+            move |name: &str| vars.iter().find(|(n, _)| *n == name).map(|(_, value)| value.clone())
+        };
+
+        let logger = Logger::initialize().with_vars(vars(vec![])).unwrap();
+        assert!(logger.file_config.is_none());
+
+        let env = vec![("FLASHLOG_DIR", dir.to_str().unwrap()), ("FLASHLOG_ROLL", " Hourly ")];
+        let logger = Logger::initialize().with_vars(vars(env)).unwrap();
+        let config = logger.file_config.unwrap();
+        assert_eq!(config.base_path, dir);
+        assert_eq!(config.file_name_prefix, "message");
+        assert!(matches!(config.roll_period, Some(RollingPeriod::Hourly)));
+        assert!(dir.is_dir());
+
+        // the prefix alone keeps the configured directory
+        let logger = Logger::initialize()
+            .with_file(dir.to_str().unwrap(), "message")
+            .unwrap()
+            .with_vars(vars(vec![("FLASHLOG_PREFIX", "orders"), ("FLASHLOG_DIR", "")]))
+            .unwrap();
+        let config = logger.file_config.unwrap();
+        assert_eq!(config.base_path, dir);
+        assert_eq!(config.file_name_prefix, "orders");
+
+        for env in [
+            ("FLASHLOG_ROLL", "yearly"),
+            ("FLASHLOG_CONSOLE", "maybe"),
+            ("FLASHLOG_CORE", "first"),
+            ("FLASHLOG_LEVEL", "orders=loud"),
+        ] {
+            assert!(matches!(Logger::initialize().with_vars(vars(vec![env])), Err(LoggerError::InvalidEnvVar(_))));
+        }
+        assert!(matches!(
+            Logger::initialize().with_vars(vars(vec![("FLASHLOG_ROLL", "daily")])),
+            Err(LoggerError::UnsetFile)
+        ));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_overflow_policy_round_trip() {
+        for policy in [
+            OverflowPolicy::Block,
+            OverflowPolicy::DropNewest,
+            OverflowPolicy::DropOldest,
+            OverflowPolicy::DropBelowLevel(LogLevel::Error),
+            OverflowPolicy::DropBelowLevel(LogLevel::Trace),
+        ] {
+            assert_eq!(OverflowPolicy::from_usize(policy.as_usize()), policy);
+        }
+    }
+
+    #[test]
+    fn test_lazy_message_drop_without_eval() {
+        let counter = Arc::new(());
+        let captured = counter.clone();
+        let msg = LazyMessage::new(move || format!("{:?}", captured));
+        assert!(msg.is_inline());
+        assert_eq!(Arc::strong_count(&counter), 2);
+        drop(msg);
+        assert_eq!(Arc::strong_count(&counter), 1);
     }
 }
\ No newline at end of file
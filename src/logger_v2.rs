@@ -1,7 +1,8 @@
 #[macro_export]
 macro_rules! flush {
     () => {{
-        $crate::LOG_SENDER.try_send($crate::LogMessage::Flush).expect("Failed to send flush message");
+        $crate::logger::flush_thread_batch();
+        let _ = $crate::LOG_SENDER.send($crate::LogMessage::Flush);
     }};
 }
 
@@ -119,249 +120,138 @@ macro_rules! log_fn_json_v2 {
     // Case 1: topic, format sring, kv
     ($level:expr, $topic:expr; $fmt:expr, $($arg:expr),*; $($key:ident = $value:expr),+ $(,)?) => {{
         if $level <= $crate::LogLevel::from_usize($crate::MAX_LOG_LEVEL.load(std::sync::atomic::Ordering::Relaxed)).expect("Invalid log level") {
+            let callsite_time = $crate::timer::CallsiteTime::now();
             let func = move || {
-                let unixnano = $crate::get_unix_nano();
-                let include_unixnano = $crate::logger::INCLUDE_UNIXNANO.load(std::sync::atomic::Ordering::Relaxed);
-                let json_obj = $crate::serde_json::json!({
-                    $(
-                        stringify!($key): $value,
-                    )+
-                });
-                let timezone = $crate::TIMEZONE.load(std::sync::atomic::Ordering::Relaxed);
-                let (date, time) = $crate::convert_unix_nano_to_date_and_time(unixnano, timezone);
-                
-                let json_msg = match include_unixnano {
-                    false => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $level.to_string(),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": $topic,
-                        "data": json_obj,
-                        "message": format!($fmt, $($arg),*),
-                    }),
-                    true => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $level.to_string(),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": $topic,
-                        "data": json_obj,
-                        "message": format!($fmt, $($arg),*),
-                        "unixnano": unixnano,
-                    }),
-                };
-
-                json_msg.to_string() + "\n"
+                let (unixnano, queue_latency) = callsite_time.resolve();
+                $crate::LogRecord {
+                    level: $level.as_usize(),
+                    topic: $topic.to_string(),
+                    message: format!($fmt, $($arg),*),
+                    data: Some(vec![$((stringify!($key), $crate::serde_json::json!($value)),)+]),
+                    file: file!(),
+                    line: line!(),
+                    module_path: module_path!(),
+                    unixnano,
+                    queue_latency,
+                }
             };
 
-            $crate::LOG_SENDER.try_send($crate::LogMessage::LazyMessage($crate::LazyMessage::new(func))).expect("Failed to send log message");
+            $crate::LOG_SENDER.send_log($crate::LogMessage::LazyMessage($crate::LazyMessage::record($level.as_usize(), func)));
         }
     }};
     
     // Case 2: topic, static string, kv
     ($level:expr, $topic:expr; $msg:expr; $($key:ident = $value:expr),+ $(,)?) => {{
         if $level <= $crate::LogLevel::from_usize($crate::MAX_LOG_LEVEL.load(std::sync::atomic::Ordering::Relaxed)).expect("Invalid log level") {
+            let callsite_time = $crate::timer::CallsiteTime::now();
             let func = move || {
-                let unixnano = $crate::get_unix_nano();
-                let include_unixnano = $crate::logger::INCLUDE_UNIXNANO.load(std::sync::atomic::Ordering::Relaxed);
-                let json_obj = $crate::serde_json::json!({
-                    $(
-                        stringify!($key): $value,
-                    )+
-                });
-                let timezone = $crate::TIMEZONE.load(std::sync::atomic::Ordering::Relaxed);
-                let (date, time) = $crate::convert_unix_nano_to_date_and_time(unixnano, timezone);
-                let json_msg = match include_unixnano {
-                    false => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $level.to_string(),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": $topic,
-                        "data": json_obj,
-                        "message": $msg,
-                    }),
-                    true => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $level.to_string(),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": $topic,
-                        "data": json_obj,
-                        "message": $msg,
-                        "unixnano": unixnano,
-                    }),
-                };
-
-                json_msg.to_string() + "\n"
+                let (unixnano, queue_latency) = callsite_time.resolve();
+                $crate::LogRecord {
+                    level: $level.as_usize(),
+                    topic: $topic.to_string(),
+                    message: $msg.to_string(),
+                    data: Some(vec![$((stringify!($key), $crate::serde_json::json!($value)),)+]),
+                    file: file!(),
+                    line: line!(),
+                    module_path: module_path!(),
+                    unixnano,
+                    queue_latency,
+                }
             };
 
-            $crate::LOG_SENDER.try_send($crate::LogMessage::LazyMessage($crate::LazyMessage::new(func))).expect("Failed to send log message");
+            $crate::LOG_SENDER.send_log($crate::LogMessage::LazyMessage($crate::LazyMessage::record($level.as_usize(), func)));
         }
     }};
     
     // Case 3: topic and formated string
     ($level:expr, $topic:expr; $fmt:expr, $($arg:expr),* $(,)?) => {{
         if $level <= $crate::LogLevel::from_usize($crate::MAX_LOG_LEVEL.load(std::sync::atomic::Ordering::Relaxed)).expect("Invalid log level") {
+            let callsite_time = $crate::timer::CallsiteTime::now();
             let func = move || {
-                let unixnano = $crate::get_unix_nano();
-                let include_unixnano = $crate::logger::INCLUDE_UNIXNANO.load(std::sync::atomic::Ordering::Relaxed);
-                let timezone = $crate::TIMEZONE.load(std::sync::atomic::Ordering::Relaxed);
-                let (date, time) = $crate::convert_unix_nano_to_date_and_time(unixnano, timezone);
-                let json_msg = match include_unixnano {
-                    false => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $level.to_string(),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": $topic,
-                        "message": format!($fmt, $($arg),*),
-                        "data": "",
-                    }),
-                    true => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $level.to_string(),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": $topic,
-                        "message": format!($fmt, $($arg),*),
-                        "data": "",
-                        "unixnano": unixnano,
-                    }),
-                };
-                json_msg.to_string() + "\n"
+                let (unixnano, queue_latency) = callsite_time.resolve();
+                $crate::LogRecord {
+                    level: $level.as_usize(),
+                    topic: $topic.to_string(),
+                    message: format!($fmt, $($arg),*),
+                    data: Some(Vec::new()),
+                    file: file!(),
+                    line: line!(),
+                    module_path: module_path!(),
+                    unixnano,
+                    queue_latency,
+                }
             };
-            $crate::LOG_SENDER.try_send($crate::LogMessage::LazyMessage($crate::LazyMessage::new(func))).expect("Failed to send log message");
+
+            $crate::LOG_SENDER.send_log($crate::LogMessage::LazyMessage($crate::LazyMessage::record($level.as_usize(), func)));
         }
     }};
     
     // Case 4: topic and static string
     ($level:expr, $topic:expr; $msg:expr $(,)?) => {{
         if $level <= $crate::LogLevel::from_usize($crate::MAX_LOG_LEVEL.load(std::sync::atomic::Ordering::Relaxed)).expect("Invalid log level") {
+            let callsite_time = $crate::timer::CallsiteTime::now();
             let func = move || {
-                let unixnano = $crate::get_unix_nano();
-                let include_unixnano = $crate::logger::INCLUDE_UNIXNANO.load(std::sync::atomic::Ordering::Relaxed);
-                let timezone = $crate::TIMEZONE.load(std::sync::atomic::Ordering::Relaxed);
-                let (date, time) = $crate::convert_unix_nano_to_date_and_time(unixnano, timezone);
-                let json_msg = match include_unixnano {
-                    false => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $level.to_string(),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": $topic.to_string(),
-                        "message": $msg
-                    }),
-                    true => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $level.to_string(),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": $topic.to_string(),
-                        "message": $msg,
-                        "unixnano": unixnano,
-                    }),
-                };
-
-                json_msg.to_string() + "\n"
+                let (unixnano, queue_latency) = callsite_time.resolve();
+                $crate::LogRecord {
+                    level: $level.as_usize(),
+                    topic: $topic.to_string(),
+                    message: $msg.to_string(),
+                    data: None,
+                    file: file!(),
+                    line: line!(),
+                    module_path: module_path!(),
+                    unixnano,
+                    queue_latency,
+                }
             };
 
-            $crate::LOG_SENDER.try_send($crate::LogMessage::LazyMessage($crate::LazyMessage::new(func))).expect("Failed to send log message");
+            $crate::LOG_SENDER.send_log($crate::LogMessage::LazyMessage($crate::LazyMessage::record($level.as_usize(), func)));
         }
     }};
 
     // **Case 7: Single key-value pair without topic**
     ($level:expr, $key:ident = $value:expr) => {{
         if $level <= $crate::LogLevel::from_usize($crate::MAX_LOG_LEVEL.load(std::sync::atomic::Ordering::Relaxed)).expect("Invalid log level") {
+            let callsite_time = $crate::timer::CallsiteTime::now();
             let func = move || {
-                let unixnano = $crate::get_unix_nano();
-                let include_unixnano = $crate::logger::INCLUDE_UNIXNANO.load(std::sync::atomic::Ordering::Relaxed);
-                let json_obj = $crate::serde_json::json!({
-                    $(
-                        stringify!($key): $value,
-                    )+
-                });
-                let timezone = $crate::TIMEZONE.load(std::sync::atomic::Ordering::Relaxed);
-                let (date, time) = $crate::convert_unix_nano_to_date_and_time(unixnano, timezone);
-                let json_msg = match include_unixnano {
-                    false => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $level.to_string(),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": "",
-                        "data": json_obj,
-                        "message": "",
-                    }),
-                    true => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $level.to_string(),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "topic": "",
-                        "data": json_obj,
-                        "message": "",
-                        "unixnano": unixnano,
-                    }),
-                };
-                json_msg.to_string() + "\n"
+                let (unixnano, queue_latency) = callsite_time.resolve();
+                $crate::LogRecord {
+                    level: $level.as_usize(),
+                    topic: String::new(),
+                    message: String::new(),
+                    data: Some(vec![(stringify!($key), $crate::serde_json::json!($value))]),
+                    file: file!(),
+                    line: line!(),
+                    module_path: module_path!(),
+                    unixnano,
+                    queue_latency,
+                }
             };
 
-            $crate::LOG_SENDER.try_send($crate::LogMessage::LazyMessage($crate::LazyMessage::new(func))).expect("Failed to send log message");
+            $crate::LOG_SENDER.send_log($crate::LogMessage::LazyMessage($crate::LazyMessage::record($level.as_usize(), func)));
         }
     }};
     
     // **Case 8: Multiple key-value pairs without topic**
     ($level:expr, $($key:ident = $value:expr),+ $(,)?) => {{
         if $level <= $crate::LogLevel::from_usize($crate::MAX_LOG_LEVEL.load(std::sync::atomic::Ordering::Relaxed)).expect("Invalid log level") {
+            let callsite_time = $crate::timer::CallsiteTime::now();
             let func = move || {
-                let unixnano = $crate::get_unix_nano();
-                let include_unixnano = $crate::logger::INCLUDE_UNIXNANO.load(std::sync::atomic::Ordering::Relaxed);
-                let json_obj = $crate::serde_json::json!({
-                    $(
-                        stringify!($key): $value,
-                    )+
-                });
-                let timezone = $crate::TIMEZONE.load(std::sync::atomic::Ordering::Relaxed);
-                let (date, time) = $crate::convert_unix_nano_to_date_and_time(unixnano, timezone);
-                let json_msg = match include_unixnano {
-                    false => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $level.to_string(),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "data": json_obj,
-                        "topic": "",
-                        "message": "",
-                    }),
-                    true => $crate::serde_json::json!({
-                        "date": date,
-                        "time": time,
-                        "offset": timezone,
-                        "level": $level.to_string(),
-                        "src": format!("{}:{}", file!(), line!()),
-                        "data": json_obj,
-                        "topic": "",
-                        "message": "",
-                        "unixnano": unixnano,
-                    }),
-                };
-                json_msg.to_string() + "\n"
+                let (unixnano, queue_latency) = callsite_time.resolve();
+                $crate::LogRecord {
+                    level: $level.as_usize(),
+                    topic: String::new(),
+                    message: String::new(),
+                    data: Some(vec![$((stringify!($key), $crate::serde_json::json!($value)),)+]),
+                    file: file!(),
+                    line: line!(),
+                    module_path: module_path!(),
+                    unixnano,
+                    queue_latency,
+                }
             };
 
-            $crate::LOG_SENDER.try_send($crate::LogMessage::LazyMessage($crate::LazyMessage::new(func))).expect("Failed to send log message");
+            $crate::LOG_SENDER.send_log($crate::LogMessage::LazyMessage($crate::LazyMessage::record($level.as_usize(), func)));
         }
     }};
 }
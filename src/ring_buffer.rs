@@ -0,0 +1,323 @@
+use crossbeam_utils::{Backoff, CachePadded};
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+struct Slot<T> {
+    sequence: AtomicUsize,
+    evictable: AtomicBool,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// Bounded lock-free ring buffer used as the log transport.
+///
+/// The slots are allocated once in `with_capacity` and reused afterwards, so pushing and popping
+/// never touch the allocator. Each slot carries a sequence number (Vyukov's bounded queue), which makes
+/// the buffer safe for any number of producers and consumers. With a single producer the
+/// compare-and-swap on `tail` is never contended, so the SPSC case pays no extra cost.
+pub struct RingBuffer<T> {
+    buffer: Box<[Slot<T>]>,
+    mask: usize,
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+    closed: AtomicBool,
+    evictable: fn(&T) -> bool,
+    /// Set while the consumer in `pop_wait` is about to park or parked
+    waiting: AtomicBool,
+    consumer: OnceLock<Thread>,
+}
+
+unsafe impl<T: Send> Send for RingBuffer<T> {}
+unsafe impl<T: Send> Sync for RingBuffer<T> {}
+
+impl<T> RingBuffer<T> {
+    /// Creates a ring buffer. The capacity is rounded up to the next power of two (minimum 2).
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_eviction(capacity, |_| true)
+    }
+
+    /// Creates a ring buffer where only values accepted by `evictable` can be taken by `pop_evictable`.
+    pub fn with_eviction(capacity: usize, evictable: fn(&T) -> bool) -> Self {
+        let capacity = capacity.max(2).next_power_of_two();
+        let buffer = (0..capacity)
+            .map(|i| Slot {
+                sequence: AtomicUsize::new(i),
+                evictable: AtomicBool::new(false),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+
+        RingBuffer {
+            buffer,
+            mask: capacity - 1,
+            head: CachePadded::new(AtomicUsize::new(0)),
+            tail: CachePadded::new(AtomicUsize::new(0)),
+            closed: AtomicBool::new(false),
+            evictable,
+            waiting: AtomicBool::new(false),
+            consumer: OnceLock::new(),
+        }
+    }
+
+    /// Pushes a value. Returns the value back if the buffer is full.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let evictable = (self.evictable)(&value);
+        let mut tail = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.buffer[tail & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let diff = sequence as isize - tail as isize;
+
+            if diff == 0 {
+                match self.tail.compare_exchange_weak(tail, tail.wrapping_add(1), Ordering::Relaxed, Ordering::Relaxed) {
+                    Ok(_) => {
+                        unsafe { (*slot.value.get()).write(value); }
+                        slot.evictable.store(evictable, Ordering::Relaxed);
+                        slot.sequence.store(tail.wrapping_add(1), Ordering::Release);
+                        self.wake();
+                        return Ok(());
+                    }
+                    Err(current) => tail = current,
+                }
+            } else if diff < 0 {
+                return Err(value);
+            } else {
+                tail = self.tail.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Pops the oldest value, or `None` if the buffer is empty.
+    pub fn pop(&self) -> Option<T> {
+        self.pop_when(false)
+    }
+
+    /// Pops the oldest value if it is evictable. Returns `None` if the buffer is empty or the oldest
+    /// value must stay in place; the value is never taken out and put back, so the order is kept.
+    pub fn pop_evictable(&self) -> Option<T> {
+        self.pop_when(true)
+    }
+
+    fn pop_when(&self, evictable_only: bool) -> Option<T> {
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.buffer[head & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let diff = sequence as isize - head.wrapping_add(1) as isize;
+
+            if diff == 0 {
+                // the flag is written before the sequence is released; if the slot is popped and
+                // refilled after this check, `head` has moved and the exchange below fails
+                if evictable_only && !slot.evictable.load(Ordering::Relaxed) {
+                    return None;
+                }
+                match self.head.compare_exchange_weak(head, head.wrapping_add(1), Ordering::Relaxed, Ordering::Relaxed) {
+                    Ok(_) => {
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+                        slot.sequence.store(head.wrapping_add(self.mask + 1), Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(current) => head = current,
+                }
+            } else if diff < 0 {
+                return None;
+            } else {
+                head = self.head.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Pops the oldest value, parking the calling thread while the buffer is empty.
+    /// Producers unpark it when they push; `backstop` only bounds a single park.
+    /// Returns `None` once the buffer is closed and drained. Meant for a single consumer thread.
+    pub fn pop_wait(&self, backstop: Duration) -> Option<T> {
+        let backoff = Backoff::new();
+        loop {
+            if let Some(value) = self.pop() {
+                return Some(value);
+            }
+            if self.is_closed() {
+                // a value pushed just before closing is still delivered
+                return self.pop();
+            }
+            if !backoff.is_completed() {
+                backoff.snooze();
+                continue;
+            }
+
+            self.consumer.get_or_init(thread::current);
+            self.waiting.store(true, Ordering::Relaxed);
+            // pairs with the fence in `wake`: either the producer sees `waiting` or this pop sees its value
+            fence(Ordering::SeqCst);
+            if let Some(value) = self.pop() {
+                self.waiting.store(false, Ordering::Relaxed);
+                return Some(value);
+            }
+            if !self.is_closed() {
+                thread::park_timeout(backstop);
+            }
+            self.waiting.store(false, Ordering::Relaxed);
+        }
+    }
+
+    #[inline]
+    fn wake(&self) {
+        fence(Ordering::SeqCst);
+        if self.waiting.load(Ordering::Relaxed) {
+            if let Some(consumer) = self.consumer.get() {
+                consumer.unpark();
+            }
+        }
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.mask + 1
+    }
+
+    /// Number of values currently in the buffer. This is a snapshot and may be stale under contention.
+    #[inline]
+    pub fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+        tail.wrapping_sub(head).min(self.capacity())
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Marks the buffer as closed. Used by the logger thread on shutdown so senders stop waiting for space.
+    /// A consumer waiting in `pop_wait` returns once the remaining values are popped.
+    #[inline]
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.wake();
+    }
+
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Drop for RingBuffer<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_push_pop_order() {
+        let ring = RingBuffer::with_capacity(4);
+        assert_eq!(ring.capacity(), 4);
+        for i in 0..4 {
+            ring.push(i).unwrap();
+        }
+        assert_eq!(ring.push(4), Err(4));
+        assert_eq!(ring.len(), 4);
+        for i in 0..4 {
+            assert_eq!(ring.pop(), Some(i));
+        }
+        assert_eq!(ring.pop(), None);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn test_multi_producer() {
+        let ring = Arc::new(RingBuffer::with_capacity(1024));
+        let producers: Vec<_> = (0..4)
+            .map(|p| {
+                let ring = ring.clone();
+                std::thread::spawn(move || {
+                    for i in 0..10_000u64 {
+                        let mut value = p * 1_000_000 + i;
+                        while let Err(v) = ring.push(value) {
+                            value = v;
+                            std::thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let mut last = [None::<u64>; 4];
+        let mut received = 0;
+        while received < 40_000 {
+            if let Some(value) = ring.pop() {
+                let producer = (value / 1_000_000) as usize;
+                let seq = value % 1_000_000;
+                // per-producer order must be preserved
+                assert!(last[producer].is_none_or(|prev| prev < seq));
+                last[producer] = Some(seq);
+                received += 1;
+            }
+        }
+
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn test_pop_evictable_keeps_pinned_head() {
+        let ring = RingBuffer::with_eviction(4, |value: &i32| *value >= 0);
+        ring.push(1).unwrap();
+        ring.push(-1).unwrap();
+        ring.push(2).unwrap();
+
+        assert_eq!(ring.pop_evictable(), Some(1));
+        assert_eq!(ring.pop_evictable(), None);
+        assert_eq!(ring.len(), 2);
+        assert_eq!(ring.pop(), Some(-1));
+        assert_eq!(ring.pop_evictable(), Some(2));
+        assert_eq!(ring.pop_evictable(), None);
+    }
+
+    #[test]
+    fn test_pop_wait_is_woken_by_push_and_close() {
+        let ring = Arc::new(RingBuffer::with_capacity(4));
+        let consumer = {
+            let ring = ring.clone();
+            std::thread::spawn(move || {
+                // the backstop is far longer than the test, so only an unpark can wake the consumer in time
+                let first = ring.pop_wait(Duration::from_secs(60));
+                let second = ring.pop_wait(Duration::from_secs(60));
+                (first, second)
+            })
+        };
+
+        std::thread::sleep(Duration::from_millis(50));
+        let start = std::time::Instant::now();
+        ring.push(7).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        ring.close();
+
+        assert_eq!(consumer.join().unwrap(), (Some(7), None));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_drop_remaining() {
+        let counter = Arc::new(());
+        {
+            let ring = RingBuffer::with_capacity(8);
+            for _ in 0..5 {
+                ring.push(counter.clone()).unwrap();
+            }
+            assert_eq!(Arc::strong_count(&counter), 6);
+        }
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+}
@@ -1,11 +1,18 @@
+use crate::compression::{self, Compression};
+use crate::logger::{internal_record, queue_internal_record, LogLevel, LOG_SENDER};
+use crate::timezone::{current_zone, Zone};
 use crate::{get_unix_nano, UnixNano};
 use std::path::{Path, PathBuf};
-use std::fs::{File, OpenOptions, remove_file};
+use std::fs::{File, OpenOptions, remove_file, rename};
 use std::io::{self, BufWriter, Write};
-use std::sync::OnceLock;
-use chrono::Local;
+use std::sync::{OnceLock, RwLock};
+use std::time::{Duration, SystemTime};
+use chrono::{DateTime, FixedOffset};
+use serde::Deserialize;
+use once_cell::sync::Lazy;
 
 static INITIAL_LOG_FILE_PATH: OnceLock<PathBuf> = OnceLock::new();
+static CURRENT_LOG_FILE_PATH: RwLock<Option<PathBuf>> = RwLock::new(None);
 
 /// Returns the initial log file path if file logging is enabled.
 /// Returns `None` if file logging is not configured.
@@ -13,20 +20,51 @@ pub fn get_initial_log_file_path() -> Option<PathBuf> {
     INITIAL_LOG_FILE_PATH.get().cloned()
 }
 
+/// Returns the path of the log file being written, updated when the file rolls.
+/// Returns `None` if file logging is not configured.
+pub fn current_log_file_path() -> Option<PathBuf> {
+    CURRENT_LOG_FILE_PATH.read().expect("Failed to read CURRENT_LOG_FILE_PATH").clone()
+}
+
+fn set_current_log_file_path(file_path: &Path) {
+    *CURRENT_LOG_FILE_PATH.write().expect("Failed to write CURRENT_LOG_FILE_PATH") = Some(file_path.to_path_buf());
+}
+
 /// Generates and stores the initial log file path. Called by Logger::launch().
-pub(crate) fn set_initial_log_file_path(base_path: &Path, prefix: &str) -> PathBuf {
-    let file_path = RollingFileWriter::generate_file_path(base_path, prefix);
+/// Returns `None` if the file name pattern is invalid.
+pub(crate) fn set_initial_log_file_path(config: &RollingConfig) -> Option<PathBuf> {
+    let file_path = RollingFileWriter::next_file_path(config).ok()?;
     let _ = INITIAL_LOG_FILE_PATH.set(file_path.clone());
-    file_path
+    set_current_log_file_path(&file_path);
+    Some(file_path)
 }
 
+/// Extension of JSON log files
+pub const DEFAULT_FILE_EXTENSION: &str = "log";
+/// `{prefix}-{period}`, e.g. `message-20240915` for daily files
+pub const DEFAULT_FILE_NAME_PATTERN: &str = "{prefix}-{period}";
+
+static HOSTNAME: Lazy<String> = Lazy::new(|| {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+});
+
 const SECOND_IN_NANOS: u64 = 1_000_000_000;
 const MINUATE_IN_NANOS: u64 = 60_000_000_000;
 const HOUR_IN_NANOS: u64 = 3_600_000_000_000;
 const DAY_IN_NANOS: u64 = 86_400_000_000_000;
 const WEEK_IN_NANOS: u64 = 604_800_000_000_000;
 
-#[derive(Clone, Debug)]
+/// Files are rolled at the calendar boundaries of the period in the logger's timezone:
+/// the top of the second, minute or hour, midnight, or Monday 00:00.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum RollingPeriod {
     None,
     Secondly,
@@ -36,6 +74,74 @@ pub enum RollingPeriod {
     Weekly,
 }
 
+impl RollingPeriod {
+    #[inline]
+    fn as_nanos(&self) -> Option<u64> {
+        match self {
+            RollingPeriod::None => None,
+            RollingPeriod::Secondly => Some(SECOND_IN_NANOS),
+            RollingPeriod::Minutely => Some(MINUATE_IN_NANOS),
+            RollingPeriod::Hourly => Some(HOUR_IN_NANOS),
+            RollingPeriod::Daily => Some(DAY_IN_NANOS),
+            RollingPeriod::Weekly => Some(WEEK_IN_NANOS),
+        }
+    }
+
+    /// Start and end of the period containing `now`, aligned to the calendar of `zone`. A period across a daylight
+    /// saving time transition is shorter or longer, e.g. the day clocks move forward lasts 23 hours.
+    pub fn window(&self, now: UnixNano, zone: Zone) -> Option<(UnixNano, UnixNano)> {
+        let length = self.as_nanos()? as i64;
+        let offset_at = |time: i64| zone.utc_offset_at(time.max(0) as u64) as i64 * SECOND_IN_NANOS as i64;
+        let offset = offset_at(now as i64);
+        let local = now as i64 + offset;
+        let local_start = match self {
+            RollingPeriod::Weekly => {
+                // 1970-01-01 was a Thursday
+                let days = local.div_euclid(DAY_IN_NANOS as i64);
+                (days - (days + 3).rem_euclid(7)) * DAY_IN_NANOS as i64
+            }
+            _ => local - local.rem_euclid(length),
+        };
+        // a local time back to UTC, with the offset in force at that time
+        let to_utc = |local: i64| local - offset_at(local - offset_at(local - offset));
+        let (start, end) = (to_utc(local_start), to_utc(local_start + length));
+        if start <= now as i64 && (now as i64) < end {
+            Some((start as UnixNano, end as UnixNano))
+        } else {
+            // the boundary falls in a skipped or repeated local time
+            let start = local_start - offset;
+            Some((start as UnixNano, (start + length) as UnixNano))
+        }
+    }
+
+    /// Format of the window start in file names
+    fn label_format(&self) -> &'static str {
+        match self {
+            RollingPeriod::None | RollingPeriod::Secondly => "%Y%m%d-%H%M%S",
+            RollingPeriod::Minutely => "%Y%m%d-%H%M",
+            RollingPeriod::Hourly => "%Y%m%d-%H",
+            RollingPeriod::Daily | RollingPeriod::Weekly => "%Y%m%d",
+        }
+    }
+}
+
+impl std::str::FromStr for RollingPeriod {
+    type Err = &'static str;
+
+    /// Parses `none`, `secondly`, `minutely`, `hourly`, `daily` or `weekly`, in any case
+    fn from_str(period: &str) -> Result<RollingPeriod, &'static str> {
+        match period.to_ascii_lowercase().as_str() {
+            "none" => Ok(RollingPeriod::None),
+            "secondly" => Ok(RollingPeriod::Secondly),
+            "minutely" => Ok(RollingPeriod::Minutely),
+            "hourly" => Ok(RollingPeriod::Hourly),
+            "daily" => Ok(RollingPeriod::Daily),
+            "weekly" => Ok(RollingPeriod::Weekly),
+            _ => Err("Invalid rolling period"),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct RollingConfig {
     pub base_path: PathBuf,
@@ -43,8 +149,33 @@ pub struct RollingConfig {
     //
     pub roll_period: Option<RollingPeriod>,
     pub max_roll_files: Option<usize>,
+    /// Rolls when the current file would grow past this many bytes, in addition to the roll period.
+    /// The file names then carry a sequence number, since several files can be opened in the same second.
+    pub max_file_size: Option<u64>,
+    /// Removes rolled files (plain and compressed) last written longer ago than this
+    pub max_age: Option<Duration>,
+    /// Removes the oldest rolled files while the files of this config, the current one included, take more bytes
+    /// than this. The current file is never removed.
+    pub max_total_size: Option<u64>,
     //
+    /// Compresses rolled files beyond `max_roll_files` instead of removing them, in a background thread
     pub compress: bool,
+    pub compression: Compression,
+    /// Extension of the log files, also used to find old files when rotating
+    pub file_extension: String,
+    /// File name without the extension. Placeholders:
+    /// - `{prefix}`: `file_name_prefix`
+    /// - `{period}`: start of the roll period at its resolution, e.g. `20240915` (daily) or `20240915-20` (hourly)
+    /// - `{date}`, `{time}`: start of the roll period as `YYYYmmdd` and `HHMMSS`
+    /// - `{pid}`, `{hostname}`: process id and host name
+    /// - `{seq}`: sequence number from `000`, added as `-{seq}` if missing and the file size is limited
+    ///
+    /// Without a roll period, the times are those of file creation.
+    /// Only files matching the pattern are removed or compressed when rotating.
+    pub file_name_pattern: String,
+    /// Keeps a `{prefix}.current.{extension}` symbolic link to the file being written, e.g. `message.current.log`,
+    /// for tools following a fixed path. It is replaced atomically when the file rolls.
+    pub current_link: bool,
     /// Pre-generated file path (set by Logger::launch)
     pub(crate) initial_file_path: Option<PathBuf>,
 }
@@ -56,66 +187,356 @@ impl Default for RollingConfig {
             file_name_prefix: "log".to_string(),
             roll_period: None,
             max_roll_files: None,
+            max_file_size: None,
+            max_age: None,
+            max_total_size: None,
             compress: false,
+            compression: Compression::Gzip,
+            file_extension: DEFAULT_FILE_EXTENSION.to_string(),
+            file_name_pattern: DEFAULT_FILE_NAME_PATTERN.to_string(),
+            current_link: false,
             initial_file_path: None,
         }
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum NamePart {
+    Literal(String),
+    Prefix,
+    Period,
+    Date,
+    Time,
+    Pid,
+    Hostname,
+    Seq,
+}
+
+/// Parsed `RollingConfig::file_name_pattern`
+#[derive(Clone, Debug)]
+pub struct FileNamePattern {
+    parts: Vec<NamePart>,
+}
+
+impl FileNamePattern {
+    pub fn parse(pattern: &str) -> io::Result<FileNamePattern> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let mut parts = Vec::new();
+        let mut rest = pattern;
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                parts.push(NamePart::Literal(rest[..open].to_string()));
+            }
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| invalid(format!("Unclosed placeholder in file name pattern {:?}", pattern)))?;
+            let part = match &rest[open + 1..open + close] {
+                "prefix" => NamePart::Prefix,
+                "period" => NamePart::Period,
+                "date" => NamePart::Date,
+                "time" => NamePart::Time,
+                "pid" => NamePart::Pid,
+                "hostname" => NamePart::Hostname,
+                "seq" => NamePart::Seq,
+                other => return Err(invalid(format!("Unknown placeholder {{{}}} in file name pattern {:?}", other, pattern))),
+            };
+            parts.push(part);
+            rest = &rest[open + close + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(NamePart::Literal(rest.to_string()));
+        }
+        if parts.iter().any(|part| matches!(part, NamePart::Literal(literal) if literal.contains(['/', '\\', '}']))) {
+            return Err(invalid(format!("Invalid file name pattern {:?}", pattern)));
+        }
+        if parts.is_empty() {
+            return Err(invalid("Empty file name pattern".to_string()));
+        }
+        Ok(FileNamePattern { parts })
+    }
+
+    /// Adds `-{seq}` if the pattern has no sequence number
+    fn with_seq(mut self) -> FileNamePattern {
+        if !self.parts.contains(&NamePart::Seq) {
+            self.parts.push(NamePart::Literal("-".to_string()));
+            self.parts.push(NamePart::Seq);
+        }
+        self
+    }
+
+    fn render(&self, config: &RollingConfig, start: &DateTime<FixedOffset>, seq: usize) -> String {
+        let period = config.roll_period.clone().unwrap_or(RollingPeriod::None);
+        let mut name = String::new();
+        for part in &self.parts {
+            match part {
+                NamePart::Literal(literal) => name.push_str(literal),
+                NamePart::Prefix => name.push_str(&config.file_name_prefix),
+                NamePart::Period => name.push_str(&start.format(period.label_format()).to_string()),
+                NamePart::Date => name.push_str(&start.format("%Y%m%d").to_string()),
+                NamePart::Time => name.push_str(&start.format("%H%M%S").to_string()),
+                NamePart::Pid => name.push_str(&std::process::id().to_string()),
+                NamePart::Hostname => name.push_str(&HOSTNAME),
+                NamePart::Seq => name.push_str(&format!("{:03}", seq)),
+            }
+        }
+        name
+    }
+
+    /// Whether `stem` (a file name without its extension) could have been produced by the pattern
+    fn matches(&self, config: &RollingConfig, stem: &str) -> bool {
+        let period = config.roll_period.clone().unwrap_or(RollingPeriod::None);
+        // digits replaced by 'D', e.g. "DDDDDDDD-DD" for hourly periods
+        let shape = |format: &str| -> String {
+            DateTime::from_timestamp_nanos(0)
+                .format(format)
+                .to_string()
+                .chars()
+                .map(|c| if c.is_ascii_digit() { 'D' } else { c })
+                .collect()
+        };
+        let matchers: Vec<NameMatcher> = self
+            .parts
+            .iter()
+            .map(|part| match part {
+                NamePart::Literal(literal) => NameMatcher::Literal(literal.clone()),
+                NamePart::Prefix => NameMatcher::Literal(config.file_name_prefix.clone()),
+                NamePart::Hostname => NameMatcher::Literal(HOSTNAME.clone()),
+                NamePart::Period => NameMatcher::Shape(shape(period.label_format())),
+                NamePart::Date => NameMatcher::Shape(shape("%Y%m%d")),
+                NamePart::Time => NameMatcher::Shape(shape("%H%M%S")),
+                NamePart::Pid => NameMatcher::Digits(1),
+                NamePart::Seq => NameMatcher::Digits(3),
+            })
+            .collect();
+        NameMatcher::matches_all(&matchers, stem.as_bytes())
+    }
+}
+
+enum NameMatcher {
+    Literal(String),
+    /// 'D' stands for a digit, other characters for themselves
+    Shape(String),
+    /// At least this many digits
+    Digits(usize),
+}
+
+impl NameMatcher {
+    fn matches_all(matchers: &[NameMatcher], name: &[u8]) -> bool {
+        let Some((first, rest)) = matchers.split_first() else {
+            return name.is_empty();
+        };
+        match first {
+            NameMatcher::Literal(literal) => {
+                name.starts_with(literal.as_bytes()) && Self::matches_all(rest, &name[literal.len()..])
+            }
+            NameMatcher::Shape(shape) => {
+                name.len() >= shape.len()
+                    && shape.bytes().zip(name).all(|(s, c)| if s == b'D' { c.is_ascii_digit() } else { s == *c })
+                    && Self::matches_all(rest, &name[shape.len()..])
+            }
+            NameMatcher::Digits(min) => {
+                let digits = name.iter().take_while(|c| c.is_ascii_digit()).count();
+                (*min..=digits).rev().any(|len| Self::matches_all(rest, &name[len..]))
+            }
+        }
+    }
+}
+
 pub struct RollingFileWriter {
     config: RollingConfig,
+    pattern: FileNamePattern,
     current_file: Option<BufWriter<File>>,
-    rolling_nanos: Option<UnixNano>,
+    current_path: PathBuf,
+    /// Whether this is the JSON file set by `Logger::with_file`, whose path `current_log_file_path` returns
+    main_file: bool,
     max_roll_files: usize,
-    last_roll_time: UnixNano,
+    /// End of the current period, None if the file is not rolled by time
+    next_roll_time: Option<UnixNano>,
+    /// Bytes in the current file
+    current_size: u64,
 }
 
 impl RollingFileWriter {
     pub fn new(config: RollingConfig) -> io::Result<Self> {
-        let file_path = config.initial_file_path.clone()
-            .unwrap_or_else(|| Self::generate_file_path(&config.base_path, &config.file_name_prefix));
-        let _ = INITIAL_LOG_FILE_PATH.set(file_path.clone());
+        Self::open(config, true)
+    }
+
+    /// `main_file` is false for the files of additional sinks and binary files, which do not set the initial log file path
+    pub(crate) fn open(config: RollingConfig, main_file: bool) -> io::Result<Self> {
+        let pattern = Self::pattern(&config)?;
+        let file_path = match config.initial_file_path.clone() {
+            Some(file_path) => file_path,
+            None => Self::next_file_path(&config)?,
+        };
+        if main_file {
+            let _ = INITIAL_LOG_FILE_PATH.set(file_path.clone());
+            set_current_log_file_path(&file_path);
+        }
         let current_file = OpenOptions::new()
             .create(true)
             .append(true)
             .open(&file_path)?;
+        let current_size = current_file.metadata()?.len();
+        if config.current_link {
+            let _ = update_current_link(&config, &file_path);
+        }
+        // files left by previous runs are not kept longer than the limits allow
+        let _ = remove_expired_files(&config, &pattern, &file_path);
 
-        let last_roll_time = get_unix_nano();
         let max_roll_files = config.max_roll_files.unwrap_or(10);
-        let rolling_nanos = match config.roll_period {
-            Some(RollingPeriod::None) => None,
-            Some(RollingPeriod::Secondly) => Some(SECOND_IN_NANOS),
-            Some(RollingPeriod::Minutely) => Some(MINUATE_IN_NANOS),
-            Some(RollingPeriod::Hourly) => Some(HOUR_IN_NANOS),
-            Some(RollingPeriod::Daily) => Some(DAY_IN_NANOS),
-            Some(RollingPeriod::Weekly) => Some(WEEK_IN_NANOS),
-            None => None,
-        };
+        let next_roll_time = Self::next_roll_time(&config, get_unix_nano());
         Ok(Self {
             config,
+            pattern,
             current_file: Some(BufWriter::new(current_file)),
-            rolling_nanos,
+            current_path: file_path,
+            main_file,
             max_roll_files,
-            last_roll_time,
+            next_roll_time,
+            current_size,
+        })
+    }
+
+    /// Path of the file being written
+    pub fn current_path(&self) -> &Path {
+        &self.current_path
+    }
+
+    /// Flushes and closes the current file and opens its path again, e.g. after logrotate moved the file.
+    /// If the path cannot be opened, the previous file stays in use.
+    pub fn reopen(&mut self) -> io::Result<()> {
+        if let Some(ref mut current_file) = self.current_file {
+            current_file.flush()?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.current_path)?;
+        self.current_size = file.metadata()?.len();
+        self.current_file = Some(BufWriter::new(file));
+        Ok(())
+    }
+
+    fn next_roll_time(config: &RollingConfig, now: UnixNano) -> Option<UnixNano> {
+        let period = config.roll_period.as_ref()?;
+        period.window(now, current_zone()).map(|(_, end)| end)
+    }
+
+    /// File name pattern of `config`, with a sequence number if the file size is limited
+    fn pattern(config: &RollingConfig) -> io::Result<FileNamePattern> {
+        let pattern = FileNamePattern::parse(&config.file_name_pattern)?;
+        Ok(match config.max_file_size {
+            Some(_) => pattern.with_seq(),
+            None => pattern,
         })
     }
 
-    pub(crate) fn generate_file_path(base_path: &Path, prefix: &str) -> PathBuf {
-        let now = Local::now();
-        let timestamp = now.format("%Y%m%d-%H%M%S");
-        let file_name = format!("{}-{}.log", prefix, timestamp);
-        base_path.join(file_name)
+    /// Path of the file for the current period, e.g. `message-20240915.log` for daily files with the default
+    /// pattern. With `{seq}`, the first sequence number without a file (or its compressed file) is used.
+    pub(crate) fn next_file_path(config: &RollingConfig) -> io::Result<PathBuf> {
+        let pattern = Self::pattern(config)?;
+        let now = get_unix_nano();
+        let zone = current_zone();
+        let period = config.roll_period.clone().unwrap_or(RollingPeriod::None);
+        let start = period.window(now, zone).map_or(now, |(start, _)| start);
+        let timezone = FixedOffset::east_opt(zone.utc_offset_at(start)).unwrap_or(FixedOffset::east_opt(0).unwrap());
+        let start = DateTime::from_timestamp_nanos(start as i64).with_timezone(&timezone);
+
+        let mut seq = 0;
+        loop {
+            let file_name = format!("{}.{}", pattern.render(config, &start, seq), config.file_extension);
+            let path = config.base_path.join(file_name);
+            let compressed_path = config.compression.compressed_path(&path);
+            if !pattern.parts.contains(&NamePart::Seq) || (!path.exists() && !compressed_path.exists()) {
+                return Ok(path);
+            }
+            seq += 1;
+        }
     }
 
+    /// Writes a batch of lines. If the file size is limited, the batch is split between files at line boundaries.
     pub fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        self.write_resumable(data, &mut 0)
+    }
+
+    /// Like `write_all`, but skips the first `*written` bytes of `data` and adds the bytes it writes to `*written`,
+    /// also when it fails, e.g. while rolling in the middle of the batch. Calling it again with the same `data` and
+    /// `written` writes the rest without repeating any line.
+    pub fn write_resumable(&mut self, data: &[u8], written: &mut usize) -> io::Result<()> {
         if self.should_roll(None) {
             self.roll_file()?;
         }
 
-        if let Some(ref mut current_file) = self.current_file {
+        while let Some(max_file_size) = self.config.max_file_size {
+            let rest = &data[*written..];
+            let room = max_file_size.saturating_sub(self.current_size) as usize;
+            if rest.len() <= room {
+                break;
+            }
+            // write the lines that fit and continue in a new file
+            let fit = match rest[..room].iter().rposition(|b| *b == b'\n') {
+                Some(end) => end + 1,
+                // a line longer than the limit gets a file of its own
+                None if self.current_size == 0 => rest.iter().position(|b| *b == b'\n').map_or(rest.len(), |end| end + 1),
+                None => 0,
+            };
+            self.write_counted(&rest[..fit], written)?;
+            if *written == data.len() {
+                return Ok(());
+            }
+            self.roll_file()?;
+        }
+
+        self.write_counted(&data[*written..], written)
+    }
+
+    /// Writes `data` to the current file as it is, without rolling or splitting it.
+    /// On failure no part of `data` is left in the file, so a reader never finds a torn frame before the next one.
+    pub fn write_frame(&mut self, data: &[u8]) -> io::Result<()> {
+        let Some(ref mut current_file) = self.current_file else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no log file is open"));
+        };
+        // earlier frames go first; if they fail, `data` is not written at all and they stay buffered
+        if current_file.capacity() - current_file.buffer().len() < data.len() {
+            current_file.flush()?;
+        }
+        if data.len() < current_file.capacity() {
+            // only copied into the buffer
             current_file.write_all(data)?;
+        } else {
+            // too large for the buffer: written at once, and cut off again if only a part makes it
+            let start = current_file.get_ref().metadata()?.len();
+            if let Err(e) = current_file.get_mut().write_all(data) {
+                let _ = current_file.get_ref().set_len(start);
+                return Err(e);
+            }
         }
+        self.current_size += data.len() as u64;
+        Ok(())
+    }
 
+    /// Writes `data` and adds the bytes taken by the file to `written`, also when it fails partway.
+    /// The bytes go to the file itself rather than to the buffer, so that bytes counted as written cannot be lost
+    /// with a buffer that fails to flush later.
+    fn write_counted(&mut self, mut data: &[u8], written: &mut usize) -> io::Result<()> {
+        let Some(ref mut current_file) = self.current_file else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no log file is open"));
+        };
+        // frames buffered by `write_frame` go first
+        current_file.flush()?;
+        let file = current_file.get_mut();
+        while !data.is_empty() {
+            match file.write(data) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.current_size += n as u64;
+                    *written += n;
+                    data = &data[n..];
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
         Ok(())
     }
 
@@ -133,32 +554,48 @@ impl RollingFileWriter {
         Ok(())
     }
 
-    fn should_roll(&mut self, now: Option<UnixNano>) -> bool {
-        if self.rolling_nanos.is_none() {
-            return false;
+    /// Rolls to a new file if the rolling period has elapsed or writing `incoming` more bytes would exceed
+    /// the maximum file size. Returns true if a new file was opened.
+    pub fn roll_if_needed(&mut self, incoming: usize) -> io::Result<bool> {
+        if self.should_roll(None) || self.exceeds_max_size(incoming) {
+            self.roll_file()?;
+            Ok(true)
+        } else {
+            Ok(false)
         }
+    }
 
-        let now = now.unwrap_or(get_unix_nano());
-        let diff = now - self.last_roll_time;
+    /// A line larger than the limit is written to a file of its own rather than split
+    #[inline]
+    fn exceeds_max_size(&self, incoming: usize) -> bool {
+        match self.config.max_file_size {
+            Some(max_file_size) => self.current_size > 0 && self.current_size + incoming as u64 > max_file_size,
+            None => false,
+        }
+    }
 
-        if diff >= self.rolling_nanos.unwrap() {
-            self.last_roll_time = now;
-            true
-        } else {
-            false
+    /// Also true if the previous roll failed to open a file, so that the next write tries again
+    fn should_roll(&mut self, now: Option<UnixNano>) -> bool {
+        if self.current_file.is_none() {
+            return true;
+        }
+        match self.next_roll_time {
+            Some(next_roll_time) => now.unwrap_or_else(get_unix_nano) >= next_roll_time,
+            None => false,
         }
     }
 
     fn roll_file(&mut self) -> io::Result<()> {
-        // Flush and close current file
-        if let Some(ref mut current_file) = self.current_file.take() {
+        // Flush and close current file. If the flush fails, the file stays open with the buffered bytes.
+        if let Some(ref mut current_file) = self.current_file {
             current_file.flush()?;
         }
+        self.current_file = None;
         // Generate new file path
-        let new_file_path = Self::generate_file_path(&self.config.base_path, &self.config.file_name_prefix);
+        let new_file_path = Self::next_file_path(&self.config)?;
 
         // Rotate old files if needed
-        self.rotate_old_files()?;
+        self.rotate_old_files(&new_file_path)?;
 
         // open new file
         let new_file = OpenOptions::new()
@@ -166,89 +603,400 @@ impl RollingFileWriter {
             .append(true)
             .open(&new_file_path)?;
 
+        self.current_size = new_file.metadata()?.len();
         self.current_file = Some(BufWriter::new(new_file));
-        self.last_roll_time = get_unix_nano();
-
-
-        Ok(())
-    }
+        self.next_roll_time = Self::next_roll_time(&self.config, get_unix_nano());
+        if self.main_file {
+            set_current_log_file_path(&new_file_path);
+        }
+        if self.config.current_link {
+            // the link is a convenience, a failure does not stop logging
+            let _ = update_current_link(&self.config, &new_file_path);
+        }
+        self.current_path = new_file_path;
 
-    fn compress_file(&self, file_path: &Path) -> io::Result<()> {
-        let gz_path = file_path.with_extension("gz");
-        let input = std::fs::read(file_path)?;
-        let output = File::create(&gz_path)?;
-        let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::fast());
-        encoder.write_all(&input)?;
-        encoder.finish()?;
-        remove_file(file_path)?;
         Ok(())
     }
 
-    fn collect_log_files(&self) -> io::Result<Vec<PathBuf>> {
-        let mut files = Vec::new();
-        for entry in std::fs::read_dir(&self.config.base_path)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_file() {
-                if let Some(ext) = path.extension() {
-                    if ext == "log" {
-                        if let Some(file_name) = path.file_name() {
-                            if file_name.to_string_lossy().starts_with(&self.config.file_name_prefix) {
-                                files.push(path);
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        Ok(files)
-    }
-
-    fn collect_compressed_files(&self) -> io::Result<Vec<PathBuf>> {
-        let mut files = Vec::new();
-        for entry in std::fs::read_dir(&self.config.base_path)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_file() {
-                if let Some(ext) = path.extension() {
-                    if ext == "gz" {
-                        if let Some(file_name) = path.file_name() {
-                            if file_name.to_string_lossy().starts_with(&self.config.file_name_prefix) {
-                                files.push(path);
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        Ok(files)
-    }
-    
-    fn rotate_old_files(&self) -> io::Result<()> {
-        let mut log_files = self.collect_log_files()?;
-        log_files.sort_by(|a, b| b.cmp(a)); // Sort in reverse order
+    fn rotate_old_files(&self, current: &Path) -> io::Result<()> {
+        let mut log_files = collect_files(&self.config, &self.pattern, &self.config.file_extension)?;
         
         // Remove oldest files if we exceed max_roll_files
         while log_files.len() >= self.max_roll_files {
             if let Some(oldest_file) = log_files.pop() {
                 if self.config.compress {
-                    self.compress_file(&oldest_file)?;
+                    // compressed files are counted when they are complete
+                    let (config, pattern, max_roll_files) =
+                        (self.config.clone(), self.pattern.clone(), self.max_roll_files);
+                    compression::submit(oldest_file, self.config.compression, move || {
+                        let _ = remove_old_compressed_files(&config, &pattern, max_roll_files);
+                    });
                 } else {
-                    remove_file(oldest_file)?;
+                    remove_rolled_file(&oldest_file, "max_roll_files")?;
                 }
             }
         }
 
-        let mut gz_files = self.collect_compressed_files()?;
-        gz_files.sort_by(|a, b| b.cmp(a)); // Sort in reverse order
+        remove_old_compressed_files(&self.config, &self.pattern, self.max_roll_files)?;
+        remove_expired_files(&self.config, &self.pattern, current)
+    }
+}
+
+/// Link to the file being written, e.g. `message.current.log`, so that tools can follow a fixed path
+pub fn current_link_path(config: &RollingConfig) -> PathBuf {
+    config
+        .base_path
+        .join(format!("{}.current.{}", config.file_name_prefix, config.file_extension))
+}
 
-        // Remove oldest files if we exceed max_roll_files
-        while gz_files.len() >= self.max_roll_files {
-            if let Some(oldest_file) = gz_files.pop() {
-                remove_file(oldest_file)?;
+/// Points the `current` link at `file_path`. The link is created under a temporary name and renamed over the
+/// old one, so readers always find a link.
+fn update_current_link(config: &RollingConfig, file_path: &Path) -> io::Result<()> {
+    let link = current_link_path(config);
+    let temp_link = config
+        .base_path
+        .join(format!(".{}.current.{}.tmp", config.file_name_prefix, config.file_extension));
+    // relative to the directory, so the link survives moving it
+    let target = file_path.file_name().map_or_else(|| file_path.to_path_buf(), PathBuf::from);
+    let _ = remove_file(&temp_link);
+    symlink(&target, &temp_link)?;
+    rename(&temp_link, &link).inspect_err(|_| {
+        let _ = remove_file(&temp_link);
+    })
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Creating symbolic links needs a privilege or developer mode on Windows
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "symbolic links are not supported"))
+}
+
+/// File named by the pattern, found when rotating
+struct RolledFile {
+    path: PathBuf,
+    modified: SystemTime,
+    len: u64,
+}
+
+/// Extension of the compressed files of `config`, e.g. `log.gz`
+fn compressed_extension(config: &RollingConfig) -> String {
+    format!("{}.{}", config.file_extension, config.compression.extension())
+}
+
+/// Files named by the pattern with the given extension, which may have several parts (`log.gz`), in no particular
+/// order. Files being compressed are left out.
+fn rolled_files(config: &RollingConfig, pattern: &FileNamePattern, extension: &str) -> io::Result<Vec<RolledFile>> {
+    let suffix = format!(".{}", extension);
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(&config.base_path)? {
+        let entry = entry?;
+        let path = entry.path();
+        // the `current` link is not a file of its own
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let matched = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(&suffix))
+            .is_some_and(|stem| pattern.matches(config, stem));
+        if matched && !compression::is_pending(&path) {
+            let metadata = entry.metadata()?;
+            files.push(RolledFile { path, modified: metadata.modified()?, len: metadata.len() });
+        }
+    }
+    Ok(files)
+}
+
+/// Sorts newest first. Patterns do not need to sort by time, so the modification time comes first.
+fn sort_newest_first(files: &mut [RolledFile]) {
+    files.sort_by(|a, b| (b.modified, &b.path).cmp(&(a.modified, &a.path)));
+}
+
+/// Files named by the pattern with the given extension, newest first. Files being compressed are left out.
+fn collect_files(config: &RollingConfig, pattern: &FileNamePattern, extension: &str) -> io::Result<Vec<PathBuf>> {
+    let mut files = rolled_files(config, pattern, extension)?;
+    sort_newest_first(&mut files);
+    Ok(files.into_iter().map(|file| file.path).collect())
+}
+
+/// Removes the oldest compressed files beyond `max_roll_files`.
+/// Called by the logger thread when a file rolls and by the compression thread when a file is compressed.
+fn remove_old_compressed_files(config: &RollingConfig, pattern: &FileNamePattern, max_roll_files: usize) -> io::Result<()> {
+    let mut compressed_files = collect_files(config, pattern, &compressed_extension(config))?;
+    while compressed_files.len() >= max_roll_files {
+        if let Some(oldest_file) = compressed_files.pop() {
+            remove_rolled_file(&oldest_file, "max_roll_files")?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes the rolled files, plain and compressed, beyond `max_age` and then beyond `max_total_size`.
+/// `current` is the file being written, which is never removed.
+fn remove_expired_files(config: &RollingConfig, pattern: &FileNamePattern, current: &Path) -> io::Result<()> {
+    if config.max_age.is_none() && config.max_total_size.is_none() {
+        return Ok(());
+    }
+    let mut files = rolled_files(config, pattern, &config.file_extension)?;
+    files.extend(rolled_files(config, pattern, &compressed_extension(config))?);
+    files.retain(|file| file.path != current);
+    sort_newest_first(&mut files);
+
+    if let Some(max_age) = config.max_age {
+        let now = SystemTime::now();
+        while let Some(oldest) = files.last() {
+            // files modified in the future (e.g. after a clock change) have no age
+            if now.duration_since(oldest.modified).unwrap_or_default() <= max_age {
+                break;
             }
+            remove_rolled_file(&oldest.path, "max_age")?;
+            files.pop();
         }
+    }
 
-        Ok(())
+    if let Some(max_total_size) = config.max_total_size {
+        let current_size = std::fs::metadata(current).map_or(0, |metadata| metadata.len());
+        let mut total_size = current_size + files.iter().map(|file| file.len).sum::<u64>();
+        while total_size > max_total_size {
+            let Some(oldest) = files.pop() else {
+                break;
+            };
+            remove_rolled_file(&oldest.path, "max_total_size")?;
+            total_size -= oldest.len;
+        }
+    }
+    Ok(())
+}
+
+/// Removes a rolled file and logs the removal as a `flashlog` record with the rule that removed it
+fn remove_rolled_file(path: &Path, reason: &'static str) -> io::Result<()> {
+    let size = std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+    match remove_file(path) {
+        Ok(()) => {
+            report_removal(path, size, reason);
+            Ok(())
+        }
+        // already removed by the logger or the compression thread
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+fn report_removal(path: &Path, size: u64, reason: &'static str) {
+    // nothing to report to if the logger was never launched, e.g. a writer used on its own
+    if Lazy::get(&LOG_SENDER).is_none() {
+        return;
+    }
+    queue_internal_record(internal_record(
+        LogLevel::Info,
+        "rolled file removed",
+        vec![
+            ("file", serde_json::json!(path.display().to_string())),
+            ("size", serde_json::json!(size)),
+            ("reason", serde_json::json!(reason)),
+        ],
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-09-15 (Sunday) 14:37:21 UTC
+    const NOW: UnixNano = 1_726_411_041_000_000_000;
+
+    #[test]
+    fn test_window_is_aligned_to_calendar() {
+        let (start, end) = RollingPeriod::Hourly.window(NOW, Zone::Fixed(0)).unwrap();
+        assert_eq!(start, 1_726_408_800_000_000_000); // 14:00 UTC
+        assert_eq!(end - start, HOUR_IN_NANOS);
+
+        // midnight in Seoul is 15:00 UTC of the previous day
+        let (start, end) = RollingPeriod::Daily.window(NOW, Zone::Fixed(9 * 3600)).unwrap();
+        assert_eq!(start, 1_726_326_000_000_000_000); // 2024-09-14 15:00 UTC
+        assert_eq!(end, 1_726_412_400_000_000_000); // 2024-09-15 15:00 UTC
+
+        // New York is still on Sunday, so the week started on Monday 2024-09-09
+        let new_york = Zone::Tz(chrono_tz::America::New_York);
+        let (start, end) = RollingPeriod::Weekly.window(NOW, new_york).unwrap();
+        assert_eq!(start, 1_725_854_400_000_000_000); // 2024-09-09 04:00 UTC
+        assert_eq!(end - start, WEEK_IN_NANOS);
+
+        // clocks moved forward on 2024-03-10 in New York, so that day lasted 23 hours
+        let (start, end) = RollingPeriod::Daily.window(1_710_072_000_000_000_000, new_york).unwrap();
+        assert_eq!(start, 1_710_046_800_000_000_000); // 2024-03-10 05:00 UTC
+        assert_eq!(end, 1_710_129_600_000_000_000); // 2024-03-11 04:00 UTC
+        let (start, end) = RollingPeriod::Hourly.window(1_710_054_000_000_000_000, new_york).unwrap();
+        assert_eq!((start, end - start), (1_710_054_000_000_000_000, HOUR_IN_NANOS));
+
+        // Kolkata is 5:30 ahead of UTC
+        let kolkata = Zone::Tz(chrono_tz::Asia::Kolkata);
+        let (start, _) = RollingPeriod::Hourly.window(NOW, kolkata).unwrap();
+        assert_eq!((start - 1_800_000_000_000) % HOUR_IN_NANOS, 0);
+
+        assert!(RollingPeriod::None.window(NOW, Zone::Fixed(0)).is_none());
+    }
+
+    #[test]
+    fn test_file_name_pattern() {
+        let config = RollingConfig {
+            file_name_prefix: "orders".to_string(),
+            roll_period: Some(RollingPeriod::Hourly),
+            ..Default::default()
+        };
+        let start = DateTime::from_timestamp_nanos(NOW as i64).with_timezone(&FixedOffset::east_opt(9 * 3600).unwrap());
+
+        let pattern = FileNamePattern::parse("{prefix}_{date}T{time}_{pid}_{seq}").unwrap();
+        let name = pattern.render(&config, &start, 7);
+        assert_eq!(name, format!("orders_20240915T233721_{}_007", std::process::id()));
+        assert!(pattern.matches(&config, &name));
+        assert!(pattern.matches(&config, "orders_20240101T000000_1_1234"));
+        assert!(!pattern.matches(&config, "orders_backup"));
+        assert!(!pattern.matches(&config, "orders_20240101T000000_1_12"));
+
+        let pattern = FileNamePattern::parse(DEFAULT_FILE_NAME_PATTERN).unwrap();
+        assert_eq!(pattern.render(&config, &start, 0), "orders-20240915-23");
+        assert!(pattern.matches(&config, "orders-20240101-05"));
+        assert!(!pattern.matches(&config, "orders-20240101"));
+        assert!(!pattern.matches(&config, "orders-archive-20240101-05"));
+
+        let pattern = FileNamePattern::parse("{hostname}.{prefix}").unwrap().with_seq();
+        assert_eq!(pattern.render(&config, &start, 12), format!("{}.orders-012", *HOSTNAME));
+
+        assert!(FileNamePattern::parse("{prefix}-{unknown}").is_err());
+        assert!(FileNamePattern::parse("{prefix}-{date").is_err());
+        assert!(FileNamePattern::parse("logs/{prefix}").is_err());
+    }
+
+    #[test]
+    fn test_remove_expired_files() {
+        let dir = std::env::temp_dir().join("flashlog_test_remove_expired_files");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut config = RollingConfig {
+            base_path: dir.clone(),
+            file_name_prefix: "orders".to_string(),
+            roll_period: Some(RollingPeriod::Daily),
+            ..Default::default()
+        };
+        let pattern = FileNamePattern::parse(DEFAULT_FILE_NAME_PATTERN).unwrap();
+
+        let day = Duration::from_secs(24 * 3600);
+        let now = SystemTime::now();
+        let create = |name: &str, len: usize, age: Duration| {
+            let path = dir.join(name);
+            std::fs::write(&path, vec![b'x'; len]).unwrap();
+            File::options().write(true).open(&path).unwrap().set_modified(now - age).unwrap();
+            path
+        };
+        let current = create("orders-20240915.log", 100, Duration::ZERO);
+        let recent = create("orders-20240914.log", 100, day);
+        let older = create("orders-20240913.log.gz", 100, day * 2);
+        let old = create("orders-20240901.log", 100, day * 14);
+        let other = create("trades-20240901.log", 100, day * 14);
+
+        // without limits nothing is removed
+        remove_expired_files(&config, &pattern, &current).unwrap();
+        assert!(old.exists());
+
+        config.max_age = Some(day * 7);
+        remove_expired_files(&config, &pattern, &current).unwrap();
+        assert!(!old.exists());
+        assert!(older.exists() && other.exists());
+
+        // the current file counts towards the total but is kept
+        config.max_total_size = Some(250);
+        remove_expired_files(&config, &pattern, &current).unwrap();
+        assert!(!older.exists());
+        assert!(recent.exists() && current.exists());
+
+        config.max_total_size = Some(50);
+        remove_expired_files(&config, &pattern, &current).unwrap();
+        assert!(!recent.exists());
+        assert!(current.exists() && other.exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_write_resumes_after_failed_roll() {
+        let dir = std::env::temp_dir().join("flashlog_test_write_resumable");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = RollingConfig {
+            base_path: dir.clone(),
+            file_name_prefix: "orders".to_string(),
+            roll_period: Some(RollingPeriod::None),
+            max_file_size: Some(20),
+            ..Default::default()
+        };
+        let mut writer = RollingFileWriter::open(config, false).unwrap();
+
+        // the first line fits in the open file, rolling for the second one fails without the directory
+        let data = b"first line\nsecond line\n";
+        std::fs::remove_dir_all(&dir).unwrap();
+        let mut written = 0;
+        assert!(writer.write_resumable(data, &mut written).is_err());
+        assert_eq!(written, 11);
+
+        std::fs::create_dir_all(&dir).unwrap();
+        writer.write_resumable(data, &mut written).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(written, data.len());
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(std::fs::read(&files[0]).unwrap(), b"second line\n");
+
+        let _ = std::fs::remove_dir_all(&dir);
     }
-}
\ No newline at end of file
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_failed_flush_keeps_unwritten_bytes() {
+        let dir = std::env::temp_dir().join("flashlog_test_failed_flush");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = RollingConfig {
+            base_path: dir.clone(),
+            file_name_prefix: "orders".to_string(),
+            roll_period: Some(RollingPeriod::None),
+            max_file_size: Some(20),
+            ..Default::default()
+        };
+        let mut writer = RollingFileWriter::open(config, false).unwrap();
+        let path = writer.current_path().to_path_buf();
+
+        // every write to /dev/full fails with ENOSPC
+        let full = OpenOptions::new().write(true).open("/dev/full").unwrap();
+        let file = std::mem::replace(writer.current_file.as_mut().unwrap().get_mut(), full);
+        writer.write_frame(b"frame").unwrap();
+        assert!(writer.roll_file().is_err());
+        assert!(writer.current_file.is_some());
+
+        // nothing reached the file, so nothing is counted as written
+        let data = b"first line\nsecond line\n";
+        let mut written = 0;
+        assert!(writer.write_resumable(data, &mut written).is_err());
+        assert_eq!(written, 0);
+
+        *writer.current_file.as_mut().unwrap().get_mut() = file;
+        writer.write_resumable(data, &mut written).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(written, data.len());
+        assert_eq!(std::fs::read(&path).unwrap(), b"framefirst line\n");
+        assert_eq!(std::fs::read(writer.current_path()).unwrap(), b"second line\n");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
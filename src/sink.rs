@@ -0,0 +1,197 @@
+//! Destinations of formatted log lines.
+//!
+//! The logger thread hands each line to every [`Sink`] whose level admits the record. The file set by
+//! `Logger::with_file` and the console are built in; more destinations are added with `Logger::with_sink`,
+//! each with its own level, formatter and [`FlushPolicy`].
+use crate::compression::Compression;
+use crate::formatter::Formatter;
+use crate::logger::LogLevel;
+use crate::rolling_file::{
+    RollingConfig, RollingFileWriter, RollingPeriod, DEFAULT_FILE_EXTENSION, DEFAULT_FILE_NAME_PATTERN,
+};
+use serde::Deserialize;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Receives formatted lines in the logger thread
+pub trait Sink: Send {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()>;
+
+    /// Writes `buf` after its first `*written` bytes and adds the bytes it writes to `*written`, also when it fails.
+    /// `ErrorPolicy::Retry` calls it again with the same arguments, so that lines written before a failure are not
+    /// written twice. By default, the rest is written with `write_all` and counted only if that succeeds.
+    fn write_resumable(&mut self, buf: &[u8], written: &mut usize) -> io::Result<()> {
+        self.write_all(&buf[*written..])?;
+        *written = buf.len();
+        Ok(())
+    }
+
+    /// Hands buffered lines to the destination
+    fn flush(&mut self) -> io::Result<()>;
+
+    /// Makes the written lines durable. Called on `flush!()` and when the logger shuts down.
+    fn sync(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+
+    /// Closes and reopens the destination, e.g. a file moved by logrotate. Called on `Logger::reopen()`.
+    fn reopen(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for Box<S> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        (**self).write_all(buf)
+    }
+
+    fn write_resumable(&mut self, buf: &[u8], written: &mut usize) -> io::Result<()> {
+        (**self).write_resumable(buf, written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        (**self).sync()
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        (**self).reopen()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdoutSink;
+
+impl Sink for StdoutSink {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        io::stdout().lock().write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StderrSink;
+
+impl Sink for StderrSink {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        io::stderr().lock().write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// Rolling log file
+pub struct FileSink {
+    writer: RollingFileWriter,
+}
+
+impl FileSink {
+    /// Same defaults as `Logger::with_file`: rolled daily, keeping 10 files
+    pub fn new(file_path: &str, file_name: &str) -> io::Result<FileSink> {
+        std::fs::create_dir_all(file_path)?;
+        FileSink::with_config(RollingConfig {
+            base_path: PathBuf::from(file_path),
+            file_name_prefix: file_name.to_string(),
+            roll_period: Some(RollingPeriod::Daily),
+            max_roll_files: Some(10),
+            max_file_size: None,
+            max_age: None,
+            max_total_size: None,
+            compress: false,
+            compression: Compression::Gzip,
+            file_extension: DEFAULT_FILE_EXTENSION.to_string(),
+            file_name_pattern: DEFAULT_FILE_NAME_PATTERN.to_string(),
+            current_link: false,
+            initial_file_path: None,
+        })
+    }
+
+    pub fn with_config(config: RollingConfig) -> io::Result<FileSink> {
+        let writer = RollingFileWriter::open(config, false)?;
+        Ok(FileSink { writer })
+    }
+
+    pub(crate) fn from_writer(writer: RollingFileWriter) -> FileSink {
+        FileSink { writer }
+    }
+}
+
+impl Sink for FileSink {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.writer.write_all(buf)
+    }
+
+    fn write_resumable(&mut self, buf: &[u8], written: &mut usize) -> io::Result<()> {
+        self.writer.write_resumable(buf, written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.sync_all()
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        self.writer.reopen()
+    }
+}
+
+/// When a sink receives its lines
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlushPolicy {
+    /// Lines are queued and written with the logger's buffer size and flush interval (default)
+    #[default]
+    Buffered,
+    /// Each line is written and flushed as soon as it is formatted
+    Immediate,
+}
+
+/// A sink with its level, formatter and flush policy, added with `Logger::with_sink`
+pub struct SinkConfig {
+    pub(crate) sink: Box<dyn Sink>,
+    pub(crate) level: LogLevel,
+    pub(crate) formatter: Option<Arc<dyn Formatter>>,
+    pub(crate) flush_policy: FlushPolicy,
+}
+
+impl SinkConfig {
+    /// Receives every level with the file formatter and `FlushPolicy::Buffered`
+    pub fn new<S: Sink + 'static>(sink: S) -> SinkConfig {
+        SinkConfig {
+            sink: Box::new(sink),
+            level: LogLevel::Trace,
+            formatter: None,
+            flush_policy: FlushPolicy::Buffered,
+        }
+    }
+
+    /// Least severe level written to the sink. Messages without a level (e.g. the deprecated `log_xxx!` macros)
+    /// are written to every sink.
+    pub fn with_level(mut self, level: LogLevel) -> SinkConfig {
+        self.level = level;
+        self
+    }
+
+    pub fn with_formatter<F: Formatter + 'static>(mut self, formatter: F) -> SinkConfig {
+        self.formatter = Some(Arc::new(formatter));
+        self
+    }
+
+    pub fn with_flush_policy(mut self, flush_policy: FlushPolicy) -> SinkConfig {
+        self.flush_policy = flush_policy;
+        self
+    }
+}
@@ -1,24 +1,86 @@
 use once_cell::sync::Lazy;
 use quanta::Clock;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 const UNIX_NANO_ANCHOR_BUFFER: u64 = 10; //10ns
 
 pub static UNIVERSIAL_CLOCK: Lazy<Clock> = Lazy::new(Clock::new);
 
+/// Raw reading of `UNIVERSIAL_CLOCK` and the Unix time at that reading
+static ANCHORS: Lazy<(u64, u64)> = Lazy::new(|| {
+    let raw = UNIVERSIAL_CLOCK.raw();
+    let unix_nano = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64
+        + UNIX_NANO_ANCHOR_BUFFER;
+    (raw, unix_nano)
+});
+
 #[inline]
 pub fn get_unix_nano() -> u64 {
-    static UNIVERSIAL_SYSTEMTIME_ANCHOR: Lazy<u64> = Lazy::new(|| {
-        SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos() as u64
-            + UNIX_NANO_ANCHOR_BUFFER
-    });
-    static UNIVERSIAL_CLOCK_ANCHOR: Lazy<u64> = Lazy::new(|| UNIVERSIAL_CLOCK.raw());
+    let (raw_anchor, unix_nano_anchor) = *ANCHORS;
+    UNIVERSIAL_CLOCK.delta_as_nanos(raw_anchor, UNIVERSIAL_CLOCK.raw()) + unix_nano_anchor
+}
+
+/// Unix time of a raw reading of `UNIVERSIAL_CLOCK`, which may precede the first call to `get_unix_nano`
+#[inline]
+pub fn raw_to_unix_nano(raw: u64) -> u64 {
+    let (raw_anchor, unix_nano_anchor) = *ANCHORS;
+    if raw >= raw_anchor {
+        unix_nano_anchor + UNIVERSIAL_CLOCK.delta_as_nanos(raw_anchor, raw)
+    } else {
+        unix_nano_anchor.saturating_sub(UNIVERSIAL_CLOCK.delta_as_nanos(raw, raw_anchor))
+    }
+}
+
+static CALLSITE_TIMESTAMP: AtomicBool = AtomicBool::new(false);
+static QUEUE_LATENCY: AtomicBool = AtomicBool::new(false);
+
+/// Set by `Logger::with_callsite_timestamp`
+pub(crate) fn set_callsite_timestamp(enabled: bool) {
+    // readings taken from now on convert against anchors that already exist
+    Lazy::force(&ANCHORS);
+    CALLSITE_TIMESTAMP.store(enabled, Ordering::Relaxed);
+}
 
-    UNIVERSIAL_CLOCK.delta_as_nanos(*UNIVERSIAL_CLOCK_ANCHOR, UNIVERSIAL_CLOCK.raw())
-        + *UNIVERSIAL_SYSTEMTIME_ANCHOR
+/// Set by `Logger::with_queue_latency`. Queue latency needs callsite timestamps, so enabling it enables them.
+pub(crate) fn set_queue_latency(enabled: bool) {
+    if enabled {
+        set_callsite_timestamp(true);
+    }
+    QUEUE_LATENCY.store(enabled, Ordering::Relaxed);
+}
+
+/// Raw clock reading taken where a message is logged, 0 when callsite timestamps are off. The logger thread turns it
+/// into the timestamp of the record, so that the time of the event does not drift by the time spent in the queue.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CallsiteTime(u64);
+
+impl CallsiteTime {
+    /// One relaxed load, and a read of the time stamp counter if callsite timestamps are on
+    #[inline(always)]
+    pub fn now() -> CallsiteTime {
+        if CALLSITE_TIMESTAMP.load(Ordering::Relaxed) {
+            CallsiteTime(UNIVERSIAL_CLOCK.raw())
+        } else {
+            CallsiteTime(0)
+        }
+    }
+
+    /// Unix time of the event, and the nanoseconds since then if queue latency is on. Without a reading, the current
+    /// time.
+    #[inline]
+    pub fn resolve(self) -> (u64, Option<u64>) {
+        if self.0 == 0 {
+            return (get_unix_nano(), None);
+        }
+        let queue_latency = QUEUE_LATENCY
+            .load(Ordering::Relaxed)
+            .then(|| UNIVERSIAL_CLOCK.delta_as_nanos(self.0, UNIVERSIAL_CLOCK.raw()));
+        (raw_to_unix_nano(self.0), queue_latency)
+    }
 }
 
 pub fn time_components_from_unix_nano(unix_nano: u64) -> (u8, u8, u8, u16) {
@@ -35,109 +97,181 @@ pub fn time_components_from_unix_nano(unix_nano: u64) -> (u8, u8, u8, u16) {
     (hours, minutes, seconds, millis)
 }
 
-pub fn convert_unix_nano_to_date_and_time(unix_nano: u64, utc_offset_hour: i32) -> (String, String) {
-    const NANOS_IN_SEC: u64 = 1_000_000_000;
-    const NANOS_IN_MIN: u64 = 60 * NANOS_IN_SEC;
-    const NANOS_IN_HOUR: u64 = 60 * NANOS_IN_MIN;
-    const NANOS_IN_DAY: u64 = 24 * NANOS_IN_HOUR;
-
-    let days_since_epoch = unix_nano / NANOS_IN_DAY;
-    let remaining_nanos = unix_nano % NANOS_IN_DAY;
-
-    let hours = remaining_nanos / NANOS_IN_HOUR;
-    let remaining_nanos = remaining_nanos % NANOS_IN_HOUR;
+const NANOS_IN_SEC: i64 = 1_000_000_000;
+const NANOS_IN_DAY: i64 = 86_400 * NANOS_IN_SEC;
 
-    let minutes = remaining_nanos / NANOS_IN_MIN;
-    let remaining_nanos = remaining_nanos % NANOS_IN_MIN;
-
-    let seconds = remaining_nanos / NANOS_IN_SEC;
-    let remaining_nanos = remaining_nanos % NANOS_IN_SEC;
+/// Local date of the day being formatted. Records of the same day only compute their time digits.
+#[derive(Debug, Clone)]
+pub struct DateCache {
+    /// Start and end of the cached day, in local nanoseconds since the epoch
+    day_start: i64,
+    day_end: i64,
+    /// `YYYYMMDD`
+    date: [u8; 8],
+}
 
-    let millis = remaining_nanos / 1_000_000;
-    let remaining_nanos = remaining_nanos % 1_000_000;
+impl Default for DateCache {
+    fn default() -> Self {
+        DateCache::new()
+    }
+}
 
-    let micros = remaining_nanos / 1_000;
-    let nanos = remaining_nanos % 1_000;
+impl DateCache {
+    pub const fn new() -> DateCache {
+        DateCache { day_start: 0, day_end: 0, date: *b"19700101" }
+    }
 
-    // Adjust for UTC offset
-    let mut total_hours = hours as i32 + utc_offset_hour;
-    let mut total_days = days_since_epoch as i32;
+    /// Local date (`YYYYMMDD`) of `unix_nano` at `utc_offset` seconds east of UTC, and the nanoseconds since local
+    /// midnight
+    #[inline]
+    pub fn date_and_nanos(&mut self, unix_nano: u64, utc_offset: i32) -> (&str, u64) {
+        let local = unix_nano as i64 + utc_offset as i64 * NANOS_IN_SEC;
+        if !(self.day_start..self.day_end).contains(&local) {
+            self.set_day(local.div_euclid(NANOS_IN_DAY));
+        }
+        // only ASCII digits are written to `date`
+        let date = std::str::from_utf8(&self.date).unwrap_or_default();
+        (date, (local - self.day_start) as u64)
+    }
 
-    if total_hours >= 24 {
-        total_hours -= 24;
-        total_days += 1;
-    } else if total_hours < 0 {
-        total_hours += 24;
-        total_days -= 1;
+    #[cold]
+    fn set_day(&mut self, days_since_epoch: i64) {
+        self.day_start = days_since_epoch * NANOS_IN_DAY;
+        self.day_end = self.day_start + NANOS_IN_DAY;
+        let (year, month, day) = days_to_date(days_since_epoch);
+        let year = year.clamp(0, 9999) as u64;
+        write_digits(&mut self.date[..4], year);
+        write_digits(&mut self.date[4..6], month as u64);
+        write_digits(&mut self.date[6..], day as u64);
     }
+}
 
-    let (year, month, day) = days_to_date(total_days as u32);
+thread_local! {
+    static DATE_CACHE: std::cell::RefCell<DateCache> = const { std::cell::RefCell::new(DateCache::new()) };
+}
 
-    let date = format!("{:04}{:02}{:02}", year, month, day);
-    let time = format!("{:02}:{:02}:{:02}.{:03}:{:03}:{:03}", total_hours, minutes, seconds, millis, micros, nanos);
-    
-    (date, time)
+/// Appends the local date (`YYYYMMDD`) of `unix_nano` at `utc_offset` seconds east of UTC, from a per-thread cache
+#[inline]
+pub fn write_date(buf: &mut String, unix_nano: u64, utc_offset: i32) {
+    DATE_CACHE.with(|cache| buf.push_str(cache.borrow_mut().date_and_nanos(unix_nano, utc_offset).0));
 }
 
-fn days_to_date(mut days: u32) -> (i32, u32, u32) {
-    let mut year = 1970;
+/// Appends the local time (`HH:MM:SS.mmm:uuu:nnn`) of `unix_nano` at `utc_offset` seconds east of UTC
+#[inline]
+pub fn write_time(buf: &mut String, unix_nano: u64, utc_offset: i32) {
+    let mut time = *b"00:00:00.000:000:000";
+    let nanos = nanos_of_day(unix_nano, utc_offset);
+    write_clock(&mut time, nanos);
+    write_digits(&mut time[9..12], nanos / 1_000_000 % 1_000);
+    write_digits(&mut time[13..16], nanos / 1_000 % 1_000);
+    write_digits(&mut time[17..], nanos % 1_000);
+    buf.push_str(std::str::from_utf8(&time).unwrap_or_default());
+}
 
-    // Find the year
-    loop {
-        let days_in_year = if is_leap_year(year) { 366 } else { 365 };
-        if days < days_in_year {
-            break;
-        }
-        days -= days_in_year;
-        year += 1;
-    }
+/// Appends `unix_nano` in RFC 3339 at `utc_offset` seconds east of UTC with `digits` fractional digits (at most 9),
+/// e.g. `2024-09-15T20:34:30.684+09:00`, or `2024-09-15T11:34:30.684Z` in UTC
+pub fn write_rfc3339(buf: &mut String, unix_nano: u64, utc_offset: i32, digits: u8) {
+    let mut timestamp = *b"0000-00-00T00:00:00.000000000";
+    let nanos = DATE_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let (date, nanos) = cache.date_and_nanos(unix_nano, utc_offset);
+        let date = date.as_bytes();
+        timestamp[..4].copy_from_slice(&date[..4]);
+        timestamp[5..7].copy_from_slice(&date[4..6]);
+        timestamp[8..10].copy_from_slice(&date[6..]);
+        nanos
+    });
+    write_clock(&mut timestamp[11..], nanos);
+    write_digits(&mut timestamp[20..], nanos % NANOS_IN_SEC as u64);
+    let len = match digits.min(9) {
+        0 => 19,
+        digits => 20 + digits as usize,
+    };
+    buf.push_str(std::str::from_utf8(&timestamp[..len]).unwrap_or_default());
 
-    // Find the month and day
-    let mut month = 1;
-    while days > 0 {
-        let days_in_month = days_in_month(year, month);
-        if days < days_in_month {
-            break;
-        }
-        days -= days_in_month;
-        month += 1;
+    if utc_offset == 0 {
+        buf.push('Z');
+        return;
+    }
+    let mut offset = *b"+00:00";
+    if utc_offset < 0 {
+        offset[0] = b'-';
     }
+    let minutes = utc_offset.unsigned_abs() as u64 / 60;
+    write_digits(&mut offset[1..3], minutes / 60);
+    write_digits(&mut offset[4..], minutes % 60);
+    buf.push_str(std::str::from_utf8(&offset).unwrap_or_default());
+}
+
+/// Appends the local time (`HH:MM:SS.mmm`) of `unix_nano` at `utc_offset` seconds east of UTC
+#[inline]
+pub fn write_time_millis(buf: &mut String, unix_nano: u64, utc_offset: i32) {
+    let mut time = *b"00:00:00.000";
+    let nanos = nanos_of_day(unix_nano, utc_offset);
+    write_clock(&mut time, nanos);
+    write_digits(&mut time[9..], nanos / 1_000_000 % 1_000);
+    buf.push_str(std::str::from_utf8(&time).unwrap_or_default());
+}
 
-    (year, month, days + 1)
+#[inline]
+fn nanos_of_day(unix_nano: u64, utc_offset: i32) -> u64 {
+    (unix_nano as i64 + utc_offset as i64 * NANOS_IN_SEC).rem_euclid(NANOS_IN_DAY) as u64
 }
 
-fn is_leap_year(year: i32) -> bool {
-    (year % 4 == 0 && year % 100 != 0) || (year % 400 == 0)
+/// `HH:MM:SS` into the first 8 bytes of `time`
+#[inline]
+fn write_clock(time: &mut [u8], nanos_of_day: u64) {
+    let seconds = nanos_of_day / NANOS_IN_SEC as u64;
+    write_digits(&mut time[..2], seconds / 3600);
+    write_digits(&mut time[3..5], seconds / 60 % 60);
+    write_digits(&mut time[6..8], seconds % 60);
 }
 
-fn days_in_month(year: i32, month: u32) -> u32 {
-    match month {
-        1 => 31,
-        2 => {
-            if is_leap_year(year) {
-                29
-            } else {
-                28
-            }
-        }
-        3 => 31,
-        4 => 30,
-        5 => 31,
-        6 => 30,
-        7 => 31,
-        8 => 31,
-        9 => 30,
-        10 => 31,
-        11 => 30,
-        12 => 31,
-        _ => 0,
+/// Zero-padded decimal digits of `value`, filling `out`
+#[inline]
+fn write_digits(out: &mut [u8], mut value: u64) {
+    for digit in out.iter_mut().rev() {
+        *digit = b'0' + (value % 10) as u8;
+        value /= 10;
     }
 }
 
+/// Date (`YYYYMMDD`) and time (`HH:MM:SS.mmm:uuu:nnn`) of `unix_nano` at `utc_offset_hour` whole hours east of UTC.
+/// Use `convert_unix_nano_to_date_and_time_with_offset_secs` for offsets such as +05:30.
+pub fn convert_unix_nano_to_date_and_time(unix_nano: u64, utc_offset_hour: i32) -> (String, String) {
+    convert_unix_nano_to_date_and_time_with_offset_secs(unix_nano, utc_offset_hour.saturating_mul(3600))
+}
+
+/// Date (`YYYYMMDD`) and time (`HH:MM:SS.mmm:uuu:nnn`) of `unix_nano` at `utc_offset` seconds east of UTC.
+/// `timezone::utc_offset_at` gives the offset of the logger's timezone for the timestamp.
+/// Formatters use `write_date` and `write_time`, which append to their buffer instead of allocating.
+pub fn convert_unix_nano_to_date_and_time_with_offset_secs(unix_nano: u64, utc_offset: i32) -> (String, String) {
+    let mut date = String::with_capacity(8);
+    write_date(&mut date, unix_nano, utc_offset);
+    let mut time = String::with_capacity(20);
+    write_time(&mut time, unix_nano, utc_offset);
+    (date, time)
+}
+
+/// Proleptic Gregorian (year, month, day) of a day counted from 1970-01-01, which may be negative
+fn days_to_date(days: i64) -> (i64, u32, u32) {
+    // days from 0000-03-01, in 400-year eras of 146_097 days starting in March so that leap days come last
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::Datelike;
 
     #[test]
     fn test_get_unix_nano() {
@@ -153,4 +287,96 @@ mod tests {
         println!("{:?}", res);
         
     }
+
+    #[test]
+    fn test_raw_to_unix_nano() {
+        let raw = UNIVERSIAL_CLOCK.raw();
+        let unix_nano = raw_to_unix_nano(raw);
+        let now = get_unix_nano();
+        assert!(unix_nano <= now && now - unix_nano < 1_000_000_000);
+
+        // readings taken before the anchors
+        let (raw_anchor, unix_nano_anchor) = *ANCHORS;
+        assert!(raw_to_unix_nano(raw_anchor.saturating_sub(1_000)) <= unix_nano_anchor);
+
+        let (unix_nano, queue_latency) = CallsiteTime::default().resolve();
+        assert!(unix_nano >= now && queue_latency.is_none());
+    }
+
+    #[test]
+    fn test_date_and_time() {
+        // 2024-09-15 11:34:30.684921877 UTC
+        let unix_nano = 1_726_400_070_684_921_877;
+        assert_eq!(
+            convert_unix_nano_to_date_and_time_with_offset_secs(unix_nano, 9 * 3600),
+            ("20240915".to_string(), "20:34:30.684:921:877".to_string())
+        );
+        assert_eq!(
+            convert_unix_nano_to_date_and_time_with_offset_secs(unix_nano, -(11 * 3600 + 45 * 60)),
+            ("20240914".to_string(), "23:49:30.684:921:877".to_string())
+        );
+        // before 1970 in local time
+        assert_eq!(
+            convert_unix_nano_to_date_and_time_with_offset_secs(1_000, -5 * 3600),
+            ("19691231".to_string(), "19:00:00.000:001:000".to_string())
+        );
+
+        // the offset of `convert_unix_nano_to_date_and_time` is in hours
+        assert_eq!(
+            convert_unix_nano_to_date_and_time(unix_nano, 9),
+            ("20240915".to_string(), "20:34:30.684:921:877".to_string())
+        );
+        assert_eq!(
+            convert_unix_nano_to_date_and_time(unix_nano, -4),
+            ("20240915".to_string(), "07:34:30.684:921:877".to_string())
+        );
+
+        let mut buf = String::new();
+        write_time_millis(&mut buf, unix_nano, 0);
+        assert_eq!(buf, "11:34:30.684");
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        let rfc3339 = |unix_nano, utc_offset, digits| {
+            let mut buf = String::new();
+            write_rfc3339(&mut buf, unix_nano, utc_offset, digits);
+            buf
+        };
+        let unix_nano = 1_726_400_070_684_921_877;
+        assert_eq!(rfc3339(unix_nano, 9 * 3600, 3), "2024-09-15T20:34:30.684+09:00");
+        assert_eq!(rfc3339(unix_nano, 0, 9), "2024-09-15T11:34:30.684921877Z");
+        assert_eq!(rfc3339(unix_nano, 0, 12), "2024-09-15T11:34:30.684921877Z");
+        assert_eq!(rfc3339(unix_nano, -(3 * 3600 + 30 * 60), 0), "2024-09-15T08:04:30-03:30");
+        assert_eq!(rfc3339(unix_nano, 5 * 3600 + 45 * 60, 6), "2024-09-15T17:19:30.684921+05:45");
+        let parsed = chrono::DateTime::parse_from_rfc3339(&rfc3339(unix_nano, -4 * 3600, 9)).unwrap();
+        assert_eq!(parsed.timestamp_nanos_opt(), Some(unix_nano as i64));
+    }
+
+    #[test]
+    fn test_days_to_date() {
+        assert_eq!(days_to_date(0), (1970, 1, 1));
+        assert_eq!(days_to_date(-1), (1969, 12, 31));
+        assert_eq!(days_to_date(-719_468), (0, 3, 1));
+        assert_eq!(days_to_date(11_016), (2000, 2, 29));
+        assert_eq!(days_to_date(19_981), (2024, 9, 15));
+        // every day against chrono, 1900 to 2100
+        let epoch = chrono::NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+        for days in -25_567..47_482 {
+            let date = epoch + chrono::Duration::days(days);
+            let expected = (date.year() as i64, date.month(), date.day());
+            assert_eq!(days_to_date(days), expected, "{} days", days);
+        }
+    }
+
+    #[test]
+    fn test_date_cache() {
+        let mut cache = DateCache::new();
+        let midnight = 1_726_358_400_000_000_000; // 2024-09-15 00:00 UTC
+        assert_eq!(cache.date_and_nanos(midnight - 1, 0), ("20240914", 86_400_000_000_000 - 1));
+        assert_eq!(cache.date_and_nanos(midnight, 0), ("20240915", 0));
+        assert_eq!(cache.date_and_nanos(midnight + 5, 0), ("20240915", 5));
+        assert_eq!(cache.date_and_nanos(midnight, 3600), ("20240915", 3_600_000_000_000));
+        assert_eq!(cache.date_and_nanos(midnight, -1), ("20240914", 86_400_000_000_000 - 1_000_000_000));
+    }
 }
\ No newline at end of file
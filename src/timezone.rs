@@ -0,0 +1,221 @@
+//! Timezone of the timestamps.
+//!
+//! `Logger::with_timezone` resolves a [`TimeZone`] once into a [`Zone`]. The offset from UTC is then looked up for
+//! the timestamp of each record, so that daylight saving time transitions and offsets such as +05:30 are applied.
+//! The offset found is cached per thread for the minute it was looked up in.
+use chrono::{FixedOffset, Local, NaiveDateTime, Offset, TimeZone as _};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+const NANOS_IN_MIN: u64 = 60_000_000_000;
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum TimeZone {
+    /// Timezone of the system, with its daylight saving time transitions
+    Local,
+    Seoul,
+    Japan,
+    /// `America/New_York`, with its daylight saving time transitions
+    NewYork,
+    Utc,
+    /// IANA name, e.g. `Europe/London` or `Asia/Kolkata`, with its daylight saving time transitions
+    Named(String),
+    /// Fixed offset east of UTC in seconds
+    Fixed(i32),
+}
+
+impl TimeZone {
+    /// Resolves the name of `TimeZone::Named`. Fails if the name is not in the IANA database.
+    pub fn resolve(&self) -> Result<Zone, String> {
+        match self {
+            TimeZone::Local => Ok(Zone::Local),
+            TimeZone::Seoul => Ok(Zone::Tz(Tz::Asia__Seoul)),
+            TimeZone::Japan => Ok(Zone::Tz(Tz::Asia__Tokyo)),
+            TimeZone::NewYork => Ok(Zone::Tz(Tz::America__New_York)),
+            TimeZone::Utc => Ok(Zone::Fixed(0)),
+            TimeZone::Named(name) => {
+                name.parse::<Tz>().map(Zone::Tz).map_err(|_| format!("Unknown timezone {:?}", name))
+            }
+            TimeZone::Fixed(offset) => match FixedOffset::east_opt(*offset) {
+                Some(_) => Ok(Zone::Fixed(*offset)),
+                None => Err(format!("Invalid offset of {} seconds", offset)),
+            },
+        }
+    }
+
+    /// Whole hours of the current offset from UTC
+    pub fn as_offset_hour(&self) -> i32 {
+        self.resolve().map_or(0, |zone| zone.utc_offset_at(crate::get_unix_nano())) / 3600
+    }
+}
+
+impl std::str::FromStr for TimeZone {
+    type Err = String;
+
+    /// Parses `local`, `utc`, `seoul`, `japan`, `new_york`, an offset such as `+05:30`, or an IANA name
+    fn from_str(timezone: &str) -> Result<TimeZone, String> {
+        let timezone = match timezone.to_ascii_lowercase().as_str() {
+            "local" => TimeZone::Local,
+            "utc" => TimeZone::Utc,
+            "seoul" => TimeZone::Seoul,
+            "japan" => TimeZone::Japan,
+            "new_york" | "newyork" => TimeZone::NewYork,
+            _ => match parse_offset(timezone) {
+                Some(offset) => TimeZone::Fixed(offset),
+                None => TimeZone::Named(timezone.to_string()),
+            },
+        };
+        timezone.resolve()?;
+        Ok(timezone)
+    }
+}
+
+impl TryFrom<String> for TimeZone {
+    type Error = String;
+
+    fn try_from(timezone: String) -> Result<TimeZone, String> {
+        timezone.parse()
+    }
+}
+
+/// `+HH:MM`, `-HH:MM` or `+HH` in seconds
+fn parse_offset(offset: &str) -> Option<i32> {
+    let sign = match offset.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let (hours, minutes) = offset[1..].split_once(':').unwrap_or((&offset[1..], "0"));
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if !(0..60).contains(&minutes) {
+        return None;
+    }
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// A resolved `TimeZone`, giving the offset from UTC at any instant
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Zone {
+    /// Offset east of UTC in seconds
+    Fixed(i32),
+    Tz(Tz),
+    Local,
+}
+
+impl Zone {
+    /// Offset east of UTC in seconds at `unix_nano`
+    pub fn utc_offset_at(&self, unix_nano: u64) -> i32 {
+        let utc = || {
+            let secs = (unix_nano / 1_000_000_000) as i64;
+            chrono::DateTime::from_timestamp(secs, 0).map_or(NaiveDateTime::default(), |time| time.naive_utc())
+        };
+        match self {
+            Zone::Fixed(offset) => *offset,
+            Zone::Tz(tz) => tz.offset_from_utc_datetime(&utc()).fix().local_minus_utc(),
+            Zone::Local => Local.offset_from_utc_datetime(&utc()).local_minus_utc(),
+        }
+    }
+
+    /// IANA name, if any. Binary log files store it to apply the transitions when decoded.
+    pub fn name(&self) -> Option<&'static str> {
+        match self {
+            Zone::Tz(tz) => Some(tz.name()),
+            _ => None,
+        }
+    }
+}
+
+/// Timezone of the timestamps. UTC until `Logger::with_timezone` is called.
+static ZONE: RwLock<Zone> = RwLock::new(Zone::Fixed(0));
+/// Incremented when `ZONE` changes, which invalidates the cached offsets
+static ZONE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn set_zone(zone: Zone) {
+    *ZONE.write().expect("Failed to lock ZONE") = zone;
+    ZONE_GENERATION.fetch_add(1, Ordering::Release);
+}
+
+/// Timezone of the timestamps, set by `Logger::with_timezone`
+pub fn current_zone() -> Zone {
+    *ZONE.read().expect("Failed to lock ZONE")
+}
+
+thread_local! {
+    /// Generation of `ZONE`, minute since the epoch and offset found for that minute
+    static OFFSET_CACHE: Cell<(u64, u64, i32)> = const { Cell::new((u64::MAX, 0, 0)) };
+}
+
+/// Offset east of UTC in seconds of the logger's timezone at `unix_nano`. Transitions happen at whole minutes, so
+/// the offset is looked up once per minute and thread.
+pub fn utc_offset_at(unix_nano: u64) -> i32 {
+    let generation = ZONE_GENERATION.load(Ordering::Acquire);
+    let minute = unix_nano / NANOS_IN_MIN;
+    OFFSET_CACHE.with(|cache| {
+        let (cached_generation, cached_minute, offset) = cache.get();
+        if cached_generation == generation && cached_minute == minute {
+            return offset;
+        }
+        let offset = current_zone().utc_offset_at(unix_nano);
+        cache.set((generation, minute, offset));
+        offset
+    })
+}
+
+/// The offset in hours, as in the `offset` field of JSON lines: an integer for whole hours, e.g. `9`, and a
+/// fraction otherwise, e.g. `5.5`
+#[doc(hidden)]
+pub fn offset_in_hours(utc_offset: i32) -> serde_json::Value {
+    if utc_offset % 3600 == 0 {
+        serde_json::json!(utc_offset / 3600)
+    } else {
+        serde_json::json!(utc_offset as f64 / 3600.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn unix_nano(secs: u64) -> u64 {
+        secs * 1_000_000_000
+    }
+
+    #[test]
+    fn test_named_zones() {
+        let london = TimeZone::Named("Europe/London".to_string()).resolve().unwrap();
+        // 2024-01-15 and 2024-07-15 12:00 UTC
+        assert_eq!(london.utc_offset_at(unix_nano(1_705_320_000)), 0);
+        assert_eq!(london.utc_offset_at(unix_nano(1_721_044_800)), 3600);
+
+        let new_york = TimeZone::NewYork.resolve().unwrap();
+        assert_eq!(new_york.utc_offset_at(unix_nano(1_705_320_000)), -5 * 3600);
+        assert_eq!(new_york.utc_offset_at(unix_nano(1_721_044_800)), -4 * 3600);
+        // DST starts on 2024-03-10 at 07:00 UTC
+        assert_eq!(new_york.utc_offset_at(unix_nano(1_710_053_999)), -5 * 3600);
+        assert_eq!(new_york.utc_offset_at(unix_nano(1_710_054_000)), -4 * 3600);
+
+        let kolkata = "Asia/Kolkata".parse::<TimeZone>().unwrap().resolve().unwrap();
+        assert_eq!(kolkata.utc_offset_at(unix_nano(1_721_044_800)), 5 * 3600 + 30 * 60);
+        let kathmandu = "Asia/Kathmandu".parse::<TimeZone>().unwrap().resolve().unwrap();
+        assert_eq!(kathmandu.utc_offset_at(unix_nano(1_721_044_800)), 5 * 3600 + 45 * 60);
+
+        assert!(TimeZone::Named("Mars/Olympus_Mons".to_string()).resolve().is_err());
+        assert!("Mars/Olympus_Mons".parse::<TimeZone>().is_err());
+    }
+
+    #[test]
+    fn test_parse_timezone() {
+        assert_eq!("UTC".parse::<TimeZone>().unwrap(), TimeZone::Utc);
+        assert_eq!("new_york".parse::<TimeZone>().unwrap(), TimeZone::NewYork);
+        assert_eq!("+05:30".parse::<TimeZone>().unwrap(), TimeZone::Fixed(19_800));
+        assert_eq!("-04".parse::<TimeZone>().unwrap(), TimeZone::Fixed(-14_400));
+        assert!("+05:75".parse::<TimeZone>().is_err());
+        assert_eq!(offset_in_hours(9 * 3600), serde_json::json!(9));
+        assert_eq!(offset_in_hours(-(3 * 3600 + 30 * 60)), serde_json::json!(-3.5));
+    }
+}
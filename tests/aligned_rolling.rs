@@ -0,0 +1,35 @@
+use flashlog::{Logger, RollingPeriod, TimeZone, flush, get_initial_log_file_path};
+use std::fs;
+
+#[test]
+fn test_daily_file_is_named_by_its_date() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_aligned_rolling");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    {
+        let _logger = Logger::initialize()
+            .with_file(temp_dir.to_str().unwrap(), "aligned")
+            .expect("Failed to set file")
+            .with_roll_period(RollingPeriod::Daily)
+            .expect("Failed to set roll period")
+            .with_timezone(TimeZone::Seoul)
+            .with_console_report(false)
+            .launch();
+
+        flashlog::flash_error_ct!("aligned"; "message");
+        flush!();
+    }
+
+    let files: Vec<_> = fs::read_dir(&temp_dir).unwrap().filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    assert_eq!(files.len(), 1);
+    assert_eq!(get_initial_log_file_path().as_ref(), Some(&files[0]));
+
+    let line = fs::read_to_string(&files[0]).unwrap();
+    let json: serde_json::Value = serde_json::from_str(line.trim_end()).unwrap();
+    let date = json["date"].as_str().unwrap();
+    let file_name = files[0].file_name().unwrap().to_str().unwrap();
+    assert_eq!(file_name, format!("aligned-{}.log", date));
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
@@ -0,0 +1,73 @@
+use flashlog::binary_file::BinaryFileReader;
+use flashlog::{get_initial_log_file_path, Logger, RollingPeriod, flush};
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn test_binary_file_decodes_to_json_lines() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_binary_file");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    {
+        let _logger = Logger::initialize()
+            .with_file(temp_dir.to_str().unwrap(), "text")
+            .expect("Failed to set file")
+            .with_binary_file(temp_dir.to_str().unwrap(), "ticks")
+            .expect("Failed to set binary file")
+            .with_roll_period(RollingPeriod::None)
+            .expect("Failed to set roll period")
+            .with_console_report(false)
+            .include_unixnano(true)
+            .launch();
+
+        for i in 0..100u64 {
+            flashlog::flash_error_bin!("ticks"; "px {} qty {}", 100.0 + i as f64 * 0.25, i; venue = "KRX");
+        }
+        flashlog::flash_error_bin!("ticks"; "halted"; code = 7u8);
+        flashlog::flash_error_ct!("text"; "json line");
+        flush!();
+    }
+
+    let files: Vec<_> = fs::read_dir(&temp_dir).unwrap().filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    let binary_path = files.iter().find(|p| p.extension().is_some_and(|e| e == "flog")).expect("binary file");
+    let text_path = files.iter().find(|p| p.extension().is_some_and(|e| e == "log")).expect("text file");
+
+    // the initial log file path refers to the JSON file, not to the binary file
+    assert_eq!(get_initial_log_file_path().as_ref(), Some(text_path));
+
+    // binary records do not go to the JSON file
+    let text = fs::read_to_string(text_path).unwrap();
+    assert!(text.contains("json line"));
+    assert!(!text.contains("\"topic\":\"ticks\""));
+
+    let decoded: Vec<String> = BinaryFileReader::new(fs::File::open(binary_path).unwrap())
+        .collect::<std::io::Result<_>>()
+        .unwrap();
+    assert_eq!(decoded.len(), 101);
+
+    let lines: Vec<serde_json::Value> = decoded.iter().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines[3]["level"], "Error");
+    assert_eq!(lines[3]["topic"], "ticks");
+    assert_eq!(lines[3]["message"], "px 100.75 qty 3");
+    assert_eq!(lines[3]["data"], serde_json::json!({"venue": "KRX"}));
+    assert!(lines[3]["src"].as_str().unwrap().starts_with("tests/binary_file.rs:"));
+    assert_eq!(lines[100]["message"], "halted");
+    assert_eq!(lines[100]["data"], serde_json::json!({"code": 7}));
+
+    let unixnanos: Vec<u64> = lines.iter().map(|line| line["unixnano"].as_u64().unwrap()).collect();
+    assert!(unixnanos.windows(2).all(|w| w[0] <= w[1]));
+
+    let json_size: usize = decoded.iter().map(String::len).sum();
+    assert!((fs::metadata(binary_path).unwrap().len() as usize) * 3 < json_size);
+
+    // the decoder binary prints the same lines
+    let output = Command::new(env!("CARGO_BIN_EXE_flashlog-decode"))
+        .arg(binary_path)
+        .output()
+        .expect("Failed to run flashlog-decode");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), decoded.concat());
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
@@ -0,0 +1,64 @@
+#![cfg(target_os = "linux")]
+use flashlog::binary::{BinaryRecord, Callsite, Encode};
+use flashlog::binary_file::{BinaryFileReader, BinaryFileWriter, BINARY_FILE_EXTENSION};
+use flashlog::{RollingConfig, RollingPeriod};
+use std::fs;
+
+static TICK: Callsite = Callsite::new(1, "ticks", "{}", 1, &[], file!(), line!(), module_path!());
+
+fn record(message: &str) -> BinaryRecord {
+    let mut record = BinaryRecord::new(&TICK);
+    message.encode(&mut record);
+    record
+}
+
+/// Limits the size of the files written by this process, so that a write crossing `limit` is cut short
+fn limit_file_size(limit: libc::rlim_t) {
+    unsafe {
+        let mut rlimit = std::mem::zeroed::<libc::rlimit>();
+        assert_eq!(libc::getrlimit(libc::RLIMIT_FSIZE, &mut rlimit), 0);
+        rlimit.rlim_cur = limit.min(rlimit.rlim_max);
+        assert_eq!(libc::setrlimit(libc::RLIMIT_FSIZE, &rlimit), 0);
+    }
+}
+
+#[test]
+fn test_short_write_leaves_no_torn_frame() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_binary_torn_frame");
+    let _ = fs::remove_dir_all(&temp_dir);
+    fs::create_dir_all(&temp_dir).unwrap();
+
+    let mut config = RollingConfig::default();
+    config.base_path = temp_dir.clone();
+    config.file_name_prefix = "ticks".to_string();
+    config.roll_period = Some(RollingPeriod::None);
+    config.file_extension = BINARY_FILE_EXTENSION.to_string();
+    let mut writer = BinaryFileWriter::new(config).unwrap();
+    writer.write_record(&record("before"), 1).unwrap();
+    writer.flush().unwrap();
+
+    // a frame larger than the write buffer goes to the file at once and is cut short by the limit;
+    // past the limit, writes fail with EFBIG instead of raising SIGXFSZ
+    let path = fs::read_dir(&temp_dir).unwrap().next().unwrap().unwrap().path();
+    let large = "x".repeat(64 * 1024);
+    unsafe { libc::signal(libc::SIGXFSZ, libc::SIG_IGN) };
+    limit_file_size(fs::metadata(&path).unwrap().len() + 1024);
+    assert!(writer.write_record(&record(&large), 2).is_err());
+    limit_file_size(libc::RLIM_INFINITY);
+
+    // as with ErrorPolicy::Retry
+    writer.write_record(&record(&large), 2).unwrap();
+    writer.write_record(&record("after"), 3).unwrap();
+    writer.flush().unwrap();
+
+    let lines: Vec<String> = BinaryFileReader::new(fs::File::open(&path).unwrap())
+        .collect::<std::io::Result<_>>()
+        .unwrap();
+    let messages: Vec<String> = lines
+        .iter()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["message"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(messages, vec!["before".to_string(), large, "after".to_string()]);
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
@@ -0,0 +1,59 @@
+use flashlog::{Logger, RollingPeriod, flush};
+use std::fs;
+
+#[test]
+fn test_binary_records_match_ct_layout() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_binary_records");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    {
+        let _logger = Logger::initialize()
+            .with_file(temp_dir.to_str().unwrap(), "binary")
+            .expect("Failed to set file")
+            .with_roll_period(RollingPeriod::None)
+            .expect("Failed to set roll period")
+            .with_console_report(false)
+            .include_unixnano(true)
+            .launch();
+
+        let px = 101.25f64;
+        let qty = 7u32;
+        flashlog::flash_error_bin!("orders"; "px {} qty {}", px, qty; venue = "KRX", filled = true);
+        flashlog::flash_error_bin!("orders"; "rejected");
+        flashlog::flash_error_bin!(code = -1i32);
+        flashlog::flash_error_ct!("orders"; "px {} qty {}", px, qty; venue = "KRX", filled = true);
+        flush!();
+    }
+
+    let content: String = fs::read_dir(&temp_dir)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| fs::read_to_string(e.path()).unwrap())
+        .collect();
+    let lines: Vec<serde_json::Value> = content.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines.len(), 4);
+
+    assert_eq!(lines[0]["level"], "Error");
+    assert_eq!(lines[0]["topic"], "orders");
+    assert_eq!(lines[0]["message"], "px 101.25 qty 7");
+    assert_eq!(lines[0]["data"], serde_json::json!({"venue": "KRX", "filled": true}));
+    assert!(lines[0]["src"].as_str().unwrap().starts_with("tests/binary_records.rs:"));
+    assert!(lines[0]["unixnano"].as_u64().is_some());
+
+    assert_eq!(lines[1]["level"], "Error");
+    assert_eq!(lines[1]["message"], "rejected");
+
+    assert_eq!(lines[2]["level"], "Error");
+    assert_eq!(lines[2]["topic"], "");
+    assert_eq!(lines[2]["data"], serde_json::json!({"code": -1}));
+
+    // same fields as the closure-based macro
+    let binary_keys: Vec<_> = lines[0].as_object().unwrap().keys().collect();
+    let ct_keys: Vec<_> = lines[3].as_object().unwrap().keys().collect();
+    assert_eq!(binary_keys, ct_keys);
+    assert_eq!(lines[0]["message"], lines[3]["message"]);
+    assert_eq!(lines[0]["data"], lines[3]["data"]);
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
@@ -0,0 +1,43 @@
+use flashlog::{
+    flush, get_initial_log_file_path, get_unix_nano, LazyMessage, LogMessage, Logger, RollingPeriod, LOG_SENDER,
+};
+use std::fs;
+use std::time::Duration;
+
+#[test]
+fn test_callsite_timestamp_and_queue_latency() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_callsite_timestamp");
+    let _ = fs::remove_dir_all(&temp_dir);
+
+    let (path, before, after) = {
+        let _logger = Logger::initialize()
+            .with_file(temp_dir.to_str().unwrap(), "callsite")
+            .expect("Failed to set file")
+            .with_roll_period(RollingPeriod::None)
+            .expect("Failed to set roll period")
+            .with_console_report(false)
+            .include_unixnano(true)
+            .with_queue_latency(true)
+            .launch();
+
+        // keeps the logger thread busy, so that the next message waits in the queue
+        LOG_SENDER.send_log(LogMessage::LazyMessage(LazyMessage::new(|| {
+            std::thread::sleep(Duration::from_millis(100));
+            String::new()
+        })));
+        let before = get_unix_nano();
+        flashlog::flash_error_ct!("callsite"; "queued");
+        let after = get_unix_nano();
+        flush!();
+        (get_initial_log_file_path().unwrap(), before, after)
+    };
+
+    let content = fs::read_to_string(&path).unwrap();
+    let line = content.lines().find(|line| line.contains("queued")).expect("record not written");
+    let record: serde_json::Value = serde_json::from_str(line).unwrap();
+    let unixnano = record["unixnano"].as_u64().unwrap();
+    assert!(before <= unixnano && unixnano <= after, "{} not in [{}, {}]", unixnano, before, after);
+    assert!(record["queue_latency_ns"].as_u64().unwrap() >= 50_000_000);
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
@@ -0,0 +1,63 @@
+use flashlog::{Compression, Logger, RollingPeriod, flush};
+use std::fs;
+use std::io::Read;
+
+#[test]
+fn test_rolled_files_are_compressed_in_background() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_compression");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    {
+        let _logger = Logger::initialize()
+            .with_file(temp_dir.to_str().unwrap(), "compressed")
+            .expect("Failed to set file")
+            .with_roll_period(RollingPeriod::None)
+            .expect("Failed to set roll period")
+            .with_max_file_size(2_000)
+            .expect("Failed to set max file size")
+            .with_max_roll_files(3)
+            .expect("Failed to set max roll files")
+            .with_compression(Compression::Gzip)
+            .expect("Failed to set compression")
+            .with_console_report(false)
+            .launch();
+
+        for i in 0..200 {
+            flashlog::flash_error_ct!("compression"; "message"; seq = i);
+            if i % 10 == 0 {
+                flush!();
+            }
+        }
+        flush!();
+        // let the logger thread roll the last files, then log the outcome of their compression
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        flashlog::compression::wait_for_compression();
+        flush!();
+    }
+
+    let mut lines = Vec::new();
+    let mut gz_files = 0;
+    for entry in fs::read_dir(&temp_dir).unwrap().filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+        assert!(!name.ends_with(".tmp"), "partial file left: {}", name);
+        let content = match path.extension().and_then(|e| e.to_str()) {
+            Some("gz") => {
+                assert!(name.ends_with(".log.gz"), "compressed file should keep the log extension: {}", name);
+                gz_files += 1;
+                let mut content = String::new();
+                flate2::read::GzDecoder::new(fs::File::open(&path).unwrap()).read_to_string(&mut content).unwrap();
+                content
+            }
+            _ => fs::read_to_string(&path).unwrap(),
+        };
+        lines.extend(content.lines().map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()));
+    }
+    assert!(gz_files > 0, "Should compress old files");
+    assert!(gz_files < 3, "Should keep fewer compressed files than max_roll_files");
+    assert!(lines.iter().any(|json| json["topic"] == "flashlog" && json["message"] == "rolled file compressed"));
+    assert!(!lines.iter().any(|json| json["message"] == "failed to compress rolled file"));
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
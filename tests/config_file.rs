@@ -0,0 +1,54 @@
+use flashlog::{flush, get_initial_log_file_path, Logger};
+use std::fs;
+use std::thread::sleep;
+use std::time::Duration;
+
+fn messages(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["message"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn test_config_file_with_reload() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_config_file");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+    let config_path = temp_dir.join("flashlog.toml");
+    let config = |level: &str| {
+        format!(
+            "level = \"{}\"\nconsole = false\nbuffer_size = 1\n\n[file]\npath = {:?}\nprefix = \"configured\"\nroll_period = \"none\"\n",
+            level,
+            temp_dir.to_str().unwrap(),
+        )
+    };
+    fs::write(&config_path, config("error")).unwrap();
+
+    let path = {
+        let _logger = Logger::from_config_file(&config_path)
+            .expect("Failed to load the config")
+            .with_config_watch(&config_path, Duration::from_millis(20))
+            .launch();
+        assert!(get_initial_log_file_path().unwrap().starts_with(&temp_dir));
+
+        flashlog::flash_error_ct!("config"; "before reload");
+        flush!();
+
+        fs::write(&config_path, config("off")).unwrap();
+        sleep(Duration::from_millis(200));
+        flashlog::flash_error_ct!("config"; "after reload");
+
+        fs::write(&config_path, config("loud")).unwrap();
+        sleep(Duration::from_millis(200));
+        flush!();
+        get_initial_log_file_path().unwrap()
+    };
+
+    let messages = messages(&fs::read_to_string(&path).unwrap());
+    assert_eq!(messages, vec!["before reload", "config reloaded", "failed to reload config"]);
+
+    assert!(Logger::from_config_file(temp_dir.join("missing.toml")).is_err());
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
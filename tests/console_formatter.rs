@@ -0,0 +1,40 @@
+use flashlog::{Logger, PrettyFormatter, RollingPeriod, flush};
+use std::fs;
+
+#[test]
+fn test_console_formatter_leaves_file_json() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_console_formatter");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    {
+        let _logger = Logger::initialize()
+            .with_file(temp_dir.to_str().unwrap(), "console")
+            .expect("Failed to set file")
+            .with_roll_period(RollingPeriod::None)
+            .expect("Failed to set roll period")
+            .with_console_report(true)
+            .with_console_formatter(PrettyFormatter::with_color(false))
+            .launch();
+
+        flashlog::flash_error_ct!("orders"; "px {} qty {}", 101.25, 7; venue = "KRX");
+        flashlog::flash_error_bin!("orders"; "px {} qty {}", 101.25, 7u32; venue = "KRX");
+        flush!();
+    }
+
+    let content: String = fs::read_dir(&temp_dir)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| fs::read_to_string(e.path()).unwrap())
+        .collect();
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(lines.len(), 2);
+    for line in lines {
+        let json: serde_json::Value = serde_json::from_str(line).expect("file line is not JSON");
+        assert_eq!(json["topic"], "orders");
+        assert_eq!(json["message"], "px 101.25 qty 7");
+        assert_eq!(json["data"]["venue"], "KRX");
+    }
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
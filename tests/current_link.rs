@@ -0,0 +1,44 @@
+use flashlog::{current_log_file_path, flush, get_initial_log_file_path, Logger, RollingPeriod};
+use std::fs;
+
+#[test]
+fn test_current_link_follows_rolls() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_current_link");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    {
+        let _logger = Logger::initialize()
+            .with_file(temp_dir.to_str().unwrap(), "followed")
+            .expect("Failed to set file")
+            .with_roll_period(RollingPeriod::None)
+            .expect("Failed to set roll period")
+            .with_max_file_size(1_000)
+            .expect("Failed to set max file size")
+            .with_current_link(true)
+            .expect("Failed to set current link")
+            .with_console_report(false)
+            .launch();
+
+        assert_eq!(current_log_file_path(), get_initial_log_file_path());
+
+        for i in 0..50 {
+            flashlog::flash_error_ct!("followed"; "message"; seq = i);
+            flush!();
+        }
+    }
+
+    let current = current_log_file_path().unwrap();
+    assert_ne!(Some(&current), get_initial_log_file_path().as_ref(), "Should track rolled files");
+
+    // creating symbolic links needs a privilege on Windows
+    if cfg!(unix) {
+        let link = temp_dir.join("followed.current.log");
+        assert_eq!(fs::read_link(&link).unwrap(), current.file_name().unwrap());
+        let content = fs::read_to_string(&link).unwrap();
+        let last: serde_json::Value = serde_json::from_str(content.lines().last().unwrap()).unwrap();
+        assert_eq!(last["data"]["seq"], 49);
+    }
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
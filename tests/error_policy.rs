@@ -0,0 +1,132 @@
+use flashlog::{flush, get_initial_log_file_path, ErrorPolicy, Logger, Sink, SinkConfig};
+use std::fs;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Fails every write, like a full disk
+struct BrokenSink;
+
+impl Sink for BrokenSink {
+    fn write_all(&mut self, _buf: &[u8]) -> io::Result<()> {
+        Err(io::Error::other("no space left on device"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Fails every other write
+#[derive(Clone, Default)]
+struct FlakySink {
+    lines: Arc<Mutex<Vec<u8>>>,
+    fail: Arc<Mutex<bool>>,
+}
+
+impl Sink for FlakySink {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        let mut fail = self.fail.lock().unwrap();
+        *fail = !*fail;
+        if *fail {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "try again"));
+        }
+        self.lines.lock().unwrap().extend_from_slice(buf);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Fails once after writing the first line of a batch, like a file that fails to roll in the middle of a batch
+#[derive(Clone, Default)]
+struct PartialSink {
+    lines: Arc<Mutex<Vec<u8>>>,
+    failed: Arc<Mutex<bool>>,
+}
+
+impl Sink for PartialSink {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.write_resumable(buf, &mut 0)
+    }
+
+    fn write_resumable(&mut self, buf: &[u8], written: &mut usize) -> io::Result<()> {
+        let rest = &buf[*written..];
+        let mut failed = self.failed.lock().unwrap();
+        let len = match *failed {
+            true => rest.len(),
+            false => rest.iter().position(|b| *b == b'\n').map_or(rest.len(), |end| end + 1),
+        };
+        self.lines.lock().unwrap().extend_from_slice(&rest[..len]);
+        *written += len;
+        if !*failed {
+            *failed = true;
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "failed to roll"));
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_io_errors_do_not_stop_the_logger() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_error_policy");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    let flaky = FlakySink::default();
+    let partial = PartialSink::default();
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    {
+        let reported = reported.clone();
+        let _logger = Logger::initialize()
+            .with_file(temp_dir.to_str().unwrap(), "errors")
+            .expect("Failed to set file")
+            .with_console_report(false)
+            .with_sink(SinkConfig::new(flaky.clone()))
+            .with_sink(SinkConfig::new(partial.clone()))
+            .with_sink(SinkConfig::new(BrokenSink))
+            .with_error_policy(ErrorPolicy::Retry { attempts: 2, backoff: Duration::from_millis(1) })
+            .with_error_callback(move |destination, error| {
+                reported.lock().unwrap().push(format!("{}: {}", destination, error));
+            })
+            .launch();
+
+        for i in 0..5 {
+            flashlog::flash_error_ct!("errors"; "message"; seq = i);
+        }
+        flush!();
+        assert!(Logger::health().running);
+    }
+
+    let health = Logger::health();
+    assert!(!health.running);
+    // the broken sink loses every line after three attempts, the flaky one succeeds on the second and the partial
+    // one fails once
+    assert_eq!(health.lost_lines, 5);
+    assert!(health.io_errors > 1 && (health.io_errors - 1).is_multiple_of(4));
+    assert_eq!(health.last_error.as_deref(), Some("sink: no space left on device"));
+
+    let reported = reported.lock().unwrap();
+    assert_eq!(reported.len() as u64, health.io_errors);
+    assert!(reported.iter().all(|error| error.starts_with("sink: ")));
+
+    // the other destinations are unaffected
+    let flaky_lines = String::from_utf8(flaky.lines.lock().unwrap().clone()).unwrap();
+    assert_eq!(flaky_lines.lines().count(), 5);
+    // the retry resumes after the line written before the failure
+    let partial_lines = String::from_utf8(partial.lines.lock().unwrap().clone()).unwrap();
+    assert_eq!(partial_lines.lines().count(), 5);
+    for i in 0..5 {
+        assert_eq!(partial_lines.matches(&format!("\"seq\":{}", i)).count(), 1);
+    }
+    let content = fs::read_to_string(get_initial_log_file_path().unwrap()).unwrap();
+    assert_eq!(content.lines().count(), 5);
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
@@ -0,0 +1,62 @@
+use flashlog::{Logger, RollingPeriod, flush};
+use std::fs;
+
+#[test]
+fn test_file_name_pattern_and_retention() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_file_name_pattern");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    // files sharing the prefix that the logger did not write
+    let unrelated = ["app.log", "app-notes.log", "app_backup_1.log", "app_20240101_1_000.gz"];
+    for name in unrelated.iter().take(3) {
+        fs::write(temp_dir.join(name), "keep me\n").unwrap();
+    }
+    fs::write(temp_dir.join(unrelated[3]), "not gzip, but it does not match the pid\n").unwrap();
+
+    {
+        let _logger = Logger::initialize()
+            .with_file(temp_dir.to_str().unwrap(), "app")
+            .expect("Failed to set file")
+            .with_roll_period(RollingPeriod::None)
+            .expect("Failed to set roll period")
+            .with_file_name_pattern("{prefix}_{date}_{pid}_{seq}")
+            .expect("Failed to set file name pattern")
+            .with_max_file_size(200)
+            .expect("Failed to set max file size")
+            .with_max_roll_files(2)
+            .expect("Failed to set max roll files")
+            .with_console_report(false)
+            .launch();
+
+        for i in 0..20 {
+            flashlog::flash_error_ct!("pattern"; "message"; seq = i);
+            flush!();
+        }
+    }
+
+    let names: Vec<String> = fs::read_dir(&temp_dir)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect();
+    for name in unrelated {
+        assert!(names.iter().any(|n| n == name), "{} should not be removed", name);
+    }
+
+    let suffix = format!("_{}_", std::process::id());
+    let logs: Vec<&String> = names.iter().filter(|n| n.contains(&suffix) && n.ends_with(".log")).collect();
+    assert_eq!(logs.len(), 2, "Should keep max_roll_files files: {:?}", names);
+    for name in logs {
+        let (date, seq) = name.strip_prefix("app_").unwrap().strip_suffix(".log").unwrap().split_once(&suffix).unwrap();
+        assert!(date.len() == 8 && date.chars().all(|c| c.is_ascii_digit()));
+        assert!(seq.len() == 3 && seq.chars().all(|c| c.is_ascii_digit()));
+    }
+
+    assert!(matches!(
+        Logger::initialize().with_file(temp_dir.to_str().unwrap(), "app").unwrap().with_file_name_pattern("{prefix}-{host}"),
+        Err(flashlog::logger::LoggerError::InvalidFileNamePattern(_))
+    ));
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
@@ -0,0 +1,66 @@
+use flashlog::{Formatter, Logger, Record, RollingPeriod, flush};
+use std::fs;
+
+/// `level|topic|message|key=value,...|src`
+struct PipeFormatter;
+
+impl Formatter for PipeFormatter {
+    fn format(&self, record: &Record<'_>, buf: &mut String) {
+        let data: Vec<String> = record
+            .data
+            .unwrap_or_default()
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        buf.push_str(&format!(
+            "{}|{}|{}|{}|{}\n",
+            record.level,
+            record.topic,
+            record.message,
+            data.join(","),
+            record.src(),
+        ));
+    }
+}
+
+#[test]
+fn test_custom_formatter_applies_to_all_macros() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_formatter");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    {
+        let _logger = Logger::initialize()
+            .with_file(temp_dir.to_str().unwrap(), "formatter")
+            .expect("Failed to set file")
+            .with_roll_period(RollingPeriod::None)
+            .expect("Failed to set roll period")
+            .with_console_report(false)
+            .with_formatter(PipeFormatter)
+            .launch();
+
+        flashlog::flash_error_ct!("orders"; "px {} qty {}", 101.25, 7; venue = "KRX");
+        flashlog::flash_error_ct!("orders"; "rejected");
+        flashlog::flash_error_ct!(code = 42);
+        flashlog::flash_error_bin!("orders"; "px {} qty {}", 101.25, 7u32; venue = "KRX");
+        flush!();
+    }
+
+    let content: String = fs::read_dir(&temp_dir)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| fs::read_to_string(e.path()).unwrap())
+        .collect();
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(lines.len(), 4);
+
+    let src = |line: &str| line.rsplit('|').next().unwrap().to_string();
+    assert_eq!(lines[0].rsplit_once('|').unwrap().0, "1|orders|px 101.25 qty 7|venue=\"KRX\"");
+    assert!(src(lines[0]).starts_with("tests/formatter.rs:"));
+    assert_eq!(lines[1].rsplit_once('|').unwrap().0, "1|orders|rejected|");
+    assert_eq!(lines[2].rsplit_once('|').unwrap().0, "1|||code=42");
+    // binary records go through the same formatter
+    assert_eq!(lines[3].rsplit_once('|').unwrap().0, lines[0].rsplit_once('|').unwrap().0);
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
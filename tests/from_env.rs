@@ -0,0 +1,39 @@
+use flashlog::compile_time::{MAX_LEVEL, WARN};
+use flashlog::{flush, get_initial_log_file_path, get_level, LogLevel, Logger};
+use std::fs;
+
+#[test]
+fn test_logger_from_env() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_from_env");
+    let _ = fs::remove_dir_all(&temp_dir);
+
+    std::env::set_var("FLASHLOG_DIR", &temp_dir);
+    std::env::set_var("FLASHLOG_PREFIX", "from_env");
+    std::env::set_var("FLASHLOG_ROLL", "none");
+    std::env::set_var("FLASHLOG_CONSOLE", "false");
+    std::env::set_var("FLASHLOG_LEVEL", "error");
+    std::env::set_var("FLASHLOG_CORE", "-1");
+
+    let path = {
+        let _logger = Logger::from_env().expect("Failed to read the environment").launch();
+        assert_eq!(get_level(), LogLevel::Error.min(LogLevel::from_usize(MAX_LEVEL).unwrap()));
+
+        flashlog::flash_error_ct!("env"; "error");
+        flashlog::flash_warn_ct!("env"; "warn below FLASHLOG_LEVEL");
+        flush!();
+        get_initial_log_file_path().unwrap()
+    };
+
+    assert_eq!(path.parent().unwrap(), temp_dir);
+    assert!(path.file_name().unwrap().to_str().unwrap().starts_with("from_env"));
+    let content = fs::read_to_string(&path).unwrap();
+    assert!(content.contains("\"error\""));
+    if MAX_LEVEL >= WARN {
+        assert!(!content.contains("warn below FLASHLOG_LEVEL"));
+    }
+
+    std::env::set_var("FLASHLOG_ROLL", "yearly");
+    assert!(Logger::from_env().is_err());
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
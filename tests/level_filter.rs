@@ -0,0 +1,62 @@
+use flashlog::compile_time::{MAX_LEVEL, WARN};
+use flashlog::{clear_filter, flush, get_initial_log_file_path, set_filter, set_level, LogLevel, Logger};
+use std::fs;
+
+mod md {
+    pub fn quote() {
+        flashlog::flash_error_ct!("quotes"; "md error");
+        flashlog::flash_warn_ct!("quotes"; "md warn");
+    }
+}
+
+fn log_all() {
+    flashlog::flash_error_ct!("orders"; "orders error");
+    flashlog::flash_warn_ct!("orders"; "orders warn");
+    flashlog::flash_error_ct!("fills"; "fills error");
+    flashlog::flash_error_bin!("orders"; "orders bin error");
+    md::quote();
+}
+
+fn messages(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["message"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn test_level_filter_directives() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_level_filter");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    let path = {
+        let _logger = Logger::initialize()
+            .with_file(temp_dir.to_str().unwrap(), "level_filter")
+            .expect("Failed to set file")
+            .with_level_filter("orders=warn,level_filter::md=off,*=off")
+            .expect("Failed to set level filter")
+            .with_console_report(false)
+            .launch();
+        assert!(set_filter("orders=loud").is_err());
+
+        // the cached levels of the callsites follow the filter
+        log_all();
+        set_filter("level_filter::md=error,*=error").unwrap();
+        log_all();
+        clear_filter();
+        set_level(LogLevel::NIL);
+        log_all();
+        flush!();
+        get_initial_log_file_path().unwrap()
+    };
+
+    let mut expected = vec!["orders error"];
+    if MAX_LEVEL >= WARN {
+        expected.push("orders warn");
+    }
+    expected.extend(["orders bin error", "orders error", "fills error", "orders bin error", "md error"]);
+    assert_eq!(messages(&fs::read_to_string(&path).unwrap()), expected);
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
@@ -0,0 +1,73 @@
+use flashlog::binary_file::BinaryFileReader;
+use flashlog::{Logger, RollingPeriod, flush};
+use std::fs;
+use std::path::PathBuf;
+
+fn files_with_extension(dir: &std::path::Path, extension: &str) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == extension))
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn test_files_roll_by_size() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_max_file_size");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+    let max_file_size = 1_000;
+
+    {
+        let _logger = Logger::initialize()
+            .with_file(temp_dir.to_str().unwrap(), "sized")
+            .expect("Failed to set file")
+            .with_binary_file(temp_dir.to_str().unwrap(), "sized")
+            .expect("Failed to set binary file")
+            .with_roll_period(RollingPeriod::None)
+            .expect("Failed to set roll period")
+            .with_max_file_size(max_file_size)
+            .expect("Failed to set max file size")
+            .with_max_roll_files(1_000)
+            .expect("Failed to set max roll files")
+            .with_console_report(false)
+            .launch();
+
+        for i in 0..100 {
+            flashlog::flash_error_ct!("sized"; "message"; seq = i);
+            flashlog::flash_error_bin!("sized"; "binary {}", i as u32);
+        }
+        flush!();
+    }
+
+    let log_files = files_with_extension(&temp_dir, "log");
+    assert!(log_files.len() > 1, "Should roll by size");
+    let mut seqs = Vec::new();
+    for path in &log_files {
+        assert!(fs::metadata(path).unwrap().len() <= max_file_size);
+        for line in fs::read_to_string(path).unwrap().lines() {
+            let json: serde_json::Value = serde_json::from_str(line).unwrap();
+            seqs.push(json["data"]["seq"].as_u64().unwrap());
+        }
+    }
+    // file names sort in the order they were written
+    assert_eq!(seqs, (0..100).collect::<Vec<u64>>());
+
+    // every binary file starts with its own header and callsite dictionary
+    let binary_files = files_with_extension(&temp_dir, "flog");
+    assert!(binary_files.len() > 1, "Should roll binary files by size");
+    let mut messages = Vec::new();
+    for path in &binary_files {
+        assert!(fs::metadata(path).unwrap().len() <= max_file_size);
+        for line in BinaryFileReader::new(fs::File::open(path).unwrap()) {
+            let json: serde_json::Value = serde_json::from_str(&line.unwrap()).unwrap();
+            messages.push(json["message"].as_str().unwrap().to_string());
+        }
+    }
+    assert_eq!(messages, (0..100).map(|i| format!("binary {}", i)).collect::<Vec<_>>());
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
@@ -0,0 +1,52 @@
+use flashlog::{Logger, LogTransport, OverflowPolicy, RollingPeriod, flush};
+use std::fs;
+
+#[test]
+fn test_drop_newest_reports_dropped_messages() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_overflow");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    let total = 20_000;
+
+    {
+        let _logger = Logger::initialize()
+            .with_transport(LogTransport::RingBuffer(4))
+            .with_overflow_policy(OverflowPolicy::DropNewest)
+            .with_file(temp_dir.to_str().unwrap(), "overflow")
+            .expect("Failed to set file")
+            .with_roll_period(RollingPeriod::None)
+            .expect("Failed to set roll period")
+            .with_console_report(false)
+            .launch();
+
+        for i in 0..total {
+            flashlog::flash_error_ct!("burst"; "message"; seq = i);
+        }
+        flush!();
+    }
+
+    let content: String = fs::read_dir(&temp_dir)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| fs::read_to_string(e.path()).unwrap())
+        .collect();
+
+    let mut written = 0;
+    let mut dropped = 0;
+    for line in content.lines() {
+        let json: serde_json::Value = serde_json::from_str(line).unwrap();
+        if json["topic"] == "burst" {
+            written += 1;
+        } else if json["topic"] == "flashlog" {
+            let message = json["message"].as_str().unwrap();
+            assert!(message.ends_with(" messages dropped"));
+            dropped += json["data"]["Error"].as_u64().unwrap();
+        }
+    }
+
+    // every message is either written or accounted for in a summary record
+    assert_eq!(written + dropped, total);
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
@@ -0,0 +1,48 @@
+use flashlog::{Logger, LogLevel, LogTransport, OverflowPolicy, RollingPeriod, flush};
+use std::fs;
+
+#[test]
+fn test_drop_below_level_waits_for_severe_messages() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_overflow_drop_below_level");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    let total = 20_000;
+
+    {
+        let _logger = Logger::initialize()
+            .with_transport(LogTransport::RingBuffer(4))
+            .with_overflow_policy(OverflowPolicy::DropBelowLevel(LogLevel::Error))
+            .with_file(temp_dir.to_str().unwrap(), "overflow")
+            .expect("Failed to set file")
+            .with_roll_period(RollingPeriod::None)
+            .expect("Failed to set roll period")
+            .with_console_report(false)
+            .launch();
+
+        // errors are not below the threshold, so a full buffer blocks instead of dropping them
+        for i in 0..total {
+            flashlog::flash_error_ct!("burst"; "message"; seq = i);
+        }
+        flush!();
+    }
+
+    let content: String = fs::read_dir(&temp_dir)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| fs::read_to_string(e.path()).unwrap())
+        .collect();
+
+    let mut seqs = Vec::new();
+    for line in content.lines() {
+        let json: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert_ne!(json["topic"], "flashlog", "unexpected drop report: {}", line);
+        if json["topic"] == "burst" {
+            seqs.push(json["data"]["seq"].as_u64().unwrap());
+        }
+    }
+
+    assert_eq!(seqs, (0..total).collect::<Vec<_>>());
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
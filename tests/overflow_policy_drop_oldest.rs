@@ -0,0 +1,59 @@
+use flashlog::{Logger, LogTransport, OverflowPolicy, RollingPeriod, flush};
+use std::fs;
+
+#[test]
+fn test_drop_oldest_keeps_newest_messages_in_order() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_overflow_drop_oldest");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    let total = 20_000;
+
+    {
+        let _logger = Logger::initialize()
+            .with_transport(LogTransport::RingBuffer(4))
+            .with_overflow_policy(OverflowPolicy::DropOldest)
+            .with_file(temp_dir.to_str().unwrap(), "overflow")
+            .expect("Failed to set file")
+            .with_roll_period(RollingPeriod::None)
+            .expect("Failed to set roll period")
+            .with_console_report(false)
+            .launch();
+
+        for i in 0..total {
+            flashlog::flash_error_ct!("burst"; "message"; seq = i);
+            if i < total / 2 && i % 1_000 == 0 {
+                // control messages queued mid-burst are never dropped or reordered;
+                // while one is at the head of the queue, the new messages are dropped instead
+                flush!();
+            }
+        }
+        flush!();
+    }
+
+    let content: String = fs::read_dir(&temp_dir)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| fs::read_to_string(e.path()).unwrap())
+        .collect();
+
+    let mut seqs = Vec::new();
+    let mut dropped = 0;
+    for line in content.lines() {
+        let json: serde_json::Value = serde_json::from_str(line).unwrap();
+        if json["topic"] == "burst" {
+            seqs.push(json["data"]["seq"].as_u64().unwrap());
+        } else if json["topic"] == "flashlog" {
+            let message = json["message"].as_str().unwrap();
+            assert!(message.ends_with(" messages dropped"));
+            dropped += json["data"]["Error"].as_u64().unwrap();
+        }
+    }
+
+    assert_eq!(seqs.len() as u64 + dropped, total);
+    assert!(seqs.windows(2).all(|pair| pair[0] < pair[1]), "messages written out of order");
+    // with only log messages queued, the newest one always makes it into the queue
+    assert_eq!(seqs.last(), Some(&(total - 1)));
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
@@ -0,0 +1,45 @@
+use flashlog::{flush, get_initial_log_file_path, Logger};
+use std::fs;
+
+fn messages(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["message"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn test_reopen_after_external_rotation() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_reopen");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    let path = {
+        let _logger = Logger::initialize()
+            .with_file(temp_dir.to_str().unwrap(), "reopened")
+            .expect("Failed to set file")
+            .with_console_report(false)
+            .launch();
+        let path = get_initial_log_file_path().unwrap();
+
+        flashlog::flash_error_ct!("reopen"; "before rotation");
+        flush!();
+        // let the logger thread open the file before it is moved, as logrotate would find it
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        fs::rename(&path, path.with_extension("log.1")).unwrap();
+
+        // written to the moved file, which the logger still holds
+        flashlog::flash_error_ct!("reopen"; "before reopen");
+        Logger::reopen();
+        flashlog::flash_error_ct!("reopen"; "after reopen");
+        flush!();
+        path
+    };
+
+    let rotated = fs::read_to_string(path.with_extension("log.1")).unwrap();
+    assert_eq!(messages(&rotated), vec!["before rotation", "before reopen"]);
+    let reopened = fs::read_to_string(&path).unwrap();
+    assert_eq!(messages(&reopened), vec!["after reopen"]);
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
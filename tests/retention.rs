@@ -0,0 +1,65 @@
+use flashlog::{flush, get_initial_log_file_path, Logger, RollingPeriod};
+use std::fs;
+use std::time::{Duration, SystemTime};
+
+#[test]
+fn test_retention_by_age_and_total_size() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_retention");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    // files left by previous runs
+    let day = Duration::from_secs(24 * 3600);
+    let now = SystemTime::now();
+    let create = |name: &str, len: usize, age: Duration| {
+        let path = temp_dir.join(name);
+        fs::write(&path, vec![b'x'; len]).unwrap();
+        fs::File::options().write(true).open(&path).unwrap().set_modified(now - age).unwrap();
+        path
+    };
+    let expired = create("retained-20200101.log", 10, day * 30);
+    let oldest = create("retained-20200102.log.gz", 1_000, day * 3);
+    let newest = create("retained-20200103.log", 1_000, day);
+    let unrelated = create("backup-20200101.log", 10, day * 30);
+
+    {
+        let _logger = Logger::initialize()
+            .with_file(temp_dir.to_str().unwrap(), "retained")
+            .expect("Failed to set file")
+            .with_roll_period(RollingPeriod::Daily)
+            .expect("Failed to set roll period")
+            .with_max_age(day * 14)
+            .expect("Failed to set max age")
+            .with_max_total_size(1_500)
+            .expect("Failed to set max total size")
+            .with_console_report(false)
+            .launch();
+
+        flashlog::flash_error_ct!("retention"; "started");
+        flush!();
+    }
+
+    assert!(!expired.exists(), "Should remove files older than max_age");
+    assert!(!oldest.exists(), "Should remove the oldest files beyond max_total_size");
+    assert!(newest.exists());
+    assert!(unrelated.exists(), "Should leave files not matching the pattern");
+
+    let content = fs::read_to_string(get_initial_log_file_path().unwrap()).unwrap();
+    let mut removed = Vec::new();
+    for line in content.lines() {
+        let json: serde_json::Value = serde_json::from_str(line).unwrap();
+        if json["topic"] == "flashlog" && json["message"] == "rolled file removed" {
+            let file = json["data"]["file"].as_str().unwrap().to_string();
+            removed.push((file, json["data"]["reason"].as_str().unwrap().to_string()));
+        }
+    }
+    assert_eq!(
+        removed,
+        vec![
+            (expired.display().to_string(), "max_age".to_string()),
+            (oldest.display().to_string(), "max_total_size".to_string()),
+        ]
+    );
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
@@ -0,0 +1,50 @@
+use flashlog::{Logger, LogTransport, RollingPeriod, flush};
+use std::fs;
+
+#[test]
+fn test_ring_buffer_transport_delivers_all_messages() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_ring_buffer");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    let threads = 4;
+    let per_thread = 5_000;
+
+    {
+        let _logger = Logger::initialize()
+            .with_transport(LogTransport::RingBuffer(64)) // small buffer so senders have to wait
+            .with_file(temp_dir.to_str().unwrap(), "ring")
+            .expect("Failed to set file")
+            .with_roll_period(RollingPeriod::None)
+            .expect("Failed to set roll period")
+            .with_console_report(false)
+            .with_msg_buffer_size(100)
+            .launch();
+
+        let handles: Vec<_> = (0..threads)
+            .map(|t| {
+                std::thread::spawn(move || {
+                    for i in 0..per_thread {
+                        flashlog::flash_error_ct!("ring"; "message"; thread = t, seq = i);
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        flush!();
+    }
+
+    let content: String = fs::read_dir(&temp_dir)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| fs::read_to_string(e.path()).unwrap())
+        .collect();
+
+    let lines = content.lines().filter(|line| line.contains("\"topic\":\"ring\"")).count();
+    assert_eq!(lines, threads * per_thread);
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
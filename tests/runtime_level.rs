@@ -0,0 +1,50 @@
+use flashlog::compile_time::{MAX_LEVEL, WARN};
+use flashlog::{flush, get_initial_log_file_path, get_level, set_level, LogLevel, Logger};
+use std::fs;
+
+fn messages(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["message"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn test_set_level_at_runtime() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_runtime_level");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    let path = {
+        let _logger = Logger::initialize()
+            .with_file(temp_dir.to_str().unwrap(), "runtime_level")
+            .expect("Failed to set file")
+            .with_console_report(false)
+            .launch();
+
+        flashlog::flash_error_ct!("level"; "error before");
+
+        set_level(LogLevel::NIL);
+        assert_eq!(get_level(), LogLevel::NIL);
+        flashlog::flash_error_ct!("level"; "error while off");
+
+        set_level(LogLevel::Error);
+        flashlog::flash_error_ct!("level"; "error after");
+        flashlog::flash_warn_ct!("level"; "warn below the runtime level");
+
+        // the compile-time ceiling still applies
+        set_level(LogLevel::Trace);
+        assert_eq!(get_level(), LogLevel::from_usize(MAX_LEVEL).unwrap());
+        flashlog::flash_warn_ct!("level"; "warn with the trace level");
+        flush!();
+        get_initial_log_file_path().unwrap()
+    };
+
+    let mut expected = vec!["error before", "error after"];
+    if MAX_LEVEL >= WARN {
+        expected.push("warn with the trace level");
+    }
+    assert_eq!(messages(&fs::read_to_string(&path).unwrap()), expected);
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
@@ -0,0 +1,105 @@
+use flashlog::{
+    FileSink, FlushPolicy, LazyMessage, LogLevel, LogMessage, LogRecord, Logger, RollingPeriod, Sink, SinkConfig,
+    TextFormatter, LOG_SENDER, flush,
+};
+use std::fs;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Default)]
+struct MemorySink {
+    lines: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Sink for MemorySink {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.lines.lock().unwrap().extend_from_slice(buf);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn send_record(level: LogLevel, message: &'static str) {
+    LOG_SENDER.send_log(LogMessage::LazyMessage(LazyMessage::record(level.as_usize(), move || LogRecord {
+        level: level.as_usize(),
+        topic: "sinks".to_string(),
+        message: message.to_string(),
+        data: None,
+        file: file!(),
+        line: line!(),
+        module_path: module_path!(),
+        unixnano: flashlog::get_unix_nano(),
+        queue_latency: None,
+    })));
+}
+
+fn read_dir(path: &std::path::Path) -> String {
+    fs::read_dir(path)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| fs::read_to_string(e.path()).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_sinks_filter_by_level() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_sinks");
+    let alerts_dir = temp_dir.join("alerts");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    let errors = MemorySink::default();
+    {
+        let _logger = Logger::initialize()
+            .with_file(temp_dir.to_str().unwrap(), "all")
+            .expect("Failed to set file")
+            .with_roll_period(RollingPeriod::None)
+            .expect("Failed to set roll period")
+            .with_console_report(false)
+            .with_sink(
+                SinkConfig::new(errors.clone())
+                    .with_level(LogLevel::Error)
+                    .with_formatter(TextFormatter::new())
+                    .with_flush_policy(FlushPolicy::Immediate),
+            )
+            .with_sink(
+                SinkConfig::new(FileSink::new(alerts_dir.to_str().unwrap(), "alerts").expect("Failed to open alerts"))
+                    .with_level(LogLevel::Warn),
+            )
+            .launch();
+
+        send_record(LogLevel::Info, "info");
+        send_record(LogLevel::Warn, "warn");
+        flashlog::flash_error_ct!("sinks"; "error");
+        flush!();
+    }
+
+    let all: Vec<serde_json::Value> = fs::read_dir(&temp_dir)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file())
+        .flat_map(|e| {
+            fs::read_to_string(e.path())
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    let messages: Vec<&str> = all.iter().map(|json| json["message"].as_str().unwrap()).collect();
+    assert_eq!(messages, ["info", "warn", "error"]);
+
+    let alerts = read_dir(&alerts_dir);
+    let alerts: Vec<serde_json::Value> = alerts.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    let messages: Vec<&str> = alerts.iter().map(|json| json["message"].as_str().unwrap()).collect();
+    assert_eq!(messages, ["warn", "error"]);
+
+    let errors = String::from_utf8(errors.lines.lock().unwrap().clone()).unwrap();
+    assert_eq!(errors.lines().count(), 1);
+    assert!(errors.contains("ERROR [sinks] error"));
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
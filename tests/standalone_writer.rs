@@ -0,0 +1,35 @@
+use flashlog::{Logger, RollingConfig, RollingFileWriter, RollingPeriod};
+use std::fs;
+
+#[test]
+fn test_standalone_writer_does_not_start_the_logger() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_standalone_writer");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    let mut config = RollingConfig::default();
+    config.base_path = temp_dir.clone();
+    config.file_name_prefix = "standalone".to_string();
+    config.roll_period = Some(RollingPeriod::None);
+    config.max_file_size = Some(100);
+    config.max_roll_files = Some(2);
+    config.compress = true;
+
+    let mut writer = RollingFileWriter::new(config).unwrap();
+    for i in 0..20 {
+        writer.write_all(format!("{{\"seq\":{}}}\n", i).repeat(5).as_bytes()).unwrap();
+    }
+    writer.flush().unwrap();
+    flashlog::compression::wait_for_compression();
+
+    let compressed = fs::read_dir(&temp_dir)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_str().unwrap().ends_with(".log.gz"))
+        .count();
+    assert!(compressed > 0, "Should compress rolled files");
+    // rolling, compressing and removing files are reported only to a launched logger
+    assert!(!Logger::health().running);
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
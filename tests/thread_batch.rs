@@ -0,0 +1,72 @@
+use flashlog::{Logger, RollingPeriod, flush};
+use std::fs;
+
+#[test]
+fn test_thread_batches_are_published_on_flush_and_thread_exit() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_thread_batch");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+
+    let threads = 3;
+    let per_thread = 1_005; // not a multiple of the batch size
+
+    {
+        let _logger = Logger::initialize()
+            .with_thread_batch_size(64)
+            .with_file(temp_dir.to_str().unwrap(), "batch")
+            .expect("Failed to set file")
+            .with_roll_period(RollingPeriod::None)
+            .expect("Failed to set roll period")
+            .with_console_report(false)
+            .launch();
+
+        let handles: Vec<_> = (0..threads)
+            .map(|t| {
+                std::thread::spawn(move || {
+                    for i in 0..per_thread {
+                        flashlog::flash_error_ct!("worker"; "message"; thread = t, seq = i);
+                    }
+                    // the partial batch is published when the thread exits
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // fewer messages than the batch size: only flush! publishes them
+        for i in 0..10 {
+            flashlog::flash_error_ct!("main"; "message"; seq = i);
+        }
+        flush!();
+    }
+
+    let content: String = fs::read_dir(&temp_dir)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| fs::read_to_string(e.path()).unwrap())
+        .collect();
+
+    let worker_lines: Vec<serde_json::Value> = content
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .filter(|json: &serde_json::Value| json["topic"] == "worker")
+        .collect();
+    assert_eq!(worker_lines.len(), threads * per_thread);
+
+    // each thread's messages keep their order
+    for t in 0..threads {
+        let seqs: Vec<u64> = worker_lines
+            .iter()
+            .filter(|json| json["data"]["thread"] == t)
+            .map(|json| json["data"]["seq"].as_u64().unwrap())
+            .collect();
+        assert_eq!(seqs, (0..per_thread as u64).collect::<Vec<_>>());
+    }
+
+    let main_lines = content.lines().filter(|line| line.contains("\"topic\":\"main\"")).count();
+    assert_eq!(main_lines, 10);
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
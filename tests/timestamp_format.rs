@@ -0,0 +1,38 @@
+use flashlog::{flush, get_initial_log_file_path, Logger, RollingPeriod, TimeZone, TimestampFormat};
+use std::fs;
+
+#[test]
+fn test_rfc3339_timestamp() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_timestamp_format");
+    let _ = fs::remove_dir_all(&temp_dir);
+
+    let path = {
+        let _logger = Logger::initialize()
+            .with_file(temp_dir.to_str().unwrap(), "timestamp")
+            .expect("Failed to set file")
+            .with_roll_period(RollingPeriod::None)
+            .expect("Failed to set roll period")
+            .with_console_report(false)
+            .with_timezone(TimeZone::Fixed(9 * 3600))
+            .with_timestamp_format(TimestampFormat::Rfc3339 { digits: 6 })
+            .include_unixnano(true)
+            .launch();
+
+        flashlog::flash_error_ct!("ts"; "rfc3339");
+        flush!();
+        get_initial_log_file_path().unwrap()
+    };
+
+    let content = fs::read_to_string(&path).unwrap();
+    let line = content.lines().find(|line| line.contains("rfc3339")).expect("record not written");
+    let record: serde_json::Value = serde_json::from_str(line).unwrap();
+    assert!(record.get("date").is_none() && record.get("time").is_none() && record.get("offset").is_none());
+
+    let timestamp = record["timestamp"].as_str().unwrap();
+    assert!(timestamp.ends_with("+09:00"));
+    let parsed = chrono::DateTime::parse_from_rfc3339(timestamp).unwrap();
+    let unixnano = record["unixnano"].as_u64().unwrap();
+    assert_eq!(parsed.timestamp_nanos_opt(), Some((unixnano / 1_000 * 1_000) as i64));
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
@@ -0,0 +1,35 @@
+use flashlog::{flush, get_initial_log_file_path, Logger, RollingPeriod, TimeZone};
+use std::fs;
+
+#[test]
+fn test_named_timezone_with_minute_offset() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_timezone");
+    let _ = fs::remove_dir_all(&temp_dir);
+
+    let path = {
+        let _logger = Logger::initialize()
+            .with_file(temp_dir.to_str().unwrap(), "timezone")
+            .expect("Failed to set file")
+            .with_roll_period(RollingPeriod::None)
+            .expect("Failed to set roll period")
+            .with_console_report(false)
+            .with_timezone(TimeZone::Named("Asia/Kolkata".to_string()))
+            .with_timezone(TimeZone::Named("Mars/Olympus_Mons".to_string()))
+            .launch();
+
+        flashlog::flash_error_ct!("tz"; "kolkata");
+        flush!();
+        get_initial_log_file_path().unwrap()
+    };
+
+    let content = fs::read_to_string(&path).unwrap();
+    let line = content.lines().find(|line| line.contains("kolkata")).expect("record not written");
+    let record: serde_json::Value = serde_json::from_str(line).unwrap();
+    assert_eq!(record["offset"], serde_json::json!(5.5));
+
+    // the unknown name is reported and Asia/Kolkata is kept
+    let report = content.lines().find(|line| line.contains("unknown timezone")).expect("unknown name not reported");
+    assert!(report.contains("Mars/Olympus_Mons"));
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}
@@ -0,0 +1,60 @@
+use flashlog::{Logger, RollingConfig, RollingPeriod, flush};
+use std::collections::HashMap;
+use std::fs;
+
+#[test]
+fn test_topics_are_routed_to_their_files() {
+    let temp_dir = std::env::temp_dir().join("flashlog_test_topic_routes");
+    let _ = fs::remove_dir_all(&temp_dir);
+    let _ = fs::create_dir_all(&temp_dir);
+    let dir = temp_dir.to_str().unwrap();
+
+    let mut md_config = RollingConfig::default();
+    md_config.base_path = temp_dir.join("md");
+    md_config.file_name_prefix = "md".to_string();
+    md_config.roll_period = Some(RollingPeriod::None);
+
+    {
+        let _logger = Logger::initialize()
+            .with_file(dir, "default")
+            .expect("Failed to set file")
+            .with_roll_period(RollingPeriod::None)
+            .expect("Failed to set roll period")
+            .with_topic_file("orders", dir, "orders")
+            .expect("Failed to set orders file")
+            .with_topic_route("md", md_config)
+            .expect("Failed to set md file")
+            .with_console_report(false)
+            .launch();
+
+        flashlog::flash_error_ct!("orders"; "new"; id = 1);
+        flashlog::flash_error_ct!("md"; "tick"; px = 101.25);
+        flashlog::flash_error_ct!("risk"; "limit");
+        flashlog::flash_error_ct!("orders"; "filled"; id = 1);
+        flashlog::flash_error_bin!("orders"; "bin");
+        flush!();
+    }
+
+    let mut topics_by_prefix: HashMap<String, Vec<String>> = HashMap::new();
+    let paths = fs::read_dir(&temp_dir)
+        .unwrap()
+        .chain(fs::read_dir(temp_dir.join("md")).unwrap())
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| path.is_file());
+    for path in paths {
+        let file_name = path.file_name().unwrap().to_str().unwrap().to_string();
+        let prefix = file_name.split('-').next().unwrap().to_string();
+        let messages = topics_by_prefix.entry(prefix).or_default();
+        for line in fs::read_to_string(&path).unwrap().lines() {
+            let json: serde_json::Value = serde_json::from_str(line).unwrap();
+            messages.push(format!("{}:{}", json["topic"].as_str().unwrap(), json["message"].as_str().unwrap()));
+        }
+    }
+
+    assert_eq!(topics_by_prefix["orders"], ["orders:new", "orders:filled", "orders:bin"]);
+    assert_eq!(topics_by_prefix["md"], ["md:tick"]);
+    assert_eq!(topics_by_prefix["default"], ["risk:limit"]);
+
+    let _ = fs::remove_dir_all(&temp_dir);
+}